@@ -1,14 +1,145 @@
-struct GameBoy {
-    model: Model,
-    speed: SpeedMode,
+//! GameBoy scheduler.
+//!
+//! The CPU drives the whole system: every step reports the T-cycles it consumed, and the
+//! components on the memory bus (cartridge clock, timer, PPU, APU, serial port) are advanced by
+//! exactly that amount, see [crate::hardware::memory::Memory::tick].
+//!
+//! A frame lasts 154 scanlines of 456 T-cycles, so that at 4.194304 MHz the screen refreshes at
+//! about 59.7 Hz. In CGB double speed mode, the CPU and the timer run twice as many T-cycles per
+//! frame while the PPU keeps its clock.
+//!
+
+use crate::hardware::cpu::Cpu;
+
+use std::time::Duration;
+
+/// Number of T-cycles in one frame.
+pub const CYCLES_PER_FRAME: u32 = 70224;
+
+/// Number of T-cycles in one second.
+pub const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+/// Real time duration of one frame.
+pub const FRAME_DURATION: Duration =
+    Duration::from_nanos(CYCLES_PER_FRAME as u64 * 1_000_000_000 / CYCLES_PER_SECOND as u64);
+
+pub struct GameBoy {
+    /// The CPU, owning the memory bus and every component plugged in.
+    pub cpu: Cpu,
+
+    /// T-cycles run past the end of the previous frame.
+    overshoot: u32,
 }
 
-enum SpeedMode {
+#[allow(clippy::upper_case_acronyms)]
+/// CPU speed modes, only the CGB can switch to double speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedMode {
     DOUBLE,
     NORMAL,
 }
 
-enum Model {
+#[allow(clippy::upper_case_acronyms)]
+/// Hardware models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
     DMG,
     CGB,
 }
+
+impl GameBoy {
+    /// Constructs a GameBoy running at normal speed around *cpu*, emulating the model picked by
+    /// its memory bus.
+    pub fn new(cpu: Cpu) -> Self {
+        Self { cpu, overshoot: 0 }
+    }
+
+    /// Executes one CPU step, then advances the components on the memory bus by the T-cycles it
+    /// took, and by the T-cycles the CPU was stalled by the Video RAM DMA.
+    /// Returns the number of T-cycles elapsed.
+    pub fn step(&mut self) -> u32 {
+        let mut cycles = self.cpu.step();
+        if self.cpu.stopped() {
+            return cycles;
+        }
+
+        let mut elapsed = cycles;
+        while elapsed > 0 {
+            let memory = self.cpu.memory_mut();
+            memory.tick(elapsed);
+            elapsed = memory.take_stall();
+            cycles += elapsed;
+        }
+        cycles
+    }
+
+    /// Runs the system for one frame worth of T-cycles.
+    /// Instructions are never split, so the cycles run past the end of the frame are taken off
+    /// the next one.
+    pub fn run_frame(&mut self) {
+        let mut cycles = self.overshoot;
+        while cycles < self.cycles_per_frame() {
+            cycles += self.step();
+        }
+        self.overshoot = cycles - self.cycles_per_frame();
+    }
+
+    /// Returns the number of CPU T-cycles in one frame, doubled in double speed mode.
+    fn cycles_per_frame(&self) -> u32 {
+        match self.cpu.memory().speed() {
+            SpeedMode::NORMAL => CYCLES_PER_FRAME,
+            SpeedMode::DOUBLE => CYCLES_PER_FRAME * 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::cartridge::header::build_rom;
+    use crate::hardware::cartridge::Cartridge;
+    use crate::hardware::memory::Memory;
+    use crate::hardware::ppu::Renderer;
+
+    /// Constructs a GameBoy running a ROM-only cartridge whose first bytes are *data*.
+    fn gameboy(data: Vec<u8>) -> GameBoy {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[..data.len()].copy_from_slice(&data);
+        GameBoy::new(Cpu::new(Memory::new(
+            Cartridge::new(rom).unwrap(),
+            Renderer::Scanline,
+        )))
+    }
+
+    #[test]
+    fn test_step() {
+        // LD BC, imm16; JR NZ, 0; JR Z, 0
+        let mut gameboy = gameboy(vec![0x01, 0x00, 0x00, 0x20, 0x00, 0x28, 0x00]);
+
+        assert_eq!(gameboy.step(), 12);
+        assert_eq!(gameboy.step(), 12);
+        assert_eq!(gameboy.step(), 8);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        // LD (0xC000), SP; JP 0x0000, 36 T-cycles per loop
+        let mut gameboy = gameboy(vec![0x08, 0x00, 0xC0, 0xC3, 0x00, 0x00]);
+
+        gameboy.run_frame();
+        assert_eq!(gameboy.overshoot, 12);
+        gameboy.run_frame();
+        assert_eq!(gameboy.overshoot, 8);
+    }
+
+    #[test]
+    fn test_serial_output() {
+        // LD A, 'O'; LDH (SB), A; LD A, 0x81; LDH (SC), A; JP 0x0008
+        let mut gameboy = gameboy(vec![
+            0x3E, b'O', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0xC3, 0x08, 0x00,
+        ]);
+
+        gameboy.run_frame();
+        assert_eq!(gameboy.cpu.memory_mut().serial.take_output(), b"O");
+    }
+}
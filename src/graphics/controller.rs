@@ -2,10 +2,18 @@
 use native_dialog::{FileDialog, MessageDialog, MessageType};
 
 use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use super::super::gameboy::GameBoy;
+use super::super::hardware::apu::CHANNELS;
+use super::super::hardware::cartridge::Cartridge;
 use super::super::hardware::cpu::Cpu;
+use super::super::hardware::joypad::Button;
 use super::super::hardware::memory::Memory;
 
+use super::printout;
+use super::recorder::Recorder;
 use super::Graphics;
 
 /// Toggle overlay on the LCD Window
@@ -17,9 +25,146 @@ pub fn toggle_overlay(graphics: &mut Graphics) {
     }
 }
 
+/// Execute a single instruction
 pub fn step(graphics: &mut Graphics) {
-    if let Some(cpu) = &mut graphics.cpu {
-        cpu.step();
+    if let Some(gameboy) = &mut graphics.gameboy {
+        gameboy.step();
+    }
+}
+
+/// Press a GameBoy button
+pub fn press(graphics: &mut Graphics, button: Button) {
+    if let Some(gameboy) = &mut graphics.gameboy {
+        let memory = gameboy.cpu.memory_mut();
+        memory.joypad.press(button, &mut memory.interrupts);
+    }
+}
+
+/// Release a GameBoy button
+pub fn release(graphics: &mut Graphics, button: Button) {
+    if let Some(gameboy) = &mut graphics.gameboy {
+        gameboy.cpu.memory_mut().joypad.release(button);
+    }
+}
+
+/// Run the emulation at full speed
+pub fn play(graphics: &mut Graphics) {
+    graphics.playing = true;
+}
+
+/// Pause the emulation
+pub fn pause(graphics: &mut Graphics) {
+    graphics.playing = false;
+}
+
+/// Start recording the mixed audio output to a WAV file, or stop the recording in progress
+pub fn record_mixed(graphics: &mut Graphics) {
+    toggle_recording(graphics, Recorder::mixed);
+}
+
+/// Start recording each audio channel to its own WAV file, or stop the recording in progress
+pub fn record_channels(graphics: &mut Graphics) {
+    toggle_recording(graphics, Recorder::channels);
+}
+
+/// Open a FileDialog then start a recording with *start*, or stop the recording in progress
+fn toggle_recording(graphics: &mut Graphics, start: fn(&Path, u32) -> Result<Recorder, String>) {
+    if graphics.recorder.is_some() {
+        stop_recording(graphics);
+        return;
+    }
+    let sample_rate = graphics.sample_rate();
+    let gameboy = match &mut graphics.gameboy {
+        Some(gameboy) => gameboy,
+        None => return,
+    };
+    let path = match FileDialog::new()
+        .add_filter("wav", &["wav"])
+        .show_save_single_file()
+    {
+        Ok(Some(path)) => path,
+        Ok(None) => return, // Canceled dialog
+        Err(error) => {
+            MessageDialog::new()
+                .set_title("Error")
+                .set_type(MessageType::Error)
+                .set_text(format!("Could not open FileDialog:\n {}", error).as_str())
+                .show_alert()
+                .ok();
+            return;
+        }
+    };
+    match start(&path, sample_rate) {
+        Ok(recorder) => {
+            let capture = matches!(recorder, Recorder::Channels(_));
+            gameboy.cpu.memory_mut().apu.set_capture(capture);
+            graphics.recorder = Some(recorder);
+        }
+        Err(error) => {
+            MessageDialog::new()
+                .set_title("Error")
+                .set_type(MessageType::Error)
+                .set_text(format!("Could not start recording:\n {}", error).as_str())
+                .show_alert()
+                .ok();
+        }
+    }
+}
+
+/// Stop the recording in progress and complete its files
+pub fn stop_recording(graphics: &mut Graphics) {
+    let recorder = match graphics.recorder.take() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    if let Some(gameboy) = &mut graphics.gameboy {
+        gameboy.cpu.memory_mut().apu.set_capture(false);
+    }
+    if let Err(error) = recorder.finish() {
+        MessageDialog::new()
+            .set_title("Error")
+            .set_type(MessageType::Error)
+            .set_text(format!("Could not write recording:\n {}", error).as_str())
+            .show_alert()
+            .ok();
+    }
+}
+
+/// Cycle audio channel 1 between playing, muted and solo
+pub fn cycle_channel1(graphics: &mut Graphics) {
+    cycle_channel(graphics, 0);
+}
+
+/// Cycle audio channel 2 between playing, muted and solo
+pub fn cycle_channel2(graphics: &mut Graphics) {
+    cycle_channel(graphics, 1);
+}
+
+/// Cycle audio channel 3 between playing, muted and solo
+pub fn cycle_channel3(graphics: &mut Graphics) {
+    cycle_channel(graphics, 2);
+}
+
+/// Cycle audio channel 4 between playing, muted and solo
+pub fn cycle_channel4(graphics: &mut Graphics) {
+    cycle_channel(graphics, 3);
+}
+
+/// Mute *channel* if it plays, play it alone if it is muted, and play every channel again if it
+/// played alone
+fn cycle_channel(graphics: &mut Graphics, channel: usize) {
+    if let Some(gameboy) = &mut graphics.gameboy {
+        let apu = &mut gameboy.cpu.memory_mut().apu;
+        let solo = (0..CHANNELS).all(|other| apu.muted(other) != (other == channel));
+        let muted = apu.muted(channel);
+        for other in 0..CHANNELS {
+            let mute = match (solo, muted) {
+                (true, _) => false,
+                (false, true) => other != channel,
+                (false, false) => apu.muted(other) || other == channel,
+            };
+            apu.set_muted(other, mute);
+        }
     }
 }
 
@@ -44,7 +189,7 @@ pub fn load_rom(graphics: &mut Graphics) {
         Some(path) => path,
         None => return, // Canceled dialog
     };
-    let content = match fs::read(path) {
+    let content = match fs::read(&path) {
         Ok(content) => content,
         Err(error) => {
             MessageDialog::new()
@@ -56,6 +201,98 @@ pub fn load_rom(graphics: &mut Graphics) {
             return;
         }
     };
-    let memory = Memory::new(content);
-    graphics.cpu = Some(Cpu::new(memory));
+    let cartridge = match Cartridge::new(content) {
+        Ok(cartridge) => cartridge,
+        Err(error) => {
+            MessageDialog::new()
+                .set_title("Error")
+                .set_type(MessageType::Error)
+                .set_text(format!("Invalid ROM:\n {}", error).as_str())
+                .show_alert()
+                .ok();
+            return;
+        }
+    };
+    let mut memory = match &graphics.bootrom {
+        Some(bootrom) => Memory::with_bootrom(cartridge, graphics.renderer, bootrom.clone()),
+        None => Memory::new(cartridge, graphics.renderer),
+    };
+    let save_path = match memory.cartridge.battery() {
+        true => Some(path.with_extension("sav")),
+        false => None,
+    };
+    if let Some(save_path) = &save_path {
+        if let Err(error) = read_save(&mut memory.cartridge, save_path) {
+            MessageDialog::new()
+                .set_title("Error")
+                .set_type(MessageType::Error)
+                .set_text(format!("Could not load save:\n {}", error).as_str())
+                .show_alert()
+                .ok();
+            return;
+        }
+    }
+    // The new game is sure to replace the current one, which can be closed
+    stop_recording(graphics);
+    flush_save(graphics);
+    flush_printer(graphics);
+    graphics.save_path = save_path;
+    let partner = graphics.partner.take().or_else(|| {
+        graphics
+            .gameboy
+            .as_mut()
+            .and_then(|gameboy| gameboy.cpu.memory_mut().serial.take_partner())
+    });
+    if let Some(partner) = partner {
+        memory.serial.set_partner(partner);
+    }
+    memory.apu.set_sample_rate(graphics.sample_rate());
+    let cpu = match memory.bootrom_mapped() {
+        true => Cpu::new(memory),
+        false => Cpu::post_boot(memory),
+    };
+    graphics.gameboy = Some(GameBoy::new(cpu));
+}
+
+/// Complete the page being printed, if any, and write the pending pages of the printer
+pub fn flush_printer(graphics: &mut Graphics) {
+    let printer = graphics
+        .gameboy
+        .as_mut()
+        .and_then(|gameboy| gameboy.cpu.memory_mut().serial.printer_mut());
+    if let Some(printer) = printer {
+        printer.finish_page();
+        printout::save_all(printer, &graphics.print_dir);
+    }
+}
+
+/// Write the battery-backed RAM of the running cartridge next to its ROM
+pub fn flush_save(graphics: &mut Graphics) {
+    if let (Some(gameboy), Some(save_path)) = (&graphics.gameboy, &graphics.save_path) {
+        let save = gameboy.cpu.memory().cartridge.save(unix_time());
+        if let Err(error) = fs::write(save_path, save) {
+            MessageDialog::new()
+                .set_title("Error")
+                .set_type(MessageType::Error)
+                .set_text(format!("Could not write save:\n {}", error).as_str())
+                .show_alert()
+                .ok();
+        }
+    }
+}
+
+/// Restore the battery-backed RAM of *cartridge* from *save_path*, if the file exists
+fn read_save(cartridge: &mut Cartridge, save_path: &Path) -> Result<(), String> {
+    if !save_path.exists() {
+        return Ok(());
+    }
+    let save = fs::read(save_path).map_err(|error| error.to_string())?;
+    cartridge.load(&save, unix_time())
+}
+
+/// Get the number of seconds elapsed since the UNIX epoch
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
 }
@@ -5,7 +5,11 @@ use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::Sdl;
 
-use super::controller::{load_rom, step, toggle_overlay};
+use super::super::hardware::apu::CHANNELS;
+use super::controller::{
+    cycle_channel1, cycle_channel2, cycle_channel3, cycle_channel4, load_rom, pause, play,
+    record_channels, record_mixed, step, toggle_overlay,
+};
 use super::gui::button::Button;
 use super::gui::textbox::TextBox;
 use super::Graphics;
@@ -22,7 +26,7 @@ const PRG_HEIGHT: u32 = 400;
 /// Debugger width
 const SCREEN_WIDTH: u32 = 300;
 /// Debugger height
-const SCREEN_HEIGHT: u32 = SPACE_SZ * 7 + BTN_HEIGHT * 4 + REG_HEIGHT + PRG_HEIGHT;
+const SCREEN_HEIGHT: u32 = SPACE_SZ * 9 + BTN_HEIGHT * 6 + REG_HEIGHT + PRG_HEIGHT;
 
 const COLOR_BACKGROUND: Color = Color::RGB(13, 16, 23);
 const COLOR_BACKGROUND_BUTTON: Color = Color::RGB(33, 38, 45);
@@ -32,6 +36,8 @@ pub struct Debugger {
     canvas: Canvas<Window>,
     boxes: Vec<TextBox>,
     buttons: Vec<Button>,
+    /// Index of the first audio button, the record buttons followed by the channel buttons
+    audio_buttons: usize,
 }
 
 impl Debugger {
@@ -111,7 +117,7 @@ impl Debugger {
         for i in 0..nb_buttons {
             let x = i * btn_width + (i + 1) * SPACE_SZ;
             let y = SPACE_SZ * 5 + BTN_HEIGHT * 2 + REG_HEIGHT + PRG_HEIGHT;
-            let funcs: Vec<Option<fn(&mut Graphics)>> = vec![None, None, Some(step)];
+            let funcs: Vec<Option<fn(&mut Graphics)>> = vec![Some(play), Some(pause), Some(step)];
             buttons.push(Button::new(
                 (x as i32, y as i32),
                 btn_width,
@@ -138,11 +144,58 @@ impl Debugger {
             None,
         ));
 
-        Self {
+        // Record - Record channels
+        let audio_buttons = buttons.len();
+        let funcs: Vec<fn(&mut Graphics)> = vec![record_mixed, record_channels];
+        let nb_buttons = 2;
+        let btn_width = (SCREEN_WIDTH - SPACE_SZ * (nb_buttons + 1)) / nb_buttons;
+
+        for i in 0..nb_buttons {
+            let x = i * btn_width + (i + 1) * SPACE_SZ;
+            let y = SPACE_SZ * 7 + BTN_HEIGHT * 4 + REG_HEIGHT + PRG_HEIGHT;
+            buttons.push(Button::new(
+                (x as i32, y as i32),
+                btn_width,
+                btn_height,
+                10,
+                String::new(),
+                true,
+                Some(funcs[i as usize]),
+            ));
+        }
+
+        // Channels, cycling between playing, muted and solo
+        let funcs: Vec<fn(&mut Graphics)> = vec![
+            cycle_channel1,
+            cycle_channel2,
+            cycle_channel3,
+            cycle_channel4,
+        ];
+        let nb_buttons = CHANNELS as u32;
+        let btn_width = (SCREEN_WIDTH - SPACE_SZ * (nb_buttons + 1)) / nb_buttons;
+
+        for i in 0..nb_buttons {
+            let x = i * btn_width + (i + 1) * SPACE_SZ;
+            let y = SPACE_SZ * 8 + BTN_HEIGHT * 5 + REG_HEIGHT + PRG_HEIGHT;
+            buttons.push(Button::new(
+                (x as i32, y as i32),
+                btn_width,
+                btn_height,
+                10,
+                String::new(),
+                true,
+                Some(funcs[i as usize]),
+            ));
+        }
+
+        let mut debugger = Self {
             canvas,
             boxes,
             buttons,
-        }
+            audio_buttons,
+        };
+        debugger.update_audio(false, [false; CHANNELS]);
+        debugger
     }
 
     /// Return a button if exists at a given position
@@ -152,6 +205,28 @@ impl Debugger {
             .find(|button| button.rect().contains_point(Point::new(x, y)))
     }
 
+    /// Update the labels of the audio buttons from the *recording* state and the *muted* channels
+    pub fn update_audio(&mut self, recording: bool, muted: [bool; CHANNELS]) {
+        let labels = match recording {
+            true => ["Stop recording", "Stop recording"],
+            false => ["Record WAV", "Record channels"],
+        };
+        for (i, label) in labels.iter().enumerate() {
+            self.buttons[self.audio_buttons + i].set_text(label.to_string());
+        }
+
+        let channels = &mut self.buttons[self.audio_buttons + labels.len()..];
+        for (channel, button) in channels.iter_mut().enumerate() {
+            let solo = (0..CHANNELS).all(|other| muted[other] != (other == channel));
+            let state = match (solo, muted[channel]) {
+                (true, _) => " solo",
+                (false, true) => " mute",
+                (false, false) => "",
+            };
+            button.set_text(format!("CH{}{}", channel + 1, state));
+        }
+    }
+
     /// Print the actual frame into the Debugger window
     pub fn print_frame(
         &mut self,
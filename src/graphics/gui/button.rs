@@ -115,8 +115,8 @@ impl Button {
         &self.rect
     }
 
-    pub fn text(&self) -> &String {
-        &self.text
+    pub fn set_text(&mut self, text: String) {
+        self.text = text
     }
 
     pub fn active(&self) -> bool {
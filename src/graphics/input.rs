@@ -0,0 +1,263 @@
+//! Input map binding keyboard keys and game controller buttons to the GameBoy buttons
+//!
+//! The map is loaded from a config file of `name = value` lines, where `#` starts a comment:
+//!
+//! | Name              | Value                                                            |
+//! |-------------------|------------------------------------------------------------------|
+//! | `key.<button>`    | SDL key names, separated by commas                               |
+//! | `pad.<button>`    | SDL game controller button names, separated by commas            |
+//! | `stick_threshold` | Deflection of the left stick pressing a direction, 1 to 32767    |
+//!
+//! where `<button>` is one of `a`, `b`, `select`, `start`, `right`, `left`, `up` and `down`.
+//! Buttons missing from the file keep their default bindings.
+
+use sdl2::controller::{self, Axis};
+use sdl2::keyboard::Keycode;
+
+use std::fs;
+use std::path::Path;
+
+use super::super::hardware::joypad::Button;
+
+/// GameBoy buttons by their name in the config file
+const BUTTON_NAMES: [(&str, Button); 8] = [
+    ("a", Button::A),
+    ("b", Button::B),
+    ("select", Button::Select),
+    ("start", Button::Start),
+    ("right", Button::Right),
+    ("left", Button::Left),
+    ("up", Button::Up),
+    ("down", Button::Down),
+];
+
+/// Default keyboard keys, by SDL key name
+const DEFAULT_KEYS: [(&str, Button); 8] = [
+    ("O", Button::A),
+    ("K", Button::B),
+    ("B", Button::Select),
+    ("V", Button::Start),
+    ("D", Button::Right),
+    ("A", Button::Left),
+    ("W", Button::Up),
+    ("S", Button::Down),
+];
+
+/// Default game controller buttons, by SDL button name
+const DEFAULT_PAD_BUTTONS: [(&str, Button); 8] = [
+    ("a", Button::A),
+    ("b", Button::B),
+    ("back", Button::Select),
+    ("start", Button::Start),
+    ("dpright", Button::Right),
+    ("dpleft", Button::Left),
+    ("dpup", Button::Up),
+    ("dpdown", Button::Down),
+];
+
+/// Default deflection of the left stick pressing a direction, half of its range
+const DEFAULT_STICK_THRESHOLD: i16 = 16384;
+
+/// Bindings of the GameBoy buttons
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputMap {
+    /// Keyboard keys, by SDL key name
+    keys: Vec<(String, Button)>,
+    /// Game controller buttons, by SDL button name
+    pad_buttons: Vec<(String, Button)>,
+    /// Deflection of the left stick pressing a direction
+    stick_threshold: i16,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = |defaults: &[(&str, Button)]| {
+            defaults
+                .iter()
+                .map(|&(name, button)| (name.to_string(), button))
+                .collect()
+        };
+        Self {
+            keys: bindings(&DEFAULT_KEYS),
+            pad_buttons: bindings(&DEFAULT_PAD_BUTTONS),
+            stick_threshold: DEFAULT_STICK_THRESHOLD,
+        }
+    }
+}
+
+impl InputMap {
+    /// Load the input map from the config file *path*, checking every name against SDL
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let config =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut input =
+            Self::parse(&config).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+        for (name, _) in &mut input.keys {
+            *name = Keycode::from_name(name)
+                .ok_or(format!("{}: unknown key {}", path.display(), name))?
+                .name();
+        }
+        for (name, _) in &input.pad_buttons {
+            controller::Button::from_string(name).ok_or(format!(
+                "{}: unknown pad button {}",
+                path.display(),
+                name
+            ))?;
+        }
+        Ok(input)
+    }
+
+    /// Parse the config file content *config* over the default bindings
+    fn parse(config: &str) -> Result<Self, String> {
+        let mut input = Self::default();
+
+        for (number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let (name, value) = line
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .ok_or_else(|| error("expected name = value"))?;
+
+            if name == "stick_threshold" {
+                input.stick_threshold = value
+                    .parse()
+                    .ok()
+                    .filter(|&threshold: &i16| threshold > 0)
+                    .ok_or_else(|| error("invalid stick threshold"))?;
+                continue;
+            }
+            let (bindings, button) = match name.split_once('.') {
+                Some(("key", button)) => (&mut input.keys, button),
+                Some(("pad", button)) => (&mut input.pad_buttons, button),
+                _ => return Err(error(&format!("unknown setting {}", name))),
+            };
+            let button = BUTTON_NAMES
+                .iter()
+                .find(|(name, _)| *name == button)
+                .map(|&(_, button)| button)
+                .ok_or_else(|| error(&format!("unknown button {}", button)))?;
+            bindings.retain(|&(_, bound)| bound != button);
+            bindings.extend(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| (name.to_string(), button)),
+            );
+        }
+        Ok(input)
+    }
+
+    /// Return the GameBoy button bound to the key given
+    pub fn key(&self, keycode: Keycode) -> Option<Button> {
+        let name = keycode.name();
+        find(&self.keys, &name)
+    }
+
+    /// Return the name of the first key bound to a GameBoy button
+    pub fn key_name(&self, button: Button) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|&&(_, bound)| bound == button)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Return the GameBoy button bound to the game controller button given
+    pub fn pad_button(&self, button: controller::Button) -> Option<Button> {
+        find(&self.pad_buttons, &button.string())
+    }
+
+    /// Return the direction pressed by the left stick at *value* on *axis*, if any
+    pub fn stick_direction(&self, axis: Axis, value: i16) -> Option<Button> {
+        let (negative, positive) = match axis {
+            Axis::LeftX => (Button::Left, Button::Right),
+            Axis::LeftY => (Button::Up, Button::Down),
+            _ => return None,
+        };
+        match value {
+            value if value <= -self.stick_threshold => Some(negative),
+            value if value >= self.stick_threshold => Some(positive),
+            _ => None,
+        }
+    }
+}
+
+/// Return the GameBoy button bound to *name* in *bindings*
+fn find(bindings: &[(String, Button)], name: &str) -> Option<Button> {
+    bindings
+        .iter()
+        .find(|(bound, _)| bound == name)
+        .map(|&(_, button)| button)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = "
+            # Arrows for the directions
+            key.up = Up, Keypad 8
+            key.down = Down
+            pad.select = x  # Unusual
+            stick_threshold = 8000
+        ";
+        let input = InputMap::parse(config).unwrap();
+
+        assert_eq!(find(&input.keys, "Up"), Some(Button::Up));
+        assert_eq!(find(&input.keys, "Keypad 8"), Some(Button::Up));
+        assert_eq!(find(&input.keys, "W"), None);
+        assert_eq!(find(&input.keys, "O"), Some(Button::A));
+        assert_eq!(input.key_name(Button::Down), Some("Down"));
+        assert_eq!(find(&input.pad_buttons, "x"), Some(Button::Select));
+        assert_eq!(find(&input.pad_buttons, "back"), None);
+        assert_eq!(input.stick_threshold, 8000);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(InputMap::parse(""), Ok(InputMap::default()));
+        assert_eq!(
+            InputMap::parse("key.a O").unwrap_err(),
+            "line 1: expected name = value"
+        );
+        assert_eq!(
+            InputMap::parse("\nkey.turbo = T").unwrap_err(),
+            "line 2: unknown button turbo"
+        );
+        assert_eq!(
+            InputMap::parse("mouse.a = left").unwrap_err(),
+            "line 1: unknown setting mouse.a"
+        );
+        assert_eq!(
+            InputMap::parse("stick_threshold = -5").unwrap_err(),
+            "line 1: invalid stick threshold"
+        );
+    }
+
+    #[test]
+    fn test_stick() {
+        let input = InputMap::default();
+
+        assert_eq!(
+            input.stick_direction(Axis::LeftX, -20000),
+            Some(Button::Left)
+        );
+        assert_eq!(
+            input.stick_direction(Axis::LeftX, 16384),
+            Some(Button::Right)
+        );
+        assert_eq!(input.stick_direction(Axis::LeftY, -100), None);
+        assert_eq!(
+            input.stick_direction(Axis::LeftY, 32767),
+            Some(Button::Down)
+        );
+        assert_eq!(input.stick_direction(Axis::RightX, 32767), None);
+    }
+}
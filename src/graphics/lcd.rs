@@ -1,19 +1,27 @@
 //! LCD Window
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::{Point, Rect};
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::video::WindowPos::{Centered, Positioned};
 use sdl2::Sdl;
 
+use super::super::hardware::joypad;
 use super::gui::button::Button;
 use super::gui::joystick::{Joystick, JOYSTICK_TEXTURE_HEIGHT, JOYSTICK_TEXTURE_WIDTH};
+use super::input::InputMap;
 
 /// LCD width
-const SCREEN_WIDTH: u32 = 166;
+const SCREEN_WIDTH: u32 = 160;
 /// LCD height
 const SCREEN_HEIGHT: u32 = 144;
 
+/// LCD window title
+const TITLE: &str = "gbmu";
+
+/// LCD window title while the cartridge rumble motor is on
+const TITLE_RUMBLE: &str = "gbmu ~ rumble ~";
+
 /// Ratio used to render the LCD window
 const PIXEL_SIZE: u32 = 4;
 
@@ -39,35 +47,34 @@ const BUTTON_RIGHT_POS: (f32, f32) = (185.0, 102.0);
 const BUTTON_START_POS: (f32, f32) = (220.0, 320.0);
 const BUTTON_SELECT_POS: (f32, f32) = (380.0, 320.0);
 
-// Buttons label
-const BUTTON_A_LABEL: &str = "O";
-const BUTTON_B_LABEL: &str = "K";
-
-const BUTTON_UP_LABEL: &str = "W";
-const BUTTON_LEFT_LABEL: &str = "A";
-const BUTTON_DOWN_LABEL: &str = "S";
-const BUTTON_RIGHT_LABEL: &str = "D";
-
-const BUTTON_START_LABEL: &str = "V";
-const BUTTON_SELECT_LABEL: &str = "B";
+/// GameBoy buttons pressed by the overlay buttons, in the same order
+const JOYPAD_BUTTONS: [joypad::Button; 8] = [
+    joypad::Button::A,
+    joypad::Button::B,
+    joypad::Button::Up,
+    joypad::Button::Left,
+    joypad::Button::Down,
+    joypad::Button::Right,
+    joypad::Button::Start,
+    joypad::Button::Select,
+];
 
 /// Represent the Gameboy LCD window
 pub struct Lcd {
     canvas: Canvas<Window>,
     joystick: Joystick,
     buttons: Vec<Button>,
+    /// Rumble motor state shown in the window title
+    rumble: bool,
 }
 
 impl Lcd {
-    /// Create a new LCD using the sdl_context given
-    pub fn new(sdl_context: &Sdl) -> Self {
+    /// Create a new LCD using the sdl_context given, labelling the overlay with the keys of
+    /// the input map
+    pub fn new(sdl_context: &Sdl, input: &InputMap) -> Self {
         let video_subsystem = sdl_context.video().unwrap();
         let mut window = video_subsystem
-            .window(
-                "gbmu",
-                SCREEN_WIDTH * PIXEL_SIZE,
-                SCREEN_HEIGHT * PIXEL_SIZE,
-            )
+            .window(TITLE, SCREEN_WIDTH * PIXEL_SIZE, SCREEN_HEIGHT * PIXEL_SIZE)
             .build()
             .unwrap();
         window.set_position(Centered, Positioned(0));
@@ -85,7 +92,7 @@ impl Lcd {
                 (BUTTON_AB_WIDTH * ratio_width) as u32,
                 (BUTTON_AB_HEIGHT * ratio_height) as u32,
                 10 * PIXEL_SIZE,
-                BUTTON_A_LABEL.to_string(),
+                String::new(),
                 true,
                 None,
             ),
@@ -98,7 +105,7 @@ impl Lcd {
                 (BUTTON_AB_WIDTH * ratio_width) as u32,
                 (BUTTON_AB_HEIGHT * ratio_height) as u32,
                 10 * PIXEL_SIZE,
-                BUTTON_B_LABEL.to_string(),
+                String::new(),
                 true,
                 None,
             ),
@@ -111,7 +118,7 @@ impl Lcd {
                 (BUTTON_DIR_WIDTH * ratio_width) as u32,
                 (BUTTON_DIR_HEIGHT * ratio_height) as u32,
                 10 * PIXEL_SIZE,
-                BUTTON_UP_LABEL.to_string(),
+                String::new(),
                 true,
                 None,
             ),
@@ -124,7 +131,7 @@ impl Lcd {
                 (BUTTON_DIR_WIDTH * ratio_width) as u32,
                 (BUTTON_DIR_HEIGHT * ratio_height) as u32,
                 10 * PIXEL_SIZE,
-                BUTTON_LEFT_LABEL.to_string(),
+                String::new(),
                 true,
                 None,
             ),
@@ -137,7 +144,7 @@ impl Lcd {
                 (BUTTON_DIR_WIDTH * ratio_width) as u32,
                 (BUTTON_DIR_HEIGHT * ratio_height) as u32,
                 10 * PIXEL_SIZE,
-                BUTTON_DOWN_LABEL.to_string(),
+                String::new(),
                 true,
                 None,
             ),
@@ -150,7 +157,7 @@ impl Lcd {
                 (BUTTON_DIR_WIDTH * ratio_width) as u32,
                 (BUTTON_DIR_HEIGHT * ratio_height) as u32,
                 10 * PIXEL_SIZE,
-                BUTTON_RIGHT_LABEL.to_string(),
+                String::new(),
                 true,
                 None,
             ),
@@ -163,7 +170,7 @@ impl Lcd {
                 (BUTTON_OPT_WIDTH * ratio_width) as u32,
                 (BUTTON_OPT_HEIGHT * ratio_height) as u32,
                 7 * PIXEL_SIZE,
-                BUTTON_START_LABEL.to_string(),
+                String::new(),
                 false,
                 None,
             ),
@@ -176,15 +183,26 @@ impl Lcd {
                 (BUTTON_OPT_WIDTH * ratio_width) as u32,
                 (BUTTON_OPT_HEIGHT * ratio_height) as u32,
                 7 * PIXEL_SIZE,
-                BUTTON_SELECT_LABEL.to_string(),
+                String::new(),
                 false,
                 None,
             ),
         ];
-        Self {
+        let mut lcd = Self {
             canvas,
             joystick,
             buttons,
+            rumble: false,
+        };
+        lcd.set_labels(input);
+        lcd
+    }
+
+    /// Label the overlay buttons with the first key bound to each GameBoy button
+    pub fn set_labels(&mut self, input: &InputMap) {
+        for (button, joypad_button) in self.buttons.iter_mut().zip(JOYPAD_BUTTONS) {
+            let label = input.key_name(joypad_button).unwrap_or("-");
+            button.set_text(label.to_string());
         }
     }
 
@@ -217,23 +235,50 @@ impl Lcd {
         }
     }
 
-    /// Return a button if the name is matching
-    pub fn keypress(&mut self, name: String) -> Option<&mut Button> {
-        self.buttons
-            .iter_mut()
-            .find(|button| button.text().eq(&name))
-    }
-
-    /// Return a button if exists at a given position
-    pub fn click(&mut self, x: i32, y: i32) -> Option<&mut Button> {
+    /// Return the GameBoy button of the overlay at a given position, if it is shown
+    pub fn click(&self, x: i32, y: i32) -> Option<joypad::Button> {
+        if self.joystick.hidden() {
+            return None;
+        }
         self.buttons
-            .iter_mut()
-            .find(|button| button.rect().contains_point(Point::new(x, y)))
+            .iter()
+            .position(|button| button.rect().contains_point(Point::new(x, y)))
+            .map(|index| JOYPAD_BUTTONS[index])
     }
 
     /// Print the actual frame into the LCD window
-    pub fn print_frame(&mut self) {
+    /// Without a *frame* from the GameBoy, a placeholder gradient is printed
+    pub fn print_frame(&mut self, frame: Option<&[u32]>) {
         self.canvas.clear();
+        match frame {
+            Some(frame) => self.blit(frame),
+            None => self.print_gradient(),
+        }
+        self.render_joystick();
+        self.canvas.present();
+    }
+
+    /// Copy a frame of `0xRRGGBB` pixels, line by line, to the whole canvas
+    fn blit(&mut self, frame: &[u32]) {
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB888, SCREEN_WIDTH, SCREEN_HEIGHT)
+            .unwrap();
+        texture
+            .with_lock(None, |buffer: &mut [u8], pitch: usize| {
+                for (y, line) in frame.chunks(SCREEN_WIDTH as usize).enumerate() {
+                    for (x, color) in line.iter().enumerate() {
+                        let offset = y * pitch + x * 4;
+                        buffer[offset..offset + 4].copy_from_slice(&color.to_ne_bytes());
+                    }
+                }
+            })
+            .unwrap();
+        self.canvas.copy(&texture, None, None).unwrap();
+    }
+
+    /// Print a gradient covering the whole canvas
+    fn print_gradient(&mut self) {
         let mut x: u32 = 0;
         let mut y: u32 = 0;
         while y < self.get_height() {
@@ -246,8 +291,15 @@ impl Lcd {
                 y += 1;
             }
         }
-        self.render_joystick();
-        self.canvas.present();
+    }
+
+    /// Report the cartridge rumble motor state in the window title
+    pub fn set_rumble(&mut self, rumble: bool) {
+        if self.rumble != rumble {
+            self.rumble = rumble;
+            let title = if rumble { TITLE_RUMBLE } else { TITLE };
+            self.canvas.window_mut().set_title(title).unwrap();
+        }
     }
 
     /// Get width of the LCD screen
@@ -9,22 +9,42 @@
 //! graphics.render();
 //! ```
 
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::controller::{Axis, GameController};
 use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::Sdl;
 
-use std::time::Duration;
+use std::array;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
 
+use super::gameboy::{GameBoy, FRAME_DURATION};
+use super::hardware::apu::{CHANNELS, SAMPLE_RATE};
+use super::hardware::bootrom::Bootrom;
 use super::hardware::cpu::registers::Register8;
-use super::hardware::cpu::Cpu;
+use super::hardware::ppu::Renderer;
+use super::hardware::serial::Partner;
 
 mod controller;
 mod debugger;
 mod gui;
+pub mod input;
 mod lcd;
+mod printout;
+mod recorder;
 
 use debugger::Debugger;
+use input::InputMap;
 use lcd::Lcd;
+use recorder::Recorder;
+
+/// Number of samples per channel in the SDL audio buffer
+const AUDIO_BUFFER_SAMPLES: u16 = 1024;
+
+/// Audio queued beyond this duration in milliseconds is dropped, to bound the latency
+const AUDIO_MAX_LATENCY: u32 = 100;
 
 pub struct Graphics {
     /// Sdl context provide by sdl2
@@ -33,28 +53,108 @@ pub struct Graphics {
     pub lcd: Lcd,
     /// Debugger Window providing options for the GameBoy emulator
     pub debugger: Debugger,
-    pub cpu: Option<Cpu>,
+    /// Emulated GameBoy, once a ROM is loaded
+    pub gameboy: Option<GameBoy>,
+    /// Set while the emulation runs at full speed
+    pub playing: bool,
+    /// Save file of the running cartridge, if it has a battery
+    pub save_path: Option<PathBuf>,
+    /// PPU renderer used by the GameBoys created on ROM load
+    pub renderer: Renderer,
+    /// Stereo audio output, fed with the APU samples of each frame, if a device is available
+    audio: Option<AudioQueue<f32>>,
+    /// Audio recording in progress
+    pub recorder: Option<Recorder>,
+    /// Bindings of the keyboard and game controllers to the GameBoy buttons
+    input: InputMap,
+    /// Print the bytes sent on the serial port to the terminal
+    pub print_serial: bool,
+    /// Partner plugged in the serial port, until the first ROM is loaded
+    pub partner: Option<Partner>,
+    /// Directory receiving the pages printed by the Game Boy Printer
+    pub print_dir: PathBuf,
+    /// Boot ROM run before the cartridge by the GameBoys created on ROM load
+    pub bootrom: Option<Bootrom>,
 }
 
 impl Graphics {
     /// Create a new Graphics object from a sdl2 context
     pub fn new() -> Self {
         let sdl_context = sdl2::init().unwrap();
-        let lcd = Lcd::new(&sdl_context);
+        let input = InputMap::default();
+        let lcd = Lcd::new(&sdl_context, &input);
         let (mut x, y) = lcd.canvas().window().position();
         x += lcd.canvas().window().size().0 as i32 + 10;
         let debugger = Debugger::new(&sdl_context, x, y);
+        let audio = sdl_context.audio().and_then(|audio| {
+            audio.open_queue(
+                None,
+                &AudioSpecDesired {
+                    freq: Some(SAMPLE_RATE as i32),
+                    channels: Some(2),
+                    samples: Some(AUDIO_BUFFER_SAMPLES),
+                },
+            )
+        });
+        let audio = match audio {
+            Ok(audio) => {
+                audio.resume();
+                Some(audio)
+            }
+            Err(error) => {
+                println!("Could not open the audio device, running silent: {}", error);
+                None
+            }
+        };
         Self {
             sdl_context,
             lcd,
             debugger,
-            cpu: None,
+            gameboy: None,
+            playing: false,
+            save_path: None,
+            renderer: Renderer::Scanline,
+            audio,
+            recorder: None,
+            input,
+            print_serial: false,
+            partner: None,
+            print_dir: PathBuf::from("."),
+            bootrom: None,
         }
     }
 
+    /// Return the sample rate of the audio output, or the preferred one when running silent
+    pub fn sample_rate(&self) -> u32 {
+        self.audio
+            .as_ref()
+            .map_or(SAMPLE_RATE, |audio| audio.spec().freq as u32)
+    }
+
+    /// Replace the bindings of the GameBoy buttons
+    pub fn set_input(&mut self, input: InputMap) {
+        self.lcd.set_labels(&input);
+        self.input = input;
+    }
+
     /// Render LCD and Debugger Windows, loop and trigger GUI buttons events
     pub fn render(&mut self) {
         let mut event_pump = self.sdl_context.event_pump().unwrap();
+        // Game controllers are optional, the keyboard and the overlay still play without them
+        let game_controller = match self.sdl_context.game_controller() {
+            Ok(game_controller) => Some(game_controller),
+            Err(error) => {
+                println!("Could not start game controller support: {}", error);
+                None
+            }
+        };
+        // Opened game controllers, SDL reports the ones already plugged in as added
+        let mut controllers: Vec<GameController> = Vec::new();
+        // Directions pressed by the left stick, horizontal then vertical
+        let mut stick = [None; 2];
+        let mut next_frame = Instant::now();
+        // GameBoy button held down by the mouse on the overlay
+        let mut clicked = None;
         'running: loop {
             for event in event_pump.poll_iter() {
                 match event {
@@ -67,9 +167,20 @@ impl Graphics {
                         keycode: Some(Keycode::Escape),
                         ..
                     } => break 'running,
-                    Event::KeyDown { keycode, .. } => {
-                        if let Some(button) = self.lcd.keypress(keycode.unwrap().name()) {
-                            button.clone().action(self)
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(button) = self.input.key(keycode) {
+                            controller::press(self, button);
+                        }
+                    }
+                    Event::KeyUp {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(button) = self.input.key(keycode) {
+                            controller::release(self, button);
                         }
                     }
                     Event::MouseButtonDown {
@@ -82,19 +193,112 @@ impl Graphics {
                                 }
                             }
                         } else if self.lcd.get_window_id() == window_id {
-                            if let Some(button) = self.lcd.click(x, y) {
-                                if button.active() {
-                                    button.clone().action(self)
-                                }
+                            clicked = self.lcd.click(x, y);
+                            if let Some(button) = clicked {
+                                controller::press(self, button);
+                            }
+                        }
+                    }
+                    Event::MouseButtonUp { .. } => {
+                        if let Some(button) = clicked.take() {
+                            controller::release(self, button);
+                        }
+                    }
+                    Event::ControllerDeviceAdded { which, .. } => {
+                        if let Some(game_controller) = &game_controller {
+                            match game_controller.open(which) {
+                                Ok(opened) => controllers.push(opened),
+                                Err(error) => println!("Could not open game controller: {}", error),
                             }
                         }
                     }
+                    Event::ControllerDeviceRemoved { which, .. } => {
+                        controllers.retain(|opened| opened.instance_id() != which);
+                    }
+                    Event::ControllerButtonDown { button, .. } => {
+                        if let Some(button) = self.input.pad_button(button) {
+                            controller::press(self, button);
+                        }
+                    }
+                    Event::ControllerButtonUp { button, .. } => {
+                        if let Some(button) = self.input.pad_button(button) {
+                            controller::release(self, button);
+                        }
+                    }
+                    Event::ControllerAxisMotion { axis, value, .. } => {
+                        let direction = self.input.stick_direction(axis, value);
+                        let held = match axis {
+                            Axis::LeftX => &mut stick[0],
+                            Axis::LeftY => &mut stick[1],
+                            _ => continue,
+                        };
+                        if *held != direction {
+                            if let Some(button) = held.take() {
+                                controller::release(self, button);
+                            }
+                            if let Some(button) = direction {
+                                controller::press(self, button);
+                            }
+                            *held = direction;
+                        }
+                    }
                     _ => {}
                 }
             }
-            self.lcd.print_frame();
-            match &self.cpu {
-                Some(cpu) => self.debugger.print_frame(
+            if let Some(gameboy) = &mut self.gameboy {
+                if self.playing {
+                    gameboy.run_frame();
+                    let samples = gameboy.cpu.memory_mut().apu.take_samples();
+                    if let Some(audio) = &self.audio {
+                        let spec = audio.spec();
+                        let max_queued =
+                            spec.freq as u32 * spec.channels as u32 * 4 / 1000 * AUDIO_MAX_LATENCY;
+                        if audio.size() < max_queued {
+                            audio.queue_audio(&samples).ok();
+                        }
+                    }
+                    let output = gameboy.cpu.memory_mut().serial.take_output();
+                    if self.print_serial && !output.is_empty() {
+                        print!("{}", String::from_utf8_lossy(&output));
+                        io::stdout().flush().ok();
+                    }
+                    if let Some(error) = gameboy.cpu.memory_mut().serial.take_link_error() {
+                        println!("Link cable disconnected: {}", error);
+                    }
+                    if let Some(printer) = gameboy.cpu.memory_mut().serial.printer_mut() {
+                        printout::save_all(printer, &self.print_dir);
+                    }
+                    let apu = &mut gameboy.cpu.memory_mut().apu;
+                    let recorded = self
+                        .recorder
+                        .as_mut()
+                        .map(|recorder| recorder.record(apu, &samples));
+                    if let Some(Err(error)) = recorded {
+                        println!("Recording stopped: {}", error);
+                        apu.set_capture(false);
+                        if let Some(recorder) = self.recorder.take() {
+                            // Complete the headers, so that the samples written so far play
+                            recorder.finish().ok();
+                        }
+                    }
+                }
+                self.lcd.set_rumble(gameboy.cpu.memory().cartridge.rumble());
+            }
+            let frame = self
+                .gameboy
+                .as_ref()
+                .map(|gameboy| gameboy.cpu.memory().ppu.frame());
+            self.lcd.print_frame(frame);
+            let muted = match &self.gameboy {
+                Some(gameboy) => {
+                    let apu = &gameboy.cpu.memory().apu;
+                    array::from_fn::<_, CHANNELS, _>(|channel| apu.muted(channel))
+                }
+                None => [false; CHANNELS],
+            };
+            self.debugger.update_audio(self.recorder.is_some(), muted);
+            match &self.gameboy {
+                Some(GameBoy { cpu, .. }) => self.debugger.print_frame(
                     self.print_registers(),
                     self.get_flags(),
                     cpu.disassemble(
@@ -104,14 +308,24 @@ impl Graphics {
                 ),
                 None => self.debugger.print_frame(vec![], vec![], vec![]),
             };
-            std::thread::sleep(Duration::from_millis(10));
+            // Pace the loop to the GameBoy refresh rate, without catching up on late frames
+            next_frame += FRAME_DURATION;
+            let now = Instant::now();
+            if next_frame > now {
+                std::thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
         }
+        controller::stop_recording(self);
+        controller::flush_save(self);
+        controller::flush_printer(self);
     }
 
     fn print_registers(&self) -> Vec<String> {
         let mut registers = Vec::new();
 
-        if let Some(cpu) = &self.cpu {
+        if let Some(GameBoy { cpu, .. }) = &self.gameboy {
             registers.push("A: ".to_owned() + &cpu.print_register(Register8::A));
             registers.push("B: ".to_owned() + &cpu.print_register(Register8::B));
             registers.push("C: ".to_owned() + &cpu.print_register(Register8::C));
@@ -126,7 +340,7 @@ impl Graphics {
     fn get_flags(&self) -> Vec<String> {
         let mut flags = Vec::new();
 
-        if let Some(cpu) = &self.cpu {
+        if let Some(GameBoy { cpu, .. }) = &self.gameboy {
             flags.push("F: ".to_owned() + &cpu.print_register(Register8::F));
         }
         flags
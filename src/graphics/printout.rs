@@ -0,0 +1,46 @@
+//! Export of the Game Boy Printer pages to PNG files
+//!
+//! Pages are written to the chosen directory as `print_N.png`, N being the first number not
+//! used by an existing file.
+
+use sdl2::image::SaveSurface;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+
+use std::path::{Path, PathBuf};
+
+use super::super::hardware::serial::printer::{Printer, Printout, PRINT_WIDTH};
+
+/// Write the pages completed by *printer* to *dir*, reporting each on the standard output
+pub fn save_all(printer: &mut Printer, dir: &Path) {
+    for printout in printer.take_printouts() {
+        match save(&printout, dir) {
+            Ok(path) => println!("Printed {}", path.display()),
+            Err(error) => println!("Could not save the printout: {}", error),
+        }
+    }
+}
+
+/// Write *printout* to a new PNG file in *dir*, then return its path
+pub fn save(printout: &Printout, dir: &Path) -> Result<PathBuf, String> {
+    let path = (1..)
+        .map(|number| dir.join(format!("print_{}.png", number)))
+        .find(|path| !path.exists())
+        .unwrap_or_default();
+    let mut bytes: Vec<u8> = printout
+        .pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_ne_bytes())
+        .collect();
+    let surface = Surface::from_data(
+        &mut bytes,
+        PRINT_WIDTH as u32,
+        printout.height as u32,
+        PRINT_WIDTH as u32 * 4,
+        PixelFormatEnum::RGB888,
+    )?;
+    surface
+        .save(&path)
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+    Ok(path)
+}
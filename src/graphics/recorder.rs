@@ -0,0 +1,178 @@
+//! Audio recorder writing the APU output to WAV files
+//!
+//! The mixed stereo output is written to a single file, or each channel to its own mono file,
+//! named after the chosen file with the channel number appended. Samples are stored as 16-bit
+//! PCM, and the sizes in the header are completed when the recording stops.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::super::hardware::apu::{Apu, CHANNELS};
+
+/// Size of the WAV header preceding the samples
+const HEADER_SIZE: u32 = 44;
+/// Size of a 16-bit PCM sample
+const SAMPLE_SIZE: u16 = 2;
+
+/// Write 16-bit PCM samples to a WAV stream
+pub struct WavWriter<W: Write + Seek> {
+    /// Stream receiving the header and the samples
+    writer: W,
+    /// Number of interleaved channels
+    channels: u16,
+    /// Samples per second of each channel
+    sample_rate: u32,
+    /// Bytes of samples written so far
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Start a WAV stream of *channels* interleaved channels sampled at *sample_rate* Hz
+    pub fn new(writer: W, channels: u16, sample_rate: u32) -> Result<Self, String> {
+        let mut wav = Self {
+            writer,
+            channels,
+            sample_rate,
+            data_size: 0,
+        };
+        let header = wav.header();
+        wav.writer
+            .write_all(&header)
+            .map_err(|error| error.to_string())?;
+        Ok(wav)
+    }
+
+    /// Append interleaved *samples*, clamped from -1 to 1
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect();
+        self.writer
+            .write_all(&bytes)
+            .map_err(|error| error.to_string())?;
+        self.data_size += bytes.len() as u32;
+        Ok(())
+    }
+
+    /// Complete the header with the size of the samples, then return the stream
+    pub fn finish(mut self) -> Result<W, String> {
+        let header = self.header();
+        self.writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.writer.write_all(&header))
+            .and_then(|_| self.writer.seek(SeekFrom::End(0)))
+            .and_then(|_| self.writer.flush())
+            .map_err(|error| error.to_string())?;
+        Ok(self.writer)
+    }
+
+    /// Build the RIFF header of the samples written so far
+    fn header(&self) -> Vec<u8> {
+        let block_align = self.channels * SAMPLE_SIZE;
+        let byte_rate = self.sample_rate * block_align as u32;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&byte_rate.to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&(SAMPLE_SIZE * 8).to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_size.to_le_bytes());
+        header
+    }
+}
+
+/// Recording in progress
+pub enum Recorder {
+    /// The mixed stereo output, in one file
+    Mixed(WavWriter<BufWriter<File>>),
+    /// Each channel in its own mono file
+    Channels(Vec<WavWriter<BufWriter<File>>>),
+}
+
+impl Recorder {
+    /// Start recording the mixed output into *path*
+    pub fn mixed(path: &Path, sample_rate: u32) -> Result<Self, String> {
+        create(path, 2, sample_rate).map(Recorder::Mixed)
+    }
+
+    /// Start recording each channel into its own file, *path* with `_chN` appended
+    pub fn channels(path: &Path, sample_rate: u32) -> Result<Self, String> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        (1..=CHANNELS)
+            .map(|channel| {
+                let path = path.with_file_name(format!("{}_ch{}.wav", stem, channel));
+                create(&path, 1, sample_rate)
+            })
+            .collect::<Result<_, _>>()
+            .map(Recorder::Channels)
+    }
+
+    /// Write the mixed *samples* of a frame, or the channel samples captured by *apu*
+    pub fn record(&mut self, apu: &mut Apu, samples: &[f32]) -> Result<(), String> {
+        match self {
+            Recorder::Mixed(wav) => wav.write(samples),
+            Recorder::Channels(wavs) => wavs
+                .iter_mut()
+                .zip(apu.take_channel_samples())
+                .try_for_each(|(wav, samples)| wav.write(&samples)),
+        }
+    }
+
+    /// Complete the recorded files
+    pub fn finish(self) -> Result<(), String> {
+        let wavs = match self {
+            Recorder::Mixed(wav) => vec![wav],
+            Recorder::Channels(wavs) => wavs,
+        };
+        wavs.into_iter()
+            .try_for_each(|wav| wav.finish().map(|_| ()))
+    }
+}
+
+/// Create the WAV file *path* of *channels* channels sampled at *sample_rate* Hz
+fn create(
+    path: &Path,
+    channels: u16,
+    sample_rate: u32,
+) -> Result<WavWriter<BufWriter<File>>, String> {
+    let file = File::create(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    WavWriter::new(BufWriter::new(file), channels, sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wav() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 2, 48000).unwrap();
+        wav.write(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), HEADER_SIZE as usize + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes[4..8], 44u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(bytes[22..24], 2u16.to_le_bytes());
+        assert_eq!(bytes[24..28], 48000u32.to_le_bytes());
+        assert_eq!(bytes[28..32], 192000u32.to_le_bytes());
+        assert_eq!(bytes[32..34], 4u16.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(bytes[40..44], 8u32.to_le_bytes());
+        assert_eq!(
+            bytes[44..],
+            [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//! Audio processing unit.
+//!
+//! Mixes four sound channels into a stereo output:
+//! - channels 1 and 2 play square waves, with a volume envelope, see [pulse]. Channel 1 also
+//!   sweeps its frequency, see [sweep].
+//! - channel 3 plays the samples of the wave RAM, see [wave].
+//! - channel 4 plays noise from a linear feedback shift register, see [noise].
+//!
+//! Its registers are:
+//!
+//! | Range           | Registers                                                         |
+//! |-----------------|-------------------------------------------------------------------|
+//! | `0xFF10-0xFF14` | NR10-NR14, channel 1                                              |
+//! | `0xFF16-0xFF19` | NR21-NR24, channel 2                                              |
+//! | `0xFF1A-0xFF1E` | NR30-NR34, channel 3                                              |
+//! | `0xFF20-0xFF23` | NR41-NR44, channel 4                                              |
+//! | `0xFF24`        | NR50, bits 4-6: left volume, bits 0-2: right volume               |
+//! | `0xFF25`        | NR51, bits 4-7: channels on the left, bits 0-3: on the right      |
+//! | `0xFF26`        | NR52, bit 7: power, bits 0-3: channels playing, read only         |
+//! | `0xFF30-0xFF3F` | Wave RAM                                                          |
+//!
+//! The write only bits of the registers read as 1. Powering the APU off through NR52 clears
+//! every register but the wave RAM, which stays accessible, and ignores writes until it is
+//! powered on again.
+//!
+//! The frame sequencer clocks the channel units at 512 Hz, in 8 steps:
+//!
+//! | Step | Length counters (256 Hz) | Sweep (128 Hz) | Volume envelopes (64 Hz) |
+//! |------|--------------------------|----------------|--------------------------|
+//! | 0    | Clocked                  |                |                          |
+//! | 2    | Clocked                  | Clocked        |                          |
+//! | 4    | Clocked                  |                |                          |
+//! | 6    | Clocked                  | Clocked        |                          |
+//! | 7    |                          |                | Clocked                  |
+//!
+//! Each channel outputs a digital value from 0 to 15, which its DAC converts into an analog
+//! value from -1 to 1 while it is powered. The output is sampled at [SAMPLE_RATE] by default,
+//! and goes through a high-pass filter removing the DC offset, as the capacitors of the
+//! hardware do. The samples are interleaved left and right, and collected with
+//! [Apu::take_samples].
+//!
+//! For debugging and recording, each channel can be muted in the mix, and the output of each
+//! channel can also be captured on its own, before panning and volume, with
+//! [Apu::take_channel_samples].
+//!
+
+mod envelope;
+mod length;
+mod noise;
+mod pulse;
+mod sweep;
+mod wave;
+
+use self::noise::Noise;
+use self::pulse::Pulse;
+use self::wave::Wave;
+use crate::gameboy::CYCLES_PER_SECOND;
+
+use std::mem;
+
+/// The default output sample rate, in Hz.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Number of sound channels.
+pub const CHANNELS: usize = 4;
+
+/// Address of the first register, NR10.
+const NR10: u16 = 0xFF10;
+
+/// Address of the volume register.
+const NR50: u16 = 0xFF24;
+
+/// Address of the panning register.
+const NR51: u16 = 0xFF25;
+
+/// Address of the power and status register.
+const NR52: u16 = 0xFF26;
+
+/// Address of the wave RAM.
+const WAVE_RAM: u16 = 0xFF30;
+
+/// NR52: the power of the APU.
+const NR52_POWER: u8 = 0b1000_0000;
+
+/// Number of registers from NR10 to NR51.
+const REGISTERS_SIZE: usize = 0x16;
+
+/// The bits of each register from NR10 to NR51 that read as 1.
+const READ_MASKS: [u8; REGISTERS_SIZE] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, // NR50-NR51
+];
+
+/// T-cycles between two steps of the frame sequencer, at 512 Hz.
+const SEQUENCER_PERIOD: u32 = CYCLES_PER_SECOND / 512;
+
+/// Charge kept by the high-pass filter capacitor after one T-cycle.
+const CAPACITOR_CHARGE: f32 = 0.999958;
+
+pub struct Apu {
+    /// Set while the APU is powered.
+    power: bool,
+
+    /// The registers from NR10 to NR51, as written.
+    registers: [u8; REGISTERS_SIZE],
+
+    /// Channel 1.
+    pulse1: Pulse,
+
+    /// Channel 2.
+    pulse2: Pulse,
+
+    /// Channel 3.
+    wave: Wave,
+
+    /// Channel 4.
+    noise: Noise,
+
+    /// T-cycles since the last step of the frame sequencer.
+    sequencer_cycles: u32,
+
+    /// The next step of the frame sequencer, from 0 to 7.
+    sequencer_step: u8,
+
+    /// The output sample rate, in Hz.
+    sample_rate: u32,
+
+    /// Accumulates the sample rate every T-cycle, a sample is due whenever it reaches a second.
+    sample_clock: u32,
+
+    /// Charge kept by the high-pass filter capacitors after one sample.
+    charge: f32,
+
+    /// The left and right high-pass filter capacitors.
+    capacitors: [f32; 2],
+
+    /// The samples produced since the last call to [Apu::take_samples].
+    samples: Vec<f32>,
+
+    /// The channels left out of the mix, from channel 1.
+    muted: [bool; CHANNELS],
+
+    /// Set while the output of each channel is captured on its own.
+    capture: bool,
+
+    /// The high-pass filter capacitors of the captured channels.
+    channel_capacitors: [f32; CHANNELS],
+
+    /// The samples of each channel captured since the last call to
+    /// [Apu::take_channel_samples].
+    channel_samples: [Vec<f32>; CHANNELS],
+}
+
+impl Apu {
+    /// Constructs a powered off APU, sampling at [SAMPLE_RATE].
+    pub fn new() -> Self {
+        let mut apu = Self {
+            power: false,
+            registers: [0; REGISTERS_SIZE],
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            sequencer_cycles: 0,
+            sequencer_step: 0,
+            sample_rate: 0,
+            sample_clock: 0,
+            charge: 0.0,
+            capacitors: [0.0; 2],
+            samples: Vec::new(),
+            muted: [false; CHANNELS],
+            capture: false,
+            channel_capacitors: [0.0; CHANNELS],
+            channel_samples: Default::default(),
+        };
+        apu.set_sample_rate(SAMPLE_RATE);
+        apu
+    }
+
+    /// Sets the output sample rate to *sample_rate* Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.charge = CAPACITOR_CHARGE.powf(CYCLES_PER_SECOND as f32 / sample_rate as f32);
+    }
+
+    /// Returns the interleaved left and right samples produced since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        mem::take(&mut self.samples)
+    }
+
+    /// Returns true if *channel*, from 0 for channel 1, is left out of the mix.
+    pub fn muted(&self, channel: usize) -> bool {
+        self.muted[channel]
+    }
+
+    /// Leaves *channel*, from 0 for channel 1, out of the mix if *muted* is set.
+    pub fn set_muted(&mut self, channel: usize, muted: bool) {
+        self.muted[channel] = muted;
+    }
+
+    /// Starts or stops capturing the output of each channel on its own.
+    pub fn set_capture(&mut self, capture: bool) {
+        self.capture = capture;
+        if !capture {
+            self.channel_samples = Default::default();
+        }
+    }
+
+    /// Returns the mono samples of each channel captured since the last call, at half scale to
+    /// leave headroom.
+    pub fn take_channel_samples(&mut self) -> [Vec<f32>; CHANNELS] {
+        mem::take(&mut self.channel_samples)
+    }
+
+    /// Reads the register at *address*, in the range `0xFF10-0xFF3F`.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            NR10..=NR51 => {
+                let index = (address - NR10) as usize;
+                self.registers[index] | READ_MASKS[index]
+            }
+            NR52 => self.read_status(),
+            WAVE_RAM.. => self.wave.read_ram((address - WAVE_RAM) as usize),
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes *value* to the register at *address*, in the range `0xFF10-0xFF3F`.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            NR52 => self.write_power(value & NR52_POWER != 0),
+            WAVE_RAM.. => self.wave.write_ram((address - WAVE_RAM) as usize, value),
+            _ if !self.power => (),
+            NR10..=NR51 => {
+                self.registers[(address - NR10) as usize] = value;
+                match address {
+                    0xFF10..=0xFF14 => self.pulse1.write(address - 0xFF10, value),
+                    0xFF15..=0xFF19 => self.pulse2.write(address - 0xFF15, value),
+                    0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value),
+                    0xFF1F..=0xFF23 => self.noise.write(address - 0xFF1F, value),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Reads NR52: the power, and which channels are playing.
+    fn read_status(&self) -> u8 {
+        let playing = [
+            self.pulse1.enabled(),
+            self.pulse2.enabled(),
+            self.wave.enabled(),
+            self.noise.enabled(),
+        ];
+        let power = match self.power {
+            true => NR52_POWER,
+            false => 0,
+        };
+        let status = playing
+            .iter()
+            .enumerate()
+            .fold(0, |status, (channel, &enabled)| {
+                status | (enabled as u8) << channel
+            });
+        power | 0b0111_0000 | status
+    }
+
+    /// Powers the APU on or off.
+    /// Powering off clears every register but the wave RAM, powering on restarts the frame
+    /// sequencer.
+    fn write_power(&mut self, power: bool) {
+        match (self.power, power) {
+            (true, false) => {
+                self.registers = [0; REGISTERS_SIZE];
+                self.pulse1 = Pulse::new(true);
+                self.pulse2 = Pulse::new(false);
+                self.wave.power_off();
+                self.noise = Noise::new();
+            }
+            (false, true) => {
+                self.sequencer_cycles = 0;
+                self.sequencer_step = 0;
+            }
+            _ => (),
+        }
+        self.power = power;
+    }
+
+    /// Advances the APU by *cycles* T-cycles, producing the samples due meanwhile.
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            if self.power {
+                self.sequencer_cycles += 1;
+                if self.sequencer_cycles == SEQUENCER_PERIOD {
+                    self.sequencer_cycles = 0;
+                    self.step_sequencer();
+                }
+                self.pulse1.step();
+                self.pulse2.step();
+                self.wave.step();
+                self.noise.step();
+            }
+
+            self.sample_clock += self.sample_rate;
+            if self.sample_clock >= CYCLES_PER_SECOND {
+                self.sample_clock -= CYCLES_PER_SECOND;
+                self.push_sample();
+            }
+        }
+    }
+
+    /// Runs the next step of the frame sequencer.
+    fn step_sequencer(&mut self) {
+        match self.sequencer_step {
+            0 | 4 => self.clock_lengths(),
+            2 | 6 => {
+                self.clock_lengths();
+                self.pulse1.clock_sweep();
+            }
+            7 => {
+                self.pulse1.clock_envelope();
+                self.pulse2.clock_envelope();
+                self.noise.clock_envelope();
+            }
+            _ => (),
+        }
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    /// Clocks the length counters of every channel.
+    fn clock_lengths(&mut self) {
+        self.pulse1.clock_length();
+        self.pulse2.clock_length();
+        self.wave.clock_length();
+        self.noise.clock_length();
+    }
+
+    /// Mixes the channels into the next left and right samples.
+    fn push_sample(&mut self) {
+        let dac = |enabled: bool, output: u8| match enabled {
+            true => output as f32 / 7.5 - 1.0,
+            false => 0.0,
+        };
+        let channels = [
+            dac(self.pulse1.dac_enabled(), self.pulse1.output()),
+            dac(self.pulse2.dac_enabled(), self.pulse2.output()),
+            dac(self.wave.dac_enabled(), self.wave.output()),
+            dac(self.noise.dac_enabled(), self.noise.output()),
+        ];
+
+        if self.capture {
+            for (channel, &output) in channels.iter().enumerate() {
+                let capacitor = &mut self.channel_capacitors[channel];
+                let sample = high_pass(capacitor, output / 2.0, self.charge);
+                self.channel_samples[channel].push(sample);
+            }
+        }
+
+        let nr50 = self.registers[(NR50 - NR10) as usize];
+        let nr51 = self.registers[(NR51 - NR10) as usize];
+        for (side, shift) in [4, 0].into_iter().enumerate() {
+            let mix: f32 = channels
+                .iter()
+                .enumerate()
+                .filter(|&(channel, _)| !self.muted[channel])
+                .filter(|(channel, _)| nr51 >> shift >> channel & 1 != 0)
+                .map(|(_, output)| output)
+                .sum();
+            let volume = ((nr50 >> shift) & 0b0000_0111) as f32 + 1.0;
+            let input = mix / 4.0 * volume / 8.0;
+            let sample = high_pass(&mut self.capacitors[side], input, self.charge);
+            self.samples.push(sample);
+        }
+    }
+}
+
+/// Filters *input* through a high-pass filter *capacitor* keeping *charge* between samples.
+/// Returns the filtered sample.
+fn high_pass(capacitor: &mut f32, input: f32, charge: f32) -> f32 {
+    let output = input - *capacitor;
+    *capacitor = input - output * charge;
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Constructs a powered APU with channel 1 playing a 50% square wave at full volume on both
+    /// sides.
+    fn playing_apu() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(NR52, NR52_POWER);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0x11);
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x87);
+        apu
+    }
+
+    #[test]
+    fn test_read_masks() {
+        let mut apu = Apu::new();
+
+        assert_eq!(apu.read(NR52), 0x70);
+        assert_eq!(apu.read(0xFF10), 0x80);
+        assert_eq!(apu.read(0xFF15), 0xFF);
+        assert_eq!(apu.read(0xFF27), 0xFF);
+
+        apu.write(NR52, NR52_POWER);
+        apu.write(0xFF11, 0xBF);
+        apu.write(0xFF12, 0x53);
+        apu.write(0xFF13, 0x12);
+        apu.write(NR51, 0xA5);
+        assert_eq!(apu.read(0xFF11), 0xBF);
+        assert_eq!(apu.read(0xFF12), 0x53);
+        assert_eq!(apu.read(0xFF13), 0xFF);
+        assert_eq!(apu.read(NR51), 0xA5);
+    }
+
+    #[test]
+    fn test_power() {
+        let mut apu = playing_apu();
+        apu.write(WAVE_RAM, 0x12);
+        assert_eq!(apu.read(NR52), 0xF1);
+
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(NR52), 0x70);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(NR51), 0x00);
+
+        apu.write(0xFF12, 0xF0);
+        apu.write(WAVE_RAM + 1, 0x34);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(WAVE_RAM), 0x12);
+        assert_eq!(apu.read(WAVE_RAM + 1), 0x34);
+    }
+
+    #[test]
+    fn test_length_sequencer() {
+        let mut apu = playing_apu();
+        apu.write(0xFF11, 0xBE);
+        apu.write(0xFF14, 0xC7);
+
+        // The length counter is clocked by steps 0 and 2
+        apu.tick(SEQUENCER_PERIOD);
+        assert_eq!(apu.read(NR52), 0xF1);
+        apu.tick(SEQUENCER_PERIOD * 2);
+        assert_eq!(apu.read(NR52), 0xF0);
+    }
+
+    #[test]
+    fn test_samples() {
+        let mut apu = playing_apu();
+
+        apu.tick(CYCLES_PER_SECOND / 64);
+        let samples = apu.take_samples();
+        assert_eq!(samples.len(), SAMPLE_RATE as usize / 64 * 2);
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        assert!(samples.iter().any(|&sample| sample > 0.1));
+        assert!(samples.iter().any(|&sample| sample < -0.1));
+        assert!(apu.take_samples().is_empty());
+
+        apu.set_sample_rate(44_100);
+        apu.tick(CYCLES_PER_SECOND / 64);
+        assert_eq!(apu.take_samples().len(), 689 * 2);
+    }
+
+    #[test]
+    fn test_panning() {
+        let mut apu = playing_apu();
+        apu.write(NR51, 0x01);
+
+        apu.tick(CYCLES_PER_SECOND / 100);
+        let samples = apu.take_samples();
+        assert!(samples.chunks(2).all(|frame| frame[0] == 0.0));
+        assert!(samples.chunks(2).any(|frame| frame[1] != 0.0));
+    }
+
+    #[test]
+    fn test_mute() {
+        let mut apu = playing_apu();
+        apu.set_muted(0, true);
+        assert!(apu.muted(0));
+
+        apu.tick(CYCLES_PER_SECOND / 100);
+        assert!(apu.take_samples().iter().all(|&sample| sample == 0.0));
+        assert_eq!(apu.read(NR52), 0xF1);
+    }
+
+    #[test]
+    fn test_capture() {
+        let mut apu = playing_apu();
+        apu.set_muted(0, true);
+
+        apu.tick(CYCLES_PER_SECOND / 100);
+        assert_eq!(
+            apu.take_channel_samples(),
+            <[Vec<f32>; CHANNELS]>::default()
+        );
+
+        apu.set_capture(true);
+        apu.tick(CYCLES_PER_SECOND / 64);
+        let channels = apu.take_channel_samples();
+        assert_eq!(channels[0].len(), SAMPLE_RATE as usize / 64);
+        assert!(channels[0].iter().any(|&sample| sample > 0.1));
+        assert!(channels[1].iter().all(|&sample| sample == 0.0));
+    }
+}
@@ -0,0 +1,107 @@
+//! Volume envelope.
+//!
+//! Configured by NRx2 for the pulse and noise channels:
+//!
+//! | Bits | Content                                              |
+//! |------|------------------------------------------------------|
+//! | 4-7  | Initial volume                                       |
+//! | 3    | Direction, increase if set                           |
+//! | 0-2  | Period, in 64 Hz steps, 0 freezes the volume         |
+//!
+//! The channel DAC is powered as long as bits 3-7 are not all cleared.
+//!
+
+#[derive(Default)]
+pub struct Envelope {
+    /// The volume loaded on trigger.
+    initial: u8,
+
+    /// Set if the volume increases, otherwise it decreases.
+    increase: bool,
+
+    /// The number of 64 Hz steps between volume changes.
+    period: u8,
+
+    /// The current volume, from 0 to 15.
+    volume: u8,
+
+    /// Steps left before the next volume change.
+    timer: u8,
+}
+
+impl Envelope {
+    /// Constructs an envelope with the DAC off.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes *value* to NRx2.
+    pub fn write(&mut self, value: u8) {
+        self.initial = value >> 4;
+        self.increase = value & 0b0000_1000 != 0;
+        self.period = value & 0b0000_0111;
+    }
+
+    /// Returns true if the channel DAC is powered.
+    pub fn dac_enabled(&self) -> bool {
+        self.initial != 0 || self.increase
+    }
+
+    /// Restarts the envelope from the initial volume, when the channel is triggered.
+    pub fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+
+    /// Clocks the envelope at 64 Hz.
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period;
+        match self.increase {
+            true if self.volume < 15 => self.volume += 1,
+            false if self.volume > 0 => self.volume -= 1,
+            _ => (),
+        }
+    }
+
+    /// Returns the current volume, from 0 to 15.
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope() {
+        let mut envelope = Envelope::new();
+        assert!(!envelope.dac_enabled());
+
+        envelope.write(0xE2);
+        envelope.trigger();
+        assert!(envelope.dac_enabled());
+        assert_eq!(envelope.volume(), 14);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 14);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 13);
+
+        envelope.write(0xFB);
+        envelope.trigger();
+        for _ in 0..6 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 15);
+
+        envelope.write(0x08);
+        assert!(envelope.dac_enabled());
+    }
+}
@@ -0,0 +1,78 @@
+//! Length counter.
+//!
+//! Silences its channel once the length loaded in NRx1 has elapsed, if enabled by bit 6 of NRx4.
+//! The counter is clocked at 256 Hz by the frame sequencer.
+//!
+
+#[derive(Default)]
+pub struct LengthCounter {
+    /// The length of the channel, 64 or 256 steps.
+    max: u16,
+
+    /// Steps left before the channel is silenced.
+    counter: u16,
+
+    /// Set when the length of the channel is limited.
+    enabled: bool,
+}
+
+impl LengthCounter {
+    /// Constructs a counter of channel length *max*.
+    pub fn new(max: u16) -> Self {
+        Self {
+            max,
+            ..Self::default()
+        }
+    }
+
+    /// Loads the counter from the length *value* written to NRx1.
+    pub fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16;
+    }
+
+    /// Enables or disables the counter, from bit 6 of NRx4.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Reloads the counter with the full length if it has expired, when the channel is
+    /// triggered.
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Clocks the counter.
+    /// Returns true when it expires, silencing the channel.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length() {
+        let mut length = LengthCounter::new(64);
+
+        length.load(62);
+        assert!(!length.clock());
+        length.set_enabled(true);
+        assert!(!length.clock());
+        assert!(length.clock());
+        assert!(!length.clock());
+
+        length.trigger();
+        for _ in 0..63 {
+            assert!(!length.clock());
+        }
+        assert!(length.clock());
+    }
+}
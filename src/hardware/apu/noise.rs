@@ -0,0 +1,208 @@
+//! Noise channel 4.
+//!
+//! Outputs the lowest bit, inverted, of a linear feedback shift register clocked at a
+//! selectable rate. Its registers are:
+//!
+//! | Register  | Content                                                              |
+//! |-----------|----------------------------------------------------------------------|
+//! | NR41      | Bits 0-5: length                                                     |
+//! | NR42      | Volume envelope                                                      |
+//! | NR43      | Bits 4-7: clock shift, bit 3: 7-bit width, bits 0-2: divisor code    |
+//! | NR44      | Bit 7: trigger, bit 6: length enable                                 |
+//!
+//! The register is shifted every `divisor << shift` T-cycles, where the divisor is 8 for code 0
+//! and `code * 16` otherwise. With the 7-bit width, the feedback bit is also copied to bit 6,
+//! for a shorter and more metallic sequence.
+//!
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+use super::pulse::{NRX4_LENGTH, NRX4_TRIGGER};
+
+/// NR43: the 7-bit width.
+const NR43_WIDTH: u8 = 0b0000_1000;
+
+pub struct Noise {
+    /// The length counter, 64 steps.
+    length: LengthCounter,
+
+    /// The volume envelope.
+    envelope: Envelope,
+
+    /// The clock shift.
+    shift: u8,
+
+    /// Set for the 7-bit width.
+    short: bool,
+
+    /// The divisor code.
+    divisor: u8,
+
+    /// T-cycles left before the next shift.
+    timer: u32,
+
+    /// The 15-bit linear feedback shift register.
+    lfsr: u16,
+
+    /// Set while the channel is playing.
+    enabled: bool,
+}
+
+impl Noise {
+    /// Constructs a silent noise channel.
+    pub fn new() -> Self {
+        Self {
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            shift: 0,
+            short: false,
+            divisor: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            enabled: false,
+        }
+    }
+
+    /// Writes *value* to the register NR4*register*.
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.length.load(value & 0b0011_1111),
+            2 => {
+                self.envelope.write(value);
+                self.enabled &= self.envelope.dac_enabled();
+            }
+            3 => {
+                self.shift = value >> 4;
+                self.short = value & NR43_WIDTH != 0;
+                self.divisor = value & 0b0000_0111;
+            }
+            4 => {
+                self.length.set_enabled(value & NRX4_LENGTH != 0);
+                if value & NRX4_TRIGGER != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Restarts the channel with every bit of the shift register set.
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+    }
+
+    /// Returns the number of T-cycles between two shifts.
+    fn period(&self) -> u32 {
+        let divisor = match self.divisor {
+            0 => 8,
+            code => code as u32 * 16,
+        };
+        divisor << self.shift
+    }
+
+    /// Advances the shift register by one T-cycle.
+    pub fn step(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period();
+
+        let feedback = (self.lfsr ^ self.lfsr >> 1) & 1;
+        self.lfsr = self.lfsr >> 1 | feedback << 14;
+        if self.short {
+            self.lfsr = (self.lfsr & !0x0040) | feedback << 6;
+        }
+    }
+
+    /// Clocks the length counter at 256 Hz.
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Clocks the volume envelope at 64 Hz.
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Returns true while the channel is playing.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns true if the channel DAC is powered.
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Returns the digital output of the channel, from 0 to 15.
+    pub fn output(&self) -> u8 {
+        match self.enabled && self.lfsr & 1 == 0 {
+            true => self.envelope.volume(),
+            false => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the period of the sequence of the noise channel configured by *nr43*.
+    fn sequence_length(nr43: u8) -> usize {
+        let mut noise = Noise::new();
+        noise.write(2, 0xF0);
+        noise.write(3, nr43);
+        noise.write(4, NRX4_TRIGGER);
+
+        let period = noise.period();
+        let shift = |noise: &mut Noise| {
+            for _ in 0..period {
+                noise.step();
+            }
+        };
+        // Leave the initial state, which the 7-bit sequence never comes back to
+        for _ in 0..100 {
+            shift(&mut noise);
+        }
+        let start = noise.lfsr;
+        (1..)
+            .find(|_| {
+                shift(&mut noise);
+                noise.lfsr == start
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_lfsr() {
+        let mut noise = Noise::new();
+
+        noise.write(2, 0xA0);
+        noise.write(3, 0x00);
+        noise.write(4, NRX4_TRIGGER);
+        assert_eq!(noise.output(), 0);
+        for _ in 0..8 * 14 {
+            noise.step();
+        }
+        assert_eq!(noise.lfsr, 0x0001);
+        assert_eq!(noise.output(), 0);
+        for _ in 0..8 {
+            noise.step();
+        }
+        assert_eq!(noise.lfsr, 0x4000);
+        assert_eq!(noise.output(), 0xA);
+    }
+
+    #[test]
+    fn test_width() {
+        assert_eq!(sequence_length(0x00), 0x7FFF);
+        assert_eq!(sequence_length(NR43_WIDTH), 0x7F);
+    }
+}
@@ -0,0 +1,217 @@
+//! Pulse channels 1 and 2.
+//!
+//! A square wave with a selectable duty cycle and a volume envelope. Only channel 1 has a
+//! frequency sweep. Its registers are:
+//!
+//! | Register  | Content                                                              |
+//! |-----------|----------------------------------------------------------------------|
+//! | NR10      | Sweep, channel 1 only                                                |
+//! | NRx1      | Bits 6-7: duty cycle, bits 0-5: length                               |
+//! | NRx2      | Volume envelope                                                      |
+//! | NRx3      | Frequency, lower 8 bits                                              |
+//! | NRx4      | Bit 7: trigger, bit 6: length enable, bits 0-2: frequency, upper 3   |
+//!
+//! The 8 steps of the wave each last `(2048 - frequency) * 4` T-cycles.
+//!
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+use super::sweep::Sweep;
+
+/// The waveforms of the 12.5%, 25%, 50% and 75% duty cycles, from step 0 in bit 0.
+const DUTY_WAVEFORMS: [u8; 4] = [0b1000_0000, 0b1000_0001, 0b1110_0001, 0b0111_1110];
+
+/// NRx4: restarts the channel.
+pub const NRX4_TRIGGER: u8 = 0b1000_0000;
+
+/// NRx4: enables the length counter.
+pub const NRX4_LENGTH: u8 = 0b0100_0000;
+
+pub struct Pulse {
+    /// The frequency sweep, for channel 1.
+    sweep: Option<Sweep>,
+
+    /// The length counter, 64 steps.
+    length: LengthCounter,
+
+    /// The volume envelope.
+    envelope: Envelope,
+
+    /// The duty cycle, index in `DUTY_WAVEFORMS`.
+    duty: u8,
+
+    /// The 11-bit frequency.
+    frequency: u16,
+
+    /// T-cycles left in the current step of the wave.
+    timer: u16,
+
+    /// The current step of the wave, from 0 to 7.
+    step: u8,
+
+    /// Set while the channel is playing.
+    enabled: bool,
+}
+
+impl Pulse {
+    /// Constructs a silent pulse channel, with a frequency sweep if *sweep* is set.
+    pub fn new(sweep: bool) -> Self {
+        Self {
+            sweep: sweep.then(Sweep::new),
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            duty: 0,
+            frequency: 0,
+            timer: 0,
+            step: 0,
+            enabled: false,
+        }
+    }
+
+    /// Writes *value* to the register NRx*register*.
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.write(value);
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0b0011_1111);
+            }
+            2 => {
+                self.envelope.write(value);
+                self.enabled &= self.envelope.dac_enabled();
+            }
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            4 => {
+                self.frequency = ((value & 0b0000_0111) as u16) << 8 | (self.frequency & 0x00FF);
+                self.length.set_enabled(value & NRX4_LENGTH != 0);
+                if value & NRX4_TRIGGER != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Restarts the channel.
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        if let Some(sweep) = &mut self.sweep {
+            self.enabled &= sweep.trigger(self.frequency);
+        }
+    }
+
+    /// Returns the length of a step of the wave in T-cycles.
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    /// Advances the wave by one T-cycle.
+    pub fn step(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.step = (self.step + 1) % 8;
+        }
+    }
+
+    /// Clocks the length counter at 256 Hz.
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Clocks the volume envelope at 64 Hz.
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocks the frequency sweep at 128 Hz.
+    pub fn clock_sweep(&mut self) {
+        if let Some(sweep) = &mut self.sweep {
+            if !sweep.clock(&mut self.frequency) {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Returns true while the channel is playing.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns true if the channel DAC is powered.
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Returns the digital output of the channel, from 0 to 15.
+    pub fn output(&self) -> u8 {
+        let high = DUTY_WAVEFORMS[self.duty as usize] >> self.step & 1 != 0;
+        match self.enabled && high {
+            true => self.envelope.volume(),
+            false => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duty() {
+        let mut pulse = Pulse::new(false);
+
+        // 50% duty, volume 15, period of 4 T-cycles
+        pulse.write(1, 0x80);
+        pulse.write(2, 0xF0);
+        pulse.write(3, 0xFF);
+        pulse.write(4, NRX4_TRIGGER | 0x07);
+        assert!(pulse.enabled());
+
+        let mut wave = Vec::new();
+        for _ in 0..8 {
+            for _ in 0..4 {
+                pulse.step();
+            }
+            wave.push(pulse.output());
+        }
+        assert_eq!(wave, [0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn test_dac_off() {
+        let mut pulse = Pulse::new(true);
+
+        pulse.write(2, 0x00);
+        pulse.write(4, NRX4_TRIGGER);
+        assert!(!pulse.enabled());
+
+        pulse.write(2, 0x10);
+        pulse.write(4, NRX4_TRIGGER);
+        assert!(pulse.enabled());
+        pulse.write(2, 0x00);
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn test_length() {
+        let mut pulse = Pulse::new(false);
+
+        pulse.write(1, 0x3E);
+        pulse.write(2, 0xF0);
+        pulse.write(4, NRX4_TRIGGER | NRX4_LENGTH);
+        pulse.clock_length();
+        assert!(pulse.enabled());
+        pulse.clock_length();
+        assert!(!pulse.enabled());
+    }
+}
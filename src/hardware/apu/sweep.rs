@@ -0,0 +1,142 @@
+//! Frequency sweep of the first pulse channel.
+//!
+//! Configured by NR10:
+//!
+//! | Bits | Content                                              |
+//! |------|------------------------------------------------------|
+//! | 4-6  | Period, in 128 Hz steps, 0 stops the sweep           |
+//! | 3    | Direction, decrease if set                           |
+//! | 0-2  | Shift                                                |
+//!
+//! Every period, the frequency moves by itself shifted right by the shift. Whenever the computed
+//! frequency overflows 2047, the channel is silenced.
+//!
+
+/// The largest 11-bit frequency.
+const MAX_FREQUENCY: u16 = 2047;
+
+#[derive(Default)]
+pub struct Sweep {
+    /// The number of 128 Hz steps between frequency changes.
+    period: u8,
+
+    /// Set if the frequency decreases, otherwise it increases.
+    negate: bool,
+
+    /// The shift applied to the frequency to compute its change.
+    shift: u8,
+
+    /// Steps left before the next frequency change.
+    timer: u8,
+
+    /// The frequency the sweep works on.
+    shadow: u16,
+
+    /// Set when the sweep is running.
+    enabled: bool,
+}
+
+impl Sweep {
+    /// Constructs a stopped sweep.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes *value* to NR10.
+    pub fn write(&mut self, value: u8) {
+        self.period = (value >> 4) & 0b0000_0111;
+        self.negate = value & 0b0000_1000 != 0;
+        self.shift = value & 0b0000_0111;
+    }
+
+    /// Restarts the sweep from *frequency*, when the channel is triggered.
+    /// Returns false if the frequency overflows, silencing the channel.
+    pub fn trigger(&mut self, frequency: u16) -> bool {
+        self.shadow = frequency;
+        self.timer = self.reload();
+        self.enabled = self.period != 0 || self.shift != 0;
+        self.shift == 0 || self.next() <= MAX_FREQUENCY
+    }
+
+    /// Clocks the sweep at 128 Hz, updating *frequency*.
+    /// Returns false if the frequency overflows, silencing the channel.
+    pub fn clock(&mut self, frequency: &mut u16) -> bool {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return true;
+        }
+        self.timer = self.reload();
+        if !self.enabled || self.period == 0 {
+            return true;
+        }
+
+        let next = self.next();
+        if next > MAX_FREQUENCY {
+            return false;
+        }
+        if self.shift != 0 {
+            self.shadow = next;
+            *frequency = next;
+            return self.next() <= MAX_FREQUENCY;
+        }
+        true
+    }
+
+    /// Returns the timer reload value, a period of 0 counting as 8.
+    fn reload(&self) -> u8 {
+        match self.period {
+            0 => 8,
+            period => period,
+        }
+    }
+
+    /// Returns the next frequency.
+    fn next(&self) -> u16 {
+        let change = self.shadow >> self.shift;
+        match self.negate {
+            true => self.shadow - change,
+            false => self.shadow + change,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep() {
+        let mut sweep = Sweep::new();
+        let mut frequency = 0x400;
+
+        sweep.write(0x12);
+        assert!(sweep.trigger(frequency));
+        assert!(sweep.clock(&mut frequency));
+        assert_eq!(frequency, 0x500);
+        assert!(sweep.clock(&mut frequency));
+        assert_eq!(frequency, 0x640);
+        assert!(!sweep.clock(&mut frequency));
+        assert_eq!(frequency, 0x7D0);
+    }
+
+    #[test]
+    fn test_negate() {
+        let mut sweep = Sweep::new();
+        let mut frequency = 0x400;
+
+        sweep.write(0x29);
+        assert!(sweep.trigger(frequency));
+        assert!(sweep.clock(&mut frequency));
+        assert_eq!(frequency, 0x400);
+        assert!(sweep.clock(&mut frequency));
+        assert_eq!(frequency, 0x200);
+    }
+
+    #[test]
+    fn test_trigger_overflow() {
+        let mut sweep = Sweep::new();
+
+        sweep.write(0x01);
+        assert!(!sweep.trigger(0x7FF));
+    }
+}
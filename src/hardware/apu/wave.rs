@@ -0,0 +1,201 @@
+//! Wave channel 3.
+//!
+//! Plays the 32 4-bit samples of the wave RAM at `0xFF30-0xFF3F`, upper nibble first. Its
+//! registers are:
+//!
+//! | Register  | Content                                                              |
+//! |-----------|----------------------------------------------------------------------|
+//! | NR30      | Bit 7: DAC power                                                     |
+//! | NR31      | Length                                                               |
+//! | NR32      | Bits 5-6: volume, mute, 100%, 50% or 25%                             |
+//! | NR33      | Frequency, lower 8 bits                                              |
+//! | NR34      | Bit 7: trigger, bit 6: length enable, bits 0-2: frequency, upper 3   |
+//!
+//! Each sample lasts `(2048 - frequency) * 2` T-cycles.
+//!
+
+use super::length::LengthCounter;
+use super::pulse::{NRX4_LENGTH, NRX4_TRIGGER};
+
+/// Size of the wave RAM, 2 samples per byte.
+pub const WAVE_RAM_SIZE: usize = 16;
+
+/// The right shifts applied to the samples for each NR32 volume.
+const VOLUME_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+
+pub struct Wave {
+    /// The 32 samples.
+    ram: [u8; WAVE_RAM_SIZE],
+
+    /// Set when the DAC is powered.
+    dac: bool,
+
+    /// The length counter, 256 steps.
+    length: LengthCounter,
+
+    /// The volume, index in `VOLUME_SHIFTS`.
+    volume: u8,
+
+    /// The 11-bit frequency.
+    frequency: u16,
+
+    /// T-cycles left in the current sample.
+    timer: u16,
+
+    /// The index of the current sample, from 0 to 31.
+    position: u8,
+
+    /// Set while the channel is playing.
+    enabled: bool,
+}
+
+impl Wave {
+    /// Constructs a silent wave channel.
+    pub fn new() -> Self {
+        Self {
+            ram: [0; WAVE_RAM_SIZE],
+            dac: false,
+            length: LengthCounter::new(256),
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            enabled: false,
+        }
+    }
+
+    /// Resets the channel when the APU is powered off, the wave RAM is kept.
+    pub fn power_off(&mut self) {
+        *self = Self {
+            ram: self.ram,
+            ..Self::new()
+        };
+    }
+
+    /// Reads the wave RAM at *index*.
+    pub fn read_ram(&self, index: usize) -> u8 {
+        self.ram[index]
+    }
+
+    /// Writes *value* to the wave RAM at *index*.
+    pub fn write_ram(&mut self, index: usize, value: u8) {
+        self.ram[index] = value;
+    }
+
+    /// Writes *value* to the register NR3*register*.
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac = value & 0b1000_0000 != 0;
+                self.enabled &= self.dac;
+            }
+            1 => self.length.load(value),
+            2 => self.volume = (value >> 5) & 0b0000_0011,
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            4 => {
+                self.frequency = ((value & 0b0000_0111) as u16) << 8 | (self.frequency & 0x00FF);
+                self.length.set_enabled(value & NRX4_LENGTH != 0);
+                if value & NRX4_TRIGGER != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Restarts the channel from the first sample.
+    fn trigger(&mut self) {
+        self.enabled = self.dac;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    /// Returns the length of a sample in T-cycles.
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    /// Advances the wave by one T-cycle.
+    pub fn step(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    /// Clocks the length counter at 256 Hz.
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Returns true while the channel is playing.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns true if the channel DAC is powered.
+    pub fn dac_enabled(&self) -> bool {
+        self.dac
+    }
+
+    /// Returns the digital output of the channel, from 0 to 15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let byte = self.ram[self.position as usize / 2];
+        let sample = match self.position % 2 {
+            0 => byte >> 4,
+            _ => byte & 0x0F,
+        };
+        sample >> VOLUME_SHIFTS[self.volume as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples() {
+        let mut wave = Wave::new();
+
+        wave.write_ram(0, 0x8F);
+        wave.write_ram(1, 0x4C);
+        wave.write(0, 0x80);
+        wave.write(2, 0x20);
+        wave.write(3, 0xFF);
+        wave.write(4, NRX4_TRIGGER | 0x07);
+        assert!(wave.enabled());
+        assert_eq!(wave.output(), 0x8);
+
+        let mut samples = Vec::new();
+        for _ in 0..3 {
+            wave.step();
+            wave.step();
+            samples.push(wave.output());
+        }
+        assert_eq!(samples, [0xF, 0x4, 0xC]);
+
+        wave.write(2, 0x60);
+        assert_eq!(wave.output(), 0x3);
+        wave.write(0, 0x00);
+        assert!(!wave.enabled());
+        assert_eq!(wave.output(), 0);
+    }
+
+    #[test]
+    fn test_power_off() {
+        let mut wave = Wave::new();
+
+        wave.write_ram(15, 0x12);
+        wave.write(0, 0x80);
+        wave.power_off();
+        assert!(!wave.dac_enabled());
+        assert_eq!(wave.read_ram(15), 0x12);
+    }
+}
@@ -1,3 +1,143 @@
+//! Boot ROM.
+//!
+//! The boot ROM runs at power on, from `0x0000`: it scrolls the Nintendo logo, checks the
+//! cartridge header, then writes to BANK (`0xFF50`) to unmap itself, right before reaching the
+//! entry point of the cartridge at `0x0100`. While mapped, it hides the cartridge ROM:
+//!
+//! | Model | Size   | Mapped at                         |
+//! |-------|--------|-----------------------------------|
+//! | DMG   | 256 B  | `0x0000-0x00FF`                   |
+//! | CGB   | 2304 B | `0x0000-0x00FF`, `0x0200-0x08FF`  |
+//!
+//! The cartridge header stays visible at `0x0100-0x01FF` in both cases. The size of a boot ROM
+//! file gives the model to emulate, and its CRC-32 must match one of the known dumps.
+//!
+//! The DMG compatibility mode, that the CGB boot ROM enables for cartridges without CGB support,
+//! is not emulated: such cartridges run on a DMG without the boot ROM instead.
+//!
+
+use crate::gameboy::Model;
+
+/// Address of the register unmapping the boot ROM.
+pub const BANK: u16 = 0xFF50;
+
+/// Size of the DMG boot ROM.
+const DMG_SIZE: usize = 0x100;
+
+/// Size of the CGB boot ROM, including the unmapped `0x0100-0x01FF` hole.
+const CGB_SIZE: usize = 0x900;
+
+/// The CRC-32 of the known boot ROM dumps, of the DMG, MGB and CGB.
+const KNOWN_DUMPS: [u32; 3] = [0x59C8598E, 0xE6920754, 0x41884E46];
+
+#[derive(Clone)]
 pub struct Bootrom {
-    content: u8,
+    /// The content of the boot ROM, indexed by address.
+    content: Vec<u8>,
+
+    /// The hardware model the boot ROM runs on.
+    model: Model,
+}
+
+impl Bootrom {
+    /// Constructs a boot ROM from the content of a file, its size giving the model.
+    /// Returns an error if the content is not one of the known dumps.
+    pub fn new(content: Vec<u8>) -> Result<Self, String> {
+        let bootrom = Self::unverified(content)?;
+        let crc = crc32(&bootrom.content);
+        if !KNOWN_DUMPS.contains(&crc) {
+            return Err(format!(
+                "Unknown boot ROM with CRC-32 {:08X}, expected a DMG, MGB or CGB dump",
+                crc
+            ));
+        }
+        Ok(bootrom)
+    }
+
+    /// Constructs a boot ROM from the content of a file, its size giving the model, without
+    /// checking that it is a known dump.
+    /// Returns an error if the size is neither the one of a DMG nor of a CGB boot ROM.
+    pub fn unverified(content: Vec<u8>) -> Result<Self, String> {
+        let model = match content.len() {
+            DMG_SIZE => Model::DMG,
+            CGB_SIZE => Model::CGB,
+            size => {
+                return Err(format!(
+                    "Invalid boot ROM size {}, expected {} for a DMG or {} for a CGB",
+                    size, DMG_SIZE, CGB_SIZE
+                ))
+            }
+        };
+        Ok(Self { content, model })
+    }
+
+    /// Returns the hardware model the boot ROM runs on.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Returns true if the boot ROM hides the cartridge at *address* while mapped.
+    pub fn maps(&self, address: u16) -> bool {
+        match address {
+            0x0000..=0x00FF => true,
+            0x0200..=0x08FF => self.model == Model::CGB,
+            _ => false,
+        }
+    }
+
+    /// Reads the 8-bit value at *address*, which must be mapped.
+    pub fn read(&self, address: u16) -> u8 {
+        self.content[address as usize]
+    }
+}
+
+/// Returns the CRC-32 of *data*, as computed by zip and PNG.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => crc >> 1 ^ 0xEDB8_8320,
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model() {
+        let dmg = Bootrom::unverified(vec![0; DMG_SIZE]).unwrap();
+        assert_eq!(dmg.model(), Model::DMG);
+        assert!(dmg.maps(0x00FF));
+        assert!(!dmg.maps(0x0100));
+        assert!(!dmg.maps(0x0200));
+
+        let cgb = Bootrom::unverified(vec![0; CGB_SIZE]).unwrap();
+        assert_eq!(cgb.model(), Model::CGB);
+        assert!(cgb.maps(0x0000));
+        assert!(!cgb.maps(0x01FF));
+        assert!(cgb.maps(0x08FF));
+        assert!(!cgb.maps(0x0900));
+
+        assert_eq!(
+            Bootrom::unverified(vec![0; 0x8000]).err().unwrap(),
+            "Invalid boot ROM size 32768, expected 256 for a DMG or 2304 for a CGB"
+        );
+    }
+
+    #[test]
+    fn test_known_dump() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(
+            Bootrom::new(vec![0; DMG_SIZE]).err().unwrap(),
+            "Unknown boot ROM with CRC-32 0D968558, expected a DMG, MGB or CGB dump"
+        );
+        assert!(Bootrom::new(vec![0; 0x8000]).is_err());
+    }
 }
@@ -1,12 +1,401 @@
+//! Cartridge emulation.
+//!
+//! A cartridge holds the game ROM, an optional external RAM and the memory bank controller
+//! wiring them to the memory bus. The kind of controller is read from the cartridge header, see
+//! [header].
+//!
+//! The cartridge is mapped at two ranges of the memory bus:
+//! - `0x0000-0x7FFF`: the ROM, writes are sent to the memory bank controller registers.
+//! - `0xA000-0xBFFF`: the external RAM.
+//!
+//! When the header declares a battery, the external RAM and the MBC3 clock survive power off.
+//! They are exchanged with the frontend as raw save data: the RAM content followed by the clock
+//! footer, see [rtc].
+//!
+
+use self::header::{Controller, Header, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use self::mbc1::Mbc1;
+use self::mbc2::Mbc2;
+use self::mbc3::Mbc3;
+use self::mbc5::Mbc5;
+use self::rtc::Rtc;
+
+pub mod header;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod rtc;
+
+/// Value returned when reading an address the cartridge does not drive.
+const OPEN_BUS: u8 = 0xFF;
+
 pub struct Cartridge {
-    //header
+    /// The parsed cartridge header.
+    pub header: Header,
+
+    /// The ROM content.
+    rom: Vec<u8>,
+
+    /// The external RAM, empty if the cartridge has none.
+    ram: Vec<u8>,
+
+    /// The memory bank controller.
     ctype: Type,
 }
 
-enum Type {
+#[allow(clippy::upper_case_acronyms)]
+/// Enumerates the supported memory bank controllers.
+pub enum Type {
+    /// The ROM is directly mapped, with an optional RAM.
     ROMONLY,
-    MBC1,
-    MBC2,
-    MBC3,
-    MBC5,
+
+    /// See [mbc1].
+    MBC1(Mbc1),
+
+    /// See [mbc2].
+    MBC2(Mbc2),
+
+    /// See [mbc3].
+    MBC3(Mbc3),
+
+    /// See [mbc5].
+    MBC5(Mbc5),
+}
+
+impl Cartridge {
+    /// Parses the header of *rom* and constructs the matching cartridge.
+    /// Returns an error if the header is corrupt or describes unsupported hardware.
+    pub fn new(rom: Vec<u8>) -> Result<Self, String> {
+        let header = Header::new(&rom)?;
+
+        let ctype = match header.controller {
+            Controller::None => Type::ROMONLY,
+            Controller::MBC1 => Type::MBC1(Mbc1::new(&header, &rom)),
+            Controller::MBC2 => Type::MBC2(Mbc2::new()),
+            Controller::MBC3 => Type::MBC3(Mbc3::new(&header)),
+            Controller::MBC5 => Type::MBC5(Mbc5::new(&header)),
+        };
+
+        // The MBC2 RAM is built into the controller, the header reports no RAM
+        let ram_size = match ctype {
+            Type::MBC2(_) => mbc2::RAM_SIZE,
+            _ => header.ram_size,
+        };
+
+        Ok(Self {
+            ram: vec![0; ram_size],
+            header,
+            rom,
+            ctype,
+        })
+    }
+
+    /// Reads the 8-bit value at *address* in the ROM area `0x0000-0x7FFF`.
+    pub fn read_rom(&self, address: u16) -> u8 {
+        let bank = match &self.ctype {
+            Type::MBC1(mbc1) => mbc1.rom_bank(address),
+            Type::MBC2(mbc2) => mbc2.rom_bank(address),
+            Type::MBC3(mbc3) => mbc3.rom_bank(address),
+            Type::MBC5(mbc5) => mbc5.rom_bank(address),
+            _ => (address as usize) / ROM_BANK_SIZE,
+        };
+        self.read_rom_bank(bank, address)
+    }
+
+    /// Handles a write at *address* in the ROM area `0x0000-0x7FFF`.
+    /// The ROM itself is never modified.
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match &mut self.ctype {
+            Type::ROMONLY => (),
+            Type::MBC1(mbc1) => mbc1.write(address, value),
+            Type::MBC2(mbc2) => mbc2.write(address, value),
+            Type::MBC3(mbc3) => mbc3.write(address, value),
+            Type::MBC5(mbc5) => mbc5.write(address, value),
+        }
+    }
+
+    /// Reads the 8-bit value at *address* in the external RAM area `0xA000-0xBFFF`.
+    /// Returns `0xFF` if the cartridge has no RAM or if the RAM is disabled.
+    /// When an MBC3 clock register is selected, its latched value is returned instead.
+    /// The MBC2 RAM only stores the lower nibble, the upper nibble reads as open bus.
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if let Type::MBC3(mbc3) = &self.ctype {
+            if let Some(value) = mbc3.read_rtc() {
+                return value;
+            }
+        }
+        match (self.ram_offset(address), &self.ctype) {
+            (Some(offset), Type::MBC2(_)) => self.ram[offset] | 0xF0,
+            (Some(offset), _) => self.ram[offset],
+            (None, _) => OPEN_BUS,
+        }
+    }
+
+    /// Writes the 8-bit *value* at *address* in the external RAM area `0xA000-0xBFFF`.
+    /// The write is ignored if the cartridge has no RAM or if the RAM is disabled.
+    /// When an MBC3 clock register is selected, *value* is written to the clock instead.
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if let Type::MBC3(mbc3) = &mut self.ctype {
+            if mbc3.write_rtc(value) {
+                return;
+            }
+        }
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = match self.ctype {
+                Type::MBC2(_) => value & 0x0F,
+                _ => value,
+            };
+        }
+    }
+
+    /// Returns the offset in the external RAM mapped at *address*, or None if the RAM is not
+    /// accessible.
+    /// The offset wraps around the RAM size, so that RAM smaller than a bank is mirrored.
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        let bank = match &self.ctype {
+            Type::MBC1(mbc1) if !mbc1.ram_enabled() => return None,
+            Type::MBC1(mbc1) => mbc1.ram_bank(),
+            Type::MBC2(mbc2) if !mbc2.ram_enabled() => return None,
+            Type::MBC3(mbc3) if !mbc3.ram_enabled() => return None,
+            Type::MBC3(mbc3) => mbc3.ram_bank(),
+            Type::MBC5(mbc5) if !mbc5.ram_enabled() => return None,
+            Type::MBC5(mbc5) => mbc5.ram_bank(),
+            _ => 0,
+        };
+        match self.ram.len() {
+            0 => None,
+            size => Some((bank * RAM_BANK_SIZE + (address - 0xA000) as usize) % size),
+        }
+    }
+
+    /// Advances the clocks embedded in the cartridge by *cycles* T-cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        if let Type::MBC3(mbc3) = &mut self.ctype {
+            mbc3.tick(cycles);
+        }
+    }
+
+    /// Returns true if the external RAM, or the clock, is kept alive by a battery.
+    pub fn battery(&self) -> bool {
+        self.header.battery
+    }
+
+    /// Returns the battery-backed state as save data, with the clock stamped with the UNIX time
+    /// *now*.
+    pub fn save(&self, now: u64) -> Vec<u8> {
+        let mut save = self.ram.clone();
+        if let Type::MBC3(Mbc3 { rtc: Some(rtc), .. }) = &self.ctype {
+            save.extend(rtc.save(now));
+        }
+        save
+    }
+
+    /// Restores the battery-backed state from *save* data.
+    /// The clock is advanced by the time elapsed between the save and the UNIX time *now*.
+    /// Returns an error if *save* does not match the cartridge hardware.
+    pub fn load(&mut self, save: &[u8], now: u64) -> Result<(), String> {
+        if save.len() < self.ram.len() {
+            return Err(format!(
+                "Save is too small: {} bytes, expected {} bytes of RAM",
+                save.len(),
+                self.ram.len()
+            ));
+        }
+        let (ram, footer) = save.split_at(self.ram.len());
+
+        match &mut self.ctype {
+            Type::MBC3(Mbc3 { rtc: Some(rtc), .. }) if !footer.is_empty() => {
+                *rtc = Rtc::load(footer, now)?
+            }
+            _ if !footer.is_empty() => {
+                return Err(format!(
+                    "Save is too large: {} bytes, expected {} bytes",
+                    save.len(),
+                    self.ram.len()
+                ))
+            }
+            _ => (),
+        }
+        self.ram.copy_from_slice(ram);
+        Ok(())
+    }
+
+    /// Returns true while the rumble motor of an MBC5 cartridge is on.
+    pub fn rumble(&self) -> bool {
+        match &self.ctype {
+            Type::MBC5(mbc5) => mbc5.rumble(),
+            _ => false,
+        }
+    }
+
+    /// Reads the byte at *address* in ROM *bank*.
+    /// The bank number wraps around the number of banks of the ROM.
+    fn read_rom_bank(&self, bank: usize, address: u16) -> u8 {
+        let bank = bank % self.header.rom_banks().max(1);
+        let offset = bank * ROM_BANK_SIZE + (address as usize % ROM_BANK_SIZE);
+        self.rom.get(offset).copied().unwrap_or(OPEN_BUS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::header::build_rom;
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let cartridge = Cartridge::new(build_rom(0x01, 0x01, 0x00)).unwrap();
+
+        assert!(matches!(cartridge.ctype, Type::MBC1(_)));
+        assert_eq!(cartridge.read_rom(0x0000), 0);
+        assert_eq!(cartridge.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn test_new_corrupt() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[0x0134] ^= 0xFF;
+
+        assert!(Cartridge::new(rom).is_err());
+    }
+
+    #[test]
+    fn test_rom_only_is_read_only() {
+        let mut cartridge = Cartridge::new(build_rom(0x00, 0x00, 0x00)).unwrap();
+
+        cartridge.write_rom(0x0000, 0x42);
+        assert_eq!(cartridge.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn test_mbc1() {
+        let mut cartridge = Cartridge::new(build_rom(0x03, 0x06, 0x03)).unwrap();
+
+        cartridge.write_rom(0x2000, 0x05);
+        assert_eq!(cartridge.read_rom(0x4000), 0x05);
+
+        cartridge.write_rom(0x4000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4000), 0x25);
+
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), OPEN_BUS);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+        assert_eq!(cartridge.read_rom(0x0000), 0x20);
+    }
+
+    #[test]
+    fn test_mbc3() {
+        let mut cartridge = Cartridge::new(build_rom(0x10, 0x06, 0x03)).unwrap();
+
+        cartridge.write_rom(0x2000, 0x42);
+        assert_eq!(cartridge.read_rom(0x4000), 0x42);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x4000, 0x01);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+
+        cartridge.write_rom(0x4000, 0x08);
+        cartridge.write_ram(0xA000, 0x30);
+        cartridge.write_rom(0x6000, 0x00);
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0x30);
+
+        cartridge.tick(4_194_304);
+        cartridge.write_rom(0x6000, 0x00);
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0x31);
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut cartridge = Cartridge::new(build_rom(0x08, 0x00, 0x02)).unwrap();
+
+        cartridge.write_ram(0xA010, 0x42);
+        assert_eq!(cartridge.read_ram(0xA010), 0x42);
+
+        let cartridge = Cartridge::new(build_rom(0x00, 0x00, 0x00)).unwrap();
+        assert_eq!(cartridge.read_ram(0xA010), OPEN_BUS);
+    }
+
+    #[test]
+    fn test_mbc2() {
+        let mut cartridge = Cartridge::new(build_rom(0x06, 0x03, 0x00)).unwrap();
+
+        cartridge.write_rom(0x2100, 0x0D);
+        assert_eq!(cartridge.read_rom(0x4000), 0x0D);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA005, 0x5A);
+        assert_eq!(cartridge.read_ram(0xA005), 0xFA);
+        assert_eq!(cartridge.read_ram(0xA205), 0xFA);
+        assert_eq!(cartridge.read_ram(0xBE05), 0xFA);
+    }
+
+    #[test]
+    fn test_mbc5() {
+        let mut rom = build_rom(0x1E, 0x08, 0x03);
+        rom[0x102 * ROM_BANK_SIZE + 1] = 0x42;
+        let mut cartridge = Cartridge::new(rom).unwrap();
+
+        cartridge.write_rom(0x2000, 0x02);
+        cartridge.write_rom(0x3000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4001), 0x42);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x4000, 0x09);
+        assert!(cartridge.rumble());
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+
+        cartridge.write_rom(0x4000, 0x00);
+        assert!(!cartridge.rumble());
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut cartridge = Cartridge::new(build_rom(0x03, 0x00, 0x02)).unwrap();
+        assert!(cartridge.battery());
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA123, 0x42);
+        let save = cartridge.save(0);
+        assert_eq!(save.len(), RAM_BANK_SIZE);
+
+        let mut cartridge = Cartridge::new(build_rom(0x03, 0x00, 0x02)).unwrap();
+        cartridge.load(&save, 0).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        assert_eq!(cartridge.read_ram(0xA123), 0x42);
+
+        assert!(cartridge.load(&save[1..], 0).is_err());
+        assert!(cartridge
+            .load(&[save.clone(), vec![0]].concat(), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_save_load_rtc() {
+        let mut cartridge = Cartridge::new(build_rom(0x10, 0x00, 0x03)).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x4000, 0x08);
+        cartridge.write_ram(0xA000, 0x30);
+        let save = cartridge.save(1_000);
+        assert_eq!(save.len(), 4 * RAM_BANK_SIZE + rtc::FOOTER_SIZE);
+
+        let mut cartridge = Cartridge::new(build_rom(0x10, 0x00, 0x03)).unwrap();
+        cartridge.load(&save, 1_010).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x4000, 0x08);
+        cartridge.write_rom(0x6000, 0x00);
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0x3A);
+    }
 }
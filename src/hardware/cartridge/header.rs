@@ -0,0 +1,418 @@
+//! Cartridge header.
+//!
+//! Every cartridge stores a header at `0x0100-0x014F` describing the game and the hardware
+//! embedded in the cartridge.
+//!
+//! | Range           | Field                                                  |
+//! |-----------------|--------------------------------------------------------|
+//! | `0x0100-0x0103` | Entry point                                            |
+//! | `0x0104-0x0133` | Nintendo logo                                          |
+//! | `0x0134-0x0143` | Title, the last bytes are shared with the CGB flag     |
+//! | `0x0143`        | CGB flag                                               |
+//! | `0x0144-0x0145` | New licensee code                                      |
+//! | `0x0146`        | SGB flag                                               |
+//! | `0x0147`        | Cartridge type                                         |
+//! | `0x0148`        | ROM size                                               |
+//! | `0x0149`        | RAM size                                               |
+//! | `0x014A`        | Destination code                                       |
+//! | `0x014B`        | Old licensee code                                      |
+//! | `0x014C`        | Mask ROM version number                                |
+//! | `0x014D`        | Header checksum                                        |
+//! | `0x014E-0x014F` | Global checksum                                        |
+//!
+
+/// Address of the Nintendo logo.
+pub const LOGO_START: usize = 0x0104;
+
+/// The Nintendo logo, checked by the boot ROM before starting the game.
+pub const LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Address of the first byte of the title.
+const TITLE_START: usize = 0x0134;
+
+/// Address of the last byte of the title, when the CGB flag is not part of it.
+const TITLE_END: usize = 0x0143;
+
+/// Address of the CGB flag.
+const CGB_FLAG: usize = 0x0143;
+
+/// Address of the new licensee code.
+const NEW_LICENSEE: usize = 0x0144;
+
+/// Address of the SGB flag.
+const SGB_FLAG: usize = 0x0146;
+
+/// Address of the cartridge type.
+const CARTRIDGE_TYPE: usize = 0x0147;
+
+/// Address of the ROM size code.
+const ROM_SIZE: usize = 0x0148;
+
+/// Address of the RAM size code.
+const RAM_SIZE: usize = 0x0149;
+
+/// Address of the old licensee code.
+const OLD_LICENSEE: usize = 0x014B;
+
+/// Address of the mask ROM version number.
+const VERSION: usize = 0x014C;
+
+/// Address of the header checksum.
+const HEADER_CHECKSUM: usize = 0x014D;
+
+/// Address of the global checksum.
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+/// Size of the whole header area, including the entry point.
+pub const HEADER_END: usize = 0x0150;
+
+/// Size of a ROM bank.
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Size of a RAM bank.
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// Enumerates the memory bank controllers a cartridge can embed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Controller {
+    /// No controller, the ROM is directly mapped at `0x0000-0x7FFF`.
+    None,
+    MBC1,
+    MBC2,
+    MBC3,
+    MBC5,
+}
+
+/// Enumerates the values of the CGB flag.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CgbSupport {
+    /// The game only uses DMG features.
+    None,
+
+    /// The game supports CGB enhancements but still works on DMG.
+    Compatible,
+
+    /// The game only works on CGB.
+    Only,
+}
+
+/// Enumerates the two ways a publisher is identified.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Licensee {
+    /// The old licensee code stored at `0x014B`.
+    Old(u8),
+
+    /// The two ASCII characters stored at `0x0144-0x0145`, used when the old code is `0x33`.
+    New([u8; 2]),
+}
+
+/// The parsed cartridge header.
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// The upper case ASCII title of the game.
+    #[allow(dead_code)] // Identifies the game, nothing displays it yet
+    pub title: String,
+
+    /// Whether the game supports or requires CGB features.
+    pub cgb: CgbSupport,
+
+    /// Set if the game supports SGB functions.
+    #[allow(dead_code)] // The SGB is not emulated
+    pub sgb: bool,
+
+    /// The raw cartridge type byte.
+    #[allow(dead_code)] // Decoded into the controller and the flags below, kept for reference
+    pub cartridge_type: u8,
+
+    /// The memory bank controller described by the cartridge type.
+    pub controller: Controller,
+
+    /// Set if the cartridge embeds external RAM.
+    #[allow(dead_code)] // The RAM is allocated from `ram_size` instead
+    pub ram: bool,
+
+    /// Set if the external RAM, or the clock, is kept alive by a battery.
+    pub battery: bool,
+
+    /// Set if the cartridge embeds a real time clock.
+    pub timer: bool,
+
+    /// Set if the cartridge embeds a rumble motor.
+    pub rumble: bool,
+
+    /// The ROM size in bytes.
+    pub rom_size: usize,
+
+    /// The external RAM size in bytes.
+    pub ram_size: usize,
+
+    /// The publisher of the game.
+    #[allow(dead_code)] // Identifies the publisher, nothing displays it yet
+    pub licensee: Licensee,
+
+    /// The version number of the game.
+    #[allow(dead_code)] // Identifies the revision of the game, nothing displays it yet
+    pub version: u8,
+
+    /// The checksum of the header bytes `0x0134-0x014C`.
+    #[allow(dead_code)] // Already verified by `Header::new`
+    pub header_checksum: u8,
+
+    /// The 16-bit sum of every ROM byte, except the global checksum itself.
+    #[allow(dead_code)] // Only read by `verify_global_checksum`
+    pub global_checksum: u16,
+}
+
+impl Header {
+    /// Parses the header of *rom*.
+    /// Returns an error when *rom* is too small, when the header checksum does not match or when
+    /// the cartridge type or sizes are not supported.
+    pub fn new(rom: &[u8]) -> Result<Self, String> {
+        if rom.len() < HEADER_END {
+            return Err(format!(
+                "ROM is too small to contain a header: {} bytes",
+                rom.len()
+            ));
+        }
+
+        let header_checksum = rom[HEADER_CHECKSUM];
+        let expected = checksum(rom);
+        if header_checksum != expected {
+            return Err(format!(
+                "Header checksum mismatch: found {:#04x}, expected {:#04x}",
+                header_checksum, expected
+            ));
+        }
+
+        let cartridge_type = rom[CARTRIDGE_TYPE];
+        let (controller, ram, battery, timer, rumble) = match cartridge_type {
+            0x00 => (Controller::None, false, false, false, false),
+            0x01 => (Controller::MBC1, false, false, false, false),
+            0x02 => (Controller::MBC1, true, false, false, false),
+            0x03 => (Controller::MBC1, true, true, false, false),
+            0x05 => (Controller::MBC2, false, false, false, false),
+            0x06 => (Controller::MBC2, false, true, false, false),
+            0x08 => (Controller::None, true, false, false, false),
+            0x09 => (Controller::None, true, true, false, false),
+            0x0F => (Controller::MBC3, false, true, true, false),
+            0x10 => (Controller::MBC3, true, true, true, false),
+            0x11 => (Controller::MBC3, false, false, false, false),
+            0x12 => (Controller::MBC3, true, false, false, false),
+            0x13 => (Controller::MBC3, true, true, false, false),
+            0x19 => (Controller::MBC5, false, false, false, false),
+            0x1A => (Controller::MBC5, true, false, false, false),
+            0x1B => (Controller::MBC5, true, true, false, false),
+            0x1C => (Controller::MBC5, false, false, false, true),
+            0x1D => (Controller::MBC5, true, false, false, true),
+            0x1E => (Controller::MBC5, true, true, false, true),
+            _ => {
+                return Err(format!(
+                    "Unsupported cartridge type: {:#04x}",
+                    cartridge_type
+                ))
+            }
+        };
+
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => (2 * ROM_BANK_SIZE) << code,
+            0x52 => 72 * ROM_BANK_SIZE,
+            0x53 => 80 * ROM_BANK_SIZE,
+            0x54 => 96 * ROM_BANK_SIZE,
+            code => return Err(format!("Unknown ROM size code: {:#04x}", code)),
+        };
+        if rom.len() < rom_size {
+            return Err(format!(
+                "ROM is truncated: {} bytes, header declares {} bytes",
+                rom.len(),
+                rom_size
+            ));
+        }
+
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => RAM_BANK_SIZE,
+            0x03 => 4 * RAM_BANK_SIZE,
+            0x04 => 16 * RAM_BANK_SIZE,
+            0x05 => 8 * RAM_BANK_SIZE,
+            code => return Err(format!("Unknown RAM size code: {:#04x}", code)),
+        };
+
+        let cgb = match rom[CGB_FLAG] {
+            0x80 => CgbSupport::Compatible,
+            0xC0 => CgbSupport::Only,
+            _ => CgbSupport::None,
+        };
+
+        // The CGB flag steals the last byte of the title
+        let title_end = match cgb {
+            CgbSupport::None => TITLE_END,
+            _ => CGB_FLAG - 1,
+        };
+        let title = rom[TITLE_START..=title_end]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| byte as char)
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        let licensee = match rom[OLD_LICENSEE] {
+            0x33 => Licensee::New([rom[NEW_LICENSEE], rom[NEW_LICENSEE + 1]]),
+            code => Licensee::Old(code),
+        };
+
+        Ok(Self {
+            title,
+            cgb,
+            sgb: rom[SGB_FLAG] == 0x03,
+            cartridge_type,
+            controller,
+            ram,
+            battery,
+            timer,
+            rumble,
+            rom_size,
+            ram_size,
+            licensee,
+            version: rom[VERSION],
+            header_checksum,
+            global_checksum: u16::from_be_bytes([rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]]),
+        })
+    }
+
+    /// Returns true if the global checksum matches the content of *rom*.
+    /// The hardware never verifies it, so a mismatch is not an error.
+    #[allow(dead_code)] // Loading a ROM ignores the global checksum, like the hardware
+    pub fn verify_global_checksum(&self, rom: &[u8]) -> bool {
+        let sum = rom
+            .iter()
+            .enumerate()
+            .filter(|&(address, _)| address != GLOBAL_CHECKSUM && address != GLOBAL_CHECKSUM + 1)
+            .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte as u16));
+        sum == self.global_checksum
+    }
+
+    /// Returns the number of 16 KiB ROM banks.
+    pub fn rom_banks(&self) -> usize {
+        self.rom_size / ROM_BANK_SIZE
+    }
+}
+
+/// Computes the header checksum of *rom* over the bytes `0x0134-0x014C`, as the boot ROM does.
+pub fn checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_START..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |x, &byte| x.wrapping_sub(byte).wrapping_sub(1))
+}
+
+#[cfg(test)]
+/// Builds a ROM image with a valid header for the given type and size codes.
+/// The first byte of each bank holds the bank number.
+pub fn build_rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; (2 * ROM_BANK_SIZE) << rom_size];
+    for (bank, chunk) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+        chunk[0] = bank as u8;
+    }
+    rom[LOGO_START..LOGO_START + LOGO.len()].copy_from_slice(&LOGO);
+    rom[TITLE_START..TITLE_START + 4].copy_from_slice(b"GBMU");
+    rom[CARTRIDGE_TYPE] = cartridge_type;
+    rom[ROM_SIZE] = rom_size;
+    rom[RAM_SIZE] = ram_size;
+    rom[HEADER_CHECKSUM] = checksum(&rom);
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let rom = build_rom(0x13, 0x05, 0x03);
+        let header = Header::new(&rom).unwrap();
+
+        assert_eq!(header.title, "GBMU");
+        assert_eq!(header.cgb, CgbSupport::None);
+        assert_eq!(header.controller, Controller::MBC3);
+        assert!(header.ram);
+        assert!(header.battery);
+        assert!(!header.timer);
+        assert_eq!(header.rom_size, 1024 * 1024);
+        assert_eq!(header.rom_banks(), 64);
+        assert_eq!(header.ram_size, 32 * 1024);
+        assert_eq!(header.licensee, Licensee::Old(0));
+    }
+
+    #[test]
+    fn test_cgb_title() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[TITLE_START..=TITLE_END].copy_from_slice(b"ABCDEFGHIJKLMNO\xC0");
+        rom[HEADER_CHECKSUM] = checksum(&rom);
+        let header = Header::new(&rom).unwrap();
+
+        assert_eq!(header.cgb, CgbSupport::Only);
+        assert_eq!(header.title, "ABCDEFGHIJKLMNO");
+    }
+
+    #[test]
+    fn test_new_licensee() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[OLD_LICENSEE] = 0x33;
+        rom[NEW_LICENSEE..NEW_LICENSEE + 2].copy_from_slice(b"01");
+        rom[HEADER_CHECKSUM] = checksum(&rom);
+        let header = Header::new(&rom).unwrap();
+
+        assert_eq!(header.licensee, Licensee::New(*b"01"));
+    }
+
+    #[test]
+    fn test_too_small() {
+        assert!(Header::new(&[0; 0x100]).is_err());
+    }
+
+    #[test]
+    fn test_bad_header_checksum() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[HEADER_CHECKSUM] ^= 0xFF;
+
+        let error = Header::new(&rom).unwrap_err();
+        assert!(error.starts_with("Header checksum mismatch"));
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let rom = build_rom(0xFC, 0x00, 0x00);
+
+        assert_eq!(
+            Header::new(&rom).unwrap_err(),
+            "Unsupported cartridge type: 0xfc"
+        );
+    }
+
+    #[test]
+    fn test_truncated_rom() {
+        let rom = build_rom(0x01, 0x01, 0x00);
+
+        assert!(Header::new(&rom[..4 * ROM_BANK_SIZE]).is_ok());
+        assert!(Header::new(&rom[..3 * ROM_BANK_SIZE]).is_err());
+    }
+
+    #[test]
+    fn test_global_checksum() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        let sum = rom
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        rom[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&sum.to_be_bytes());
+        let header = Header::new(&rom).unwrap();
+
+        assert!(header.verify_global_checksum(&rom));
+        rom[0x200] = 1;
+        assert!(!header.verify_global_checksum(&rom));
+    }
+}
@@ -0,0 +1,202 @@
+//! MBC1 memory bank controller.
+//!
+//! The MBC1 supports up to 2 MiB of ROM and 32 KiB of RAM through four registers, selected by
+//! the address written to:
+//!
+//! | Range           | Register                                                     |
+//! |-----------------|--------------------------------------------------------------|
+//! | `0x0000-0x1FFF` | RAM enable, `0x0A` in the lower nibble enables the RAM       |
+//! | `0x2000-0x3FFF` | ROM bank, the lower 5 bits of the ROM bank number            |
+//! | `0x4000-0x5FFF` | Upper 2 bits of the ROM bank number, or the RAM bank number  |
+//! | `0x6000-0x7FFF` | Banking mode select                                          |
+//!
+//! The ROM bank register can never hold 0: writing 0 selects bank 1 instead. Since the check is
+//! made on the 5-bit register only, banks `0x20`, `0x40` and `0x60` cannot be mapped at
+//! `0x4000-0x7FFF`, the following bank is mapped instead.
+//!
+//! MBC1M multicarts wire only 4 bits of the ROM bank register, so the upper 2 bits select one of
+//! the four 256 KiB games stored in the cartridge.
+//!
+
+use super::header::{Header, LOGO, LOGO_START, ROM_BANK_SIZE};
+
+/// Size of the ROM of MBC1M multicarts.
+const MULTICART_ROM_SIZE: usize = 0x100000;
+
+/// Bank storing the header of the second game of a multicart.
+const MULTICART_SECOND_GAME: usize = 0x10;
+
+pub struct Mbc1 {
+    /// Set when the external RAM is readable and writable.
+    ram_enabled: bool,
+
+    /// The 5-bit ROM bank register, never 0.
+    bank1: u8,
+
+    /// The 2-bit register selecting the upper ROM bank bits or the RAM bank.
+    bank2: u8,
+
+    /// The banking mode select register.
+    /// In mode 1, `bank2` also applies to `0x0000-0x3FFF` and to the external RAM.
+    mode: bool,
+
+    /// Set for MBC1M multicarts, where the ROM bank register only has 4 bits wired.
+    multicart: bool,
+}
+
+impl Mbc1 {
+    /// Constructs an MBC1 in its power-up state.
+    /// The multicart wiring is detected when a 1 MiB ROM holds a second Nintendo logo in bank
+    /// `0x10`.
+    pub fn new(header: &Header, rom: &[u8]) -> Self {
+        let logo = MULTICART_SECOND_GAME * ROM_BANK_SIZE + LOGO_START;
+        let multicart = header.rom_size == MULTICART_ROM_SIZE
+            && rom.get(logo..logo + LOGO.len()) == Some(&LOGO[..]);
+
+        Self {
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            mode: false,
+            multicart,
+        }
+    }
+
+    /// Updates the register selected by *address* with *value*.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 0x01 == 0x01,
+        }
+    }
+
+    /// Returns the ROM bank mapped at *address*, in the range `0x0000-0x7FFF`.
+    pub fn rom_bank(&self, address: u16) -> usize {
+        let (bank1, shift) = match self.multicart {
+            true => (self.bank1 & 0x0F, 4),
+            false => (self.bank1, 5),
+        };
+        let upper = (self.bank2 as usize) << shift;
+
+        match address {
+            0x0000..=0x3FFF if self.mode => upper,
+            0x0000..=0x3FFF => 0,
+            _ => upper | bank1 as usize,
+        }
+    }
+
+    /// Returns the RAM bank mapped at `0xA000-0xBFFF`.
+    pub fn ram_bank(&self) -> usize {
+        match self.mode {
+            true => self.bank2 as usize,
+            false => 0,
+        }
+    }
+
+    /// Returns true if the external RAM is enabled.
+    pub fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::header::build_rom;
+    use super::*;
+
+    fn mbc1(rom_size: u8) -> Mbc1 {
+        let rom = build_rom(0x03, rom_size, 0x03);
+        Mbc1::new(&Header::new(&rom).unwrap(), &rom)
+    }
+
+    #[test]
+    fn test_ram_enable() {
+        let mut mbc1 = mbc1(0x00);
+
+        assert!(!mbc1.ram_enabled());
+        mbc1.write(0x0000, 0x0A);
+        assert!(mbc1.ram_enabled());
+        mbc1.write(0x1FFF, 0xFA);
+        assert!(mbc1.ram_enabled());
+        mbc1.write(0x0000, 0x0B);
+        assert!(!mbc1.ram_enabled());
+    }
+
+    #[test]
+    fn test_rom_bank() {
+        let mut mbc1 = mbc1(0x06);
+
+        assert_eq!(mbc1.rom_bank(0x0000), 0);
+        assert_eq!(mbc1.rom_bank(0x4000), 1);
+
+        mbc1.write(0x2000, 0x00);
+        assert_eq!(mbc1.rom_bank(0x4000), 1);
+
+        mbc1.write(0x2000, 0xE5);
+        assert_eq!(mbc1.rom_bank(0x4000), 0x05);
+    }
+
+    #[test]
+    fn test_bank_zero_quirk() {
+        let mut mbc1 = mbc1(0x06);
+
+        for bank2 in 1..4 {
+            mbc1.write(0x4000, bank2);
+            mbc1.write(0x2000, 0x00);
+            assert_eq!(mbc1.rom_bank(0x4000), (bank2 as usize) << 5 | 1);
+        }
+    }
+
+    #[test]
+    fn test_banking_mode() {
+        let mut mbc1 = mbc1(0x06);
+
+        mbc1.write(0x4000, 0x02);
+        mbc1.write(0x2000, 0x03);
+        assert_eq!(mbc1.rom_bank(0x0000), 0);
+        assert_eq!(mbc1.rom_bank(0x4000), 0x43);
+        assert_eq!(mbc1.ram_bank(), 0);
+
+        mbc1.write(0x6000, 0x01);
+        assert_eq!(mbc1.rom_bank(0x0000), 0x40);
+        assert_eq!(mbc1.rom_bank(0x4000), 0x43);
+        assert_eq!(mbc1.ram_bank(), 2);
+    }
+
+    #[test]
+    fn test_multicart() {
+        let mut rom = build_rom(0x01, 0x05, 0x00);
+        for game in 1..4 {
+            let offset = game * MULTICART_SECOND_GAME * ROM_BANK_SIZE;
+            rom.copy_within(0..LOGO_START + LOGO.len(), offset);
+        }
+        let mut mbc1 = Mbc1::new(&Header::new(&rom).unwrap(), &rom);
+
+        assert!(mbc1.multicart);
+
+        mbc1.write(0x4000, 0x01);
+        mbc1.write(0x2000, 0x13);
+        assert_eq!(mbc1.rom_bank(0x4000), 0x13);
+
+        mbc1.write(0x4000, 0x02);
+        mbc1.write(0x2000, 0x10);
+        assert_eq!(mbc1.rom_bank(0x4000), 0x20);
+
+        mbc1.write(0x6000, 0x01);
+        assert_eq!(mbc1.rom_bank(0x0000), 0x20);
+    }
+
+    #[test]
+    fn test_not_multicart() {
+        let mbc1 = mbc1(0x05);
+
+        assert!(!mbc1.multicart);
+    }
+}
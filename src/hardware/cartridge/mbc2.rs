@@ -0,0 +1,99 @@
+//! MBC2 memory bank controller.
+//!
+//! The MBC2 supports up to 256 KiB of ROM and embeds 512 half-bytes of RAM, mapped at
+//! `0xA000-0xA1FF` and echoed up to `0xBFFF`. Only the lower nibble of each RAM byte exists, the
+//! upper nibble reads as open bus.
+//!
+//! Both registers live at `0x0000-0x3FFF` and are selected by bit 8 of the address:
+//! - bit 8 reset: RAM enable, `0x0A` in the lower nibble enables the RAM.
+//! - bit 8 set: ROM bank, 4 bits, writing 0 selects bank 1.
+//!
+
+/// Size of the built-in RAM, in half-bytes.
+pub const RAM_SIZE: usize = 0x200;
+
+/// The address bit selecting the register written to.
+const REGISTER_SELECT: u16 = 0x0100;
+
+pub struct Mbc2 {
+    /// Set when the built-in RAM is readable and writable.
+    ram_enabled: bool,
+
+    /// The 4-bit ROM bank register, never 0.
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    /// Constructs an MBC2 in its power-up state.
+    pub fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+
+    /// Updates the register selected by *address* with *value*.
+    /// Writes to `0x4000-0x7FFF` are ignored.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x3FFF if address & REGISTER_SELECT == 0 => {
+                self.ram_enabled = value & 0x0F == 0x0A
+            }
+            0x0000..=0x3FFF => {
+                self.rom_bank = value & 0x0F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns the ROM bank mapped at *address*, in the range `0x0000-0x7FFF`.
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        }
+    }
+
+    /// Returns true if the built-in RAM is enabled.
+    pub fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_select() {
+        let mut mbc2 = Mbc2::new();
+
+        mbc2.write(0x0000, 0x0A);
+        assert!(mbc2.ram_enabled());
+        assert_eq!(mbc2.rom_bank(0x4000), 1);
+
+        mbc2.write(0x0100, 0x05);
+        assert!(mbc2.ram_enabled());
+        assert_eq!(mbc2.rom_bank(0x4000), 5);
+
+        mbc2.write(0x3EFF, 0x00);
+        assert!(!mbc2.ram_enabled());
+        assert_eq!(mbc2.rom_bank(0x4000), 5);
+
+        mbc2.write(0x2100, 0xF0);
+        assert_eq!(mbc2.rom_bank(0x4000), 1);
+    }
+
+    #[test]
+    fn test_ignored_writes() {
+        let mut mbc2 = Mbc2::new();
+
+        mbc2.write(0x4000, 0x0A);
+        mbc2.write(0x4100, 0x03);
+        assert!(!mbc2.ram_enabled());
+        assert_eq!(mbc2.rom_bank(0x4000), 1);
+    }
+}
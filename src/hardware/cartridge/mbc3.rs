@@ -0,0 +1,196 @@
+//! MBC3 memory bank controller.
+//!
+//! The MBC3 supports up to 2 MiB of ROM, 32 KiB of RAM and an optional real time clock, see
+//! [super::rtc]. Its registers are selected by the address written to:
+//!
+//! | Range           | Register                                                           |
+//! |-----------------|--------------------------------------------------------------------|
+//! | `0x0000-0x1FFF` | RAM and clock enable, `0x0A` in the lower nibble enables them      |
+//! | `0x2000-0x3FFF` | ROM bank, 7 bits, writing 0 selects bank 1                         |
+//! | `0x4000-0x5FFF` | RAM bank `0x00-0x03` or clock register `0x08-0x0C` select          |
+//! | `0x6000-0x7FFF` | Latch clock data, writing `0x00` then `0x01` latches the clock     |
+//!
+
+use super::header::Header;
+use super::rtc::{Rtc, DAYS_HIGH, SECONDS};
+
+pub struct Mbc3 {
+    /// Set when the external RAM and the clock registers are readable and writable.
+    ram_enabled: bool,
+
+    /// The 7-bit ROM bank register, never 0.
+    rom_bank: u8,
+
+    /// The RAM bank or clock register mapped at `0xA000-0xBFFF`.
+    select: u8,
+
+    /// The last value written to the latch register.
+    latch: u8,
+
+    /// The real time clock, if the cartridge has one.
+    pub rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    /// Constructs an MBC3 in its power-up state, with a clock if the header declares a timer.
+    pub fn new(header: &Header) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            select: 0,
+            latch: 0xFF,
+            rtc: header.timer.then(Rtc::new),
+        }
+    }
+
+    /// Updates the register selected by *address* with *value*.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x7F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.select = value,
+            _ => {
+                if self.latch == 0x00 && value == 0x01 {
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.latch();
+                    }
+                }
+                self.latch = value;
+            }
+        }
+    }
+
+    /// Returns the ROM bank mapped at *address*, in the range `0x0000-0x7FFF`.
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        }
+    }
+
+    /// Returns the RAM bank mapped at `0xA000-0xBFFF`.
+    pub fn ram_bank(&self) -> usize {
+        (self.select & 0x03) as usize
+    }
+
+    /// Returns true if the external RAM is mapped at `0xA000-0xBFFF`.
+    pub fn ram_enabled(&self) -> bool {
+        self.ram_enabled && self.select <= 0x03
+    }
+
+    /// Returns the value of the selected clock register, or None if no clock register is
+    /// mapped at `0xA000-0xBFFF`.
+    pub fn read_rtc(&self) -> Option<u8> {
+        match (&self.rtc, self.select) {
+            (Some(rtc), SECONDS..=DAYS_HIGH) if self.ram_enabled => Some(rtc.read(self.select)),
+            _ => None,
+        }
+    }
+
+    /// Writes *value* to the selected clock register.
+    /// Returns false if no clock register is mapped at `0xA000-0xBFFF`.
+    pub fn write_rtc(&mut self, value: u8) -> bool {
+        match (&mut self.rtc, self.select) {
+            (Some(rtc), SECONDS..=DAYS_HIGH) if self.ram_enabled => {
+                rtc.write(self.select, value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Advances the clock by *cycles* T-cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::header::build_rom;
+    use super::super::rtc::MINUTES;
+    use super::*;
+
+    fn mbc3(cartridge_type: u8) -> Mbc3 {
+        Mbc3::new(&Header::new(&build_rom(cartridge_type, 0x06, 0x03)).unwrap())
+    }
+
+    #[test]
+    fn test_rom_bank() {
+        let mut mbc3 = mbc3(0x13);
+
+        assert_eq!(mbc3.rom_bank(0x0000), 0);
+        assert_eq!(mbc3.rom_bank(0x4000), 1);
+
+        mbc3.write(0x2000, 0x00);
+        assert_eq!(mbc3.rom_bank(0x4000), 1);
+
+        mbc3.write(0x2000, 0xFF);
+        assert_eq!(mbc3.rom_bank(0x4000), 0x7F);
+    }
+
+    #[test]
+    fn test_ram_bank() {
+        let mut mbc3 = mbc3(0x13);
+
+        assert!(!mbc3.ram_enabled());
+        mbc3.write(0x0000, 0x0A);
+        mbc3.write(0x4000, 0x02);
+        assert!(mbc3.ram_enabled());
+        assert_eq!(mbc3.ram_bank(), 2);
+
+        mbc3.write(0x4000, MINUTES);
+        assert!(!mbc3.ram_enabled());
+    }
+
+    #[test]
+    fn test_rtc_registers() {
+        let mut mbc3 = mbc3(0x10);
+
+        mbc3.write(0x0000, 0x0A);
+        mbc3.write(0x4000, MINUTES);
+        assert!(mbc3.write_rtc(42));
+        assert_eq!(mbc3.read_rtc(), Some(0));
+
+        mbc3.write(0x6000, 0x00);
+        mbc3.write(0x6000, 0x01);
+        assert_eq!(mbc3.read_rtc(), Some(42));
+
+        mbc3.write(0x0000, 0x00);
+        assert_eq!(mbc3.read_rtc(), None);
+    }
+
+    #[test]
+    fn test_latch_sequence() {
+        let mut mbc3 = mbc3(0x10);
+
+        mbc3.write(0x0000, 0x0A);
+        mbc3.write(0x4000, MINUTES);
+        mbc3.write_rtc(42);
+
+        mbc3.write(0x6000, 0x01);
+        assert_eq!(mbc3.read_rtc(), Some(0));
+
+        mbc3.write(0x6000, 0x00);
+        mbc3.write(0x6000, 0x02);
+        mbc3.write(0x6000, 0x01);
+        assert_eq!(mbc3.read_rtc(), Some(0));
+    }
+
+    #[test]
+    fn test_without_rtc() {
+        let mut mbc3 = mbc3(0x13);
+
+        mbc3.write(0x0000, 0x0A);
+        mbc3.write(0x4000, SECONDS);
+        assert_eq!(mbc3.read_rtc(), None);
+        assert!(!mbc3.write_rtc(1));
+    }
+}
@@ -0,0 +1,141 @@
+//! MBC5 memory bank controller.
+//!
+//! The MBC5 supports up to 8 MiB of ROM and 128 KiB of RAM. Its registers are selected by the
+//! address written to:
+//!
+//! | Range           | Register                                                        |
+//! |-----------------|-----------------------------------------------------------------|
+//! | `0x0000-0x1FFF` | RAM enable, `0x0A` enables the RAM                              |
+//! | `0x2000-0x2FFF` | Lower 8 bits of the 9-bit ROM bank number                       |
+//! | `0x3000-0x3FFF` | Upper bit of the ROM bank number                                |
+//! | `0x4000-0x5FFF` | RAM bank `0x00-0x0F`, bit 3 drives the motor on rumble variants |
+//!
+//! Unlike the MBC1 and MBC3, bank 0 can be mapped at `0x4000-0x7FFF`.
+//!
+
+use super::header::Header;
+
+/// The bit of the RAM bank register wired to the motor on rumble cartridges.
+const RUMBLE: u8 = 0b0000_1000;
+
+pub struct Mbc5 {
+    /// Set when the external RAM is readable and writable.
+    ram_enabled: bool,
+
+    /// The 9-bit ROM bank register.
+    rom_bank: u16,
+
+    /// The 4-bit RAM bank register.
+    ram_bank: u8,
+
+    /// Set if the cartridge embeds a rumble motor.
+    has_rumble: bool,
+
+    /// Set while the rumble motor is on.
+    rumble: bool,
+}
+
+impl Mbc5 {
+    /// Constructs an MBC5 in its power-up state.
+    pub fn new(header: &Header) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble: header.rumble,
+            rumble: false,
+        }
+    }
+
+    /// Updates the register selected by *address* with *value*.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | (value as u16 & 0x01) << 8,
+            0x4000..=0x5FFF if self.has_rumble => {
+                self.rumble = value & RUMBLE == RUMBLE;
+                self.ram_bank = value & 0x07;
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => (),
+        }
+    }
+
+    /// Returns the ROM bank mapped at *address*, in the range `0x0000-0x7FFF`.
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        }
+    }
+
+    /// Returns the RAM bank mapped at `0xA000-0xBFFF`.
+    pub fn ram_bank(&self) -> usize {
+        self.ram_bank as usize
+    }
+
+    /// Returns true if the external RAM is enabled.
+    pub fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    /// Returns true while the rumble motor is on.
+    pub fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::header::build_rom;
+    use super::*;
+
+    fn mbc5(cartridge_type: u8) -> Mbc5 {
+        Mbc5::new(&Header::new(&build_rom(cartridge_type, 0x08, 0x04)).unwrap())
+    }
+
+    #[test]
+    fn test_rom_bank() {
+        let mut mbc5 = mbc5(0x1B);
+
+        assert_eq!(mbc5.rom_bank(0x4000), 1);
+
+        mbc5.write(0x2000, 0x00);
+        assert_eq!(mbc5.rom_bank(0x4000), 0);
+
+        mbc5.write(0x2000, 0x42);
+        mbc5.write(0x3000, 0x01);
+        assert_eq!(mbc5.rom_bank(0x4000), 0x142);
+        assert_eq!(mbc5.rom_bank(0x0000), 0);
+
+        mbc5.write(0x3000, 0x00);
+        assert_eq!(mbc5.rom_bank(0x4000), 0x42);
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut mbc5 = mbc5(0x1B);
+
+        mbc5.write(0x0000, 0x0A);
+        assert!(mbc5.ram_enabled());
+        mbc5.write(0x0000, 0x1A);
+        assert!(!mbc5.ram_enabled());
+
+        mbc5.write(0x4000, 0x0F);
+        assert_eq!(mbc5.ram_bank(), 0x0F);
+        assert!(!mbc5.rumble());
+    }
+
+    #[test]
+    fn test_rumble() {
+        let mut mbc5 = mbc5(0x1E);
+
+        mbc5.write(0x4000, 0x0B);
+        assert!(mbc5.rumble());
+        assert_eq!(mbc5.ram_bank(), 0x03);
+
+        mbc5.write(0x4000, 0x03);
+        assert!(!mbc5.rumble());
+    }
+}
@@ -0,0 +1,372 @@
+//! MBC3 real time clock.
+//!
+//! The clock counts seconds, minutes, hours and days, and is readable through five registers
+//! once latched:
+//!
+//! | Register | Content                                                               |
+//! |----------|-----------------------------------------------------------------------|
+//! | `0x08`   | Seconds, `0-59`                                                       |
+//! | `0x09`   | Minutes, `0-59`                                                       |
+//! | `0x0A`   | Hours, `0-23`                                                         |
+//! | `0x0B`   | Lower 8 bits of the day counter                                       |
+//! | `0x0C`   | Bit 0: upper bit of the day counter, bit 6: halt, bit 7: day carry    |
+//!
+//! The clock runs from a 32768 Hz crystal, so it keeps ticking once per second of emulated time
+//! whatever the CPU speed is.
+//!
+//! Battery saves store the clock in a 48-byte footer appended to the RAM, in the layout shared by
+//! most emulators. Every field is little endian:
+//!
+//! | Offset | Content                                                            |
+//! |--------|--------------------------------------------------------------------|
+//! | `0x00` | Live registers `0x08-0x0C`, one `u32` each                         |
+//! | `0x14` | Latched registers `0x08-0x0C`, one `u32` each                      |
+//! | `0x28` | UNIX timestamp of the save, `u64`                                  |
+//!
+//! Some emulators write a 44-byte footer with a `u32` timestamp, which is accepted as well.
+//!
+
+/// Number of T-cycles in one second of emulated time.
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+/// Size of the clock footer of save files.
+pub const FOOTER_SIZE: usize = 48;
+
+/// Size of the clock footer of save files with a 32-bit timestamp.
+const FOOTER_SIZE_SHORT: usize = 44;
+
+/// Register index of the seconds counter.
+pub const SECONDS: u8 = 0x08;
+
+/// Register index of the minutes counter.
+pub const MINUTES: u8 = 0x09;
+
+/// Register index of the hours counter.
+pub const HOURS: u8 = 0x0A;
+
+/// Register index of the lower 8 bits of the day counter.
+pub const DAYS_LOW: u8 = 0x0B;
+
+/// Register index of the upper bit of the day counter, the halt and the carry flags.
+pub const DAYS_HIGH: u8 = 0x0C;
+
+/// The halt flag in the `DAYS_HIGH` register.
+const HALT: u8 = 0b0100_0000;
+
+/// The day carry flag in the `DAYS_HIGH` register.
+const CARRY: u8 = 0b1000_0000;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Rtc {
+    /// The live seconds counter.
+    seconds: u8,
+
+    /// The live minutes counter.
+    minutes: u8,
+
+    /// The live hours counter.
+    hours: u8,
+
+    /// The live 9-bit day counter.
+    days: u16,
+
+    /// Set when the clock is stopped.
+    halt: bool,
+
+    /// Set when the day counter overflowed, until cleared by the game.
+    carry: bool,
+
+    /// The registers as they were on the last latch, in register order.
+    latched: [u8; 5],
+
+    /// T-cycles elapsed since the last second.
+    cycles: u32,
+}
+
+impl Rtc {
+    /// Constructs a clock at day 0, 00:00:00.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the clock by *cycles* T-cycles of emulated time.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.halt {
+            return;
+        }
+        self.cycles += cycles;
+        let seconds = self.cycles / CYCLES_PER_SECOND;
+        self.cycles %= CYCLES_PER_SECOND;
+        self.advance(seconds as u64);
+    }
+
+    /// Advances the clock by *seconds* seconds, as if they had elapsed while it was running.
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halt {
+            return;
+        }
+
+        // Counters set out of range by the game only wrap at their bit width
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.tick_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let total = seconds
+            + self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400;
+
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        if days > 0x1FF {
+            self.carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    /// Increments the clock by one second.
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.days = (self.days + 1) & 0x1FF;
+        if self.days == 0 {
+            self.carry = true;
+        }
+    }
+
+    /// Copies the live counters into the latched registers.
+    pub fn latch(&mut self) {
+        self.latched = [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            self.days_high(),
+        ];
+    }
+
+    /// Returns the latched value of *register*, in the range `0x08-0x0C`.
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[(register - SECONDS) as usize]
+    }
+
+    /// Writes *value* to the live counter selected by *register*, in the range `0x08-0x0C`.
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            SECONDS => {
+                self.seconds = value & 0x3F;
+                self.cycles = 0;
+            }
+            MINUTES => self.minutes = value & 0x3F,
+            HOURS => self.hours = value & 0x1F,
+            DAYS_LOW => self.days = (self.days & 0x100) | value as u16,
+            DAYS_HIGH => {
+                self.days = (self.days & 0xFF) | ((value as u16 & 0x01) << 8);
+                self.halt = value & HALT == HALT;
+                self.carry = value & CARRY == CARRY;
+            }
+            _ => (),
+        }
+    }
+
+    /// Serializes the clock into a save file footer, stamped with the UNIX time *now*.
+    pub fn save(&self, now: u64) -> Vec<u8> {
+        let live = [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            self.days_high(),
+        ];
+
+        let mut footer = Vec::with_capacity(FOOTER_SIZE);
+        for register in live.iter().chain(self.latched.iter()) {
+            footer.extend_from_slice(&(*register as u32).to_le_bytes());
+        }
+        footer.extend_from_slice(&now.to_le_bytes());
+        footer
+    }
+
+    /// Restores the clock from a save file *footer*, then advances it by the time elapsed
+    /// between the save timestamp and the UNIX time *now*.
+    /// Returns an error if the footer size is not supported.
+    pub fn load(footer: &[u8], now: u64) -> Result<Self, String> {
+        let timestamp = match footer.len() {
+            FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            FOOTER_SIZE_SHORT => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            size => return Err(format!("Unsupported clock footer size: {} bytes", size)),
+        };
+        let register = |index: usize| footer[index * 4];
+
+        let mut rtc = Self::new();
+        for (index, register_index) in (SECONDS..=DAYS_HIGH).enumerate() {
+            rtc.write(register_index, register(index));
+            rtc.latched[index] = register(index + 5);
+        }
+        rtc.advance(now.saturating_sub(timestamp));
+        Ok(rtc)
+    }
+
+    /// Returns the `DAYS_HIGH` register built from the live counters.
+    fn days_high(&self) -> u8 {
+        let mut value = (self.days >> 8) as u8;
+        if self.halt {
+            value |= HALT;
+        }
+        if self.carry {
+            value |= CARRY;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick() {
+        let mut rtc = Rtc::new();
+
+        rtc.tick(CYCLES_PER_SECOND - 1);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 0);
+
+        rtc.tick(1);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 1);
+    }
+
+    #[test]
+    fn test_latch() {
+        let mut rtc = Rtc::new();
+
+        rtc.latch();
+        rtc.tick(CYCLES_PER_SECOND * 3);
+        assert_eq!(rtc.read(SECONDS), 0);
+
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 3);
+    }
+
+    #[test]
+    fn test_rollover() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(SECONDS, 59);
+        rtc.write(MINUTES, 59);
+        rtc.write(HOURS, 23);
+        rtc.write(DAYS_LOW, 0xFF);
+        rtc.write(DAYS_HIGH, 0x01);
+        rtc.tick(CYCLES_PER_SECOND);
+        rtc.latch();
+
+        assert_eq!(rtc.read(SECONDS), 0);
+        assert_eq!(rtc.read(MINUTES), 0);
+        assert_eq!(rtc.read(HOURS), 0);
+        assert_eq!(rtc.read(DAYS_LOW), 0);
+        assert_eq!(rtc.read(DAYS_HIGH), CARRY);
+    }
+
+    #[test]
+    fn test_invalid_seconds_wrap() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(SECONDS, 63);
+        rtc.tick(CYCLES_PER_SECOND);
+        rtc.latch();
+
+        assert_eq!(rtc.read(SECONDS), 0);
+        assert_eq!(rtc.read(MINUTES), 0);
+    }
+
+    #[test]
+    fn test_halt() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(DAYS_HIGH, HALT);
+        rtc.tick(CYCLES_PER_SECOND * 2);
+        rtc.advance(100);
+        rtc.latch();
+
+        assert_eq!(rtc.read(SECONDS), 0);
+        assert_eq!(rtc.read(DAYS_HIGH), HALT);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut rtc = Rtc::new();
+        rtc.write(SECONDS, 10);
+        rtc.write(DAYS_LOW, 0x34);
+        rtc.write(DAYS_HIGH, 0x01 | CARRY);
+        rtc.latch();
+        rtc.write(MINUTES, 20);
+
+        let footer = rtc.save(1_000);
+        assert_eq!(footer.len(), FOOTER_SIZE);
+        assert_eq!(footer[4..8], [20, 0, 0, 0]);
+        assert_eq!(footer[20..24], [10, 0, 0, 0]);
+        assert_eq!(footer[40..48], 1_000u64.to_le_bytes());
+
+        let mut loaded = Rtc::load(&footer, 1_005).unwrap();
+        assert_eq!(loaded.read(SECONDS), 10);
+        assert_eq!(loaded.read(DAYS_HIGH), 0x01 | CARRY);
+        loaded.latch();
+        assert_eq!(loaded.read(SECONDS), 15);
+        assert_eq!(loaded.read(MINUTES), 20);
+        assert_eq!(loaded.read(DAYS_LOW), 0x34);
+    }
+
+    #[test]
+    fn test_load_short_footer() {
+        let mut footer = Rtc::new().save(0);
+        footer.truncate(FOOTER_SIZE_SHORT);
+        footer[40..44].copy_from_slice(&100u32.to_le_bytes());
+
+        let mut rtc = Rtc::load(&footer, 160).unwrap();
+        rtc.latch();
+        assert_eq!(rtc.read(MINUTES), 1);
+
+        assert!(Rtc::load(&footer[..10], 0).is_err());
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut rtc = Rtc::new();
+
+        rtc.advance(2 * 86400 + 3 * 3600 + 4 * 60 + 5);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 5);
+        assert_eq!(rtc.read(MINUTES), 4);
+        assert_eq!(rtc.read(HOURS), 3);
+        assert_eq!(rtc.read(DAYS_LOW), 2);
+
+        rtc.advance(510 * 86400);
+        rtc.latch();
+        assert_eq!(rtc.read(DAYS_LOW), 0);
+        assert_eq!(rtc.read(DAYS_HIGH), CARRY);
+    }
+}
@@ -2,14 +2,14 @@
 //!
 //! Implementation of the GameBoy's CPU, its registers and instructions.   
 //!
-//! Missing:
-//! - [ ] Timing
-//! - [ ] State management
-//! - [ ] CPU control instructions (halt, stop etc)
+//! The CPU does not advance the rest of the system itself: [Cpu::step] reports the T-cycles it
+//! consumed, and the scheduler ticks the memory bus accordingly.
 
 use self::registers::flags::Flags;
 use self::registers::{Register16, Register8, Registers};
+use super::joypad::P1;
 use super::memory::Memory;
+use super::timer::DIV;
 use crate::hardware::cpu::instructions::{
     At, Bit, Condition, Imm, Instruction, Opcode, Operand16, Operand8, Operation, Page0,
 };
@@ -20,6 +20,12 @@ pub mod fetch;
 pub mod instructions;
 pub mod registers;
 
+/// T-cycles elapsed while waiting in HALT or STOP mode, one M-cycle per step.
+const IDLE_CYCLES: u32 = 4;
+
+/// T-cycles spent servicing an interrupt: 2 idle M-cycles, 2 for the push and 1 for the jump.
+const INTERRUPT_CYCLES: u32 = 20;
+
 pub struct Cpu {
     /// General-purpose registers, Program Counter and Stack Pointer
     pub registers: Registers,
@@ -27,23 +33,29 @@ pub struct Cpu {
     /// CPU state
     state: State,
 
-    /// TODO
+    /// Memory bus
     memory: Memory,
+
+    /// Interrupt Master Enable flag, set when interrupts are serviced
+    ime: bool,
+
+    /// Set by `EI`, the Interrupt Master Enable flag is set after the next instruction
+    ime_pending: bool,
+
+    /// Set by the HALT bug, the `Program Counter` is not incremented after the next opcode fetch
+    halt_bug: bool,
 }
 
 /// CPU states
 pub enum State {
-    /// TODO
+    /// Fetches and executes instructions
     Running,
 
-    /// TODO
+    /// Waits for an interrupt, see `Cpu::halt`
     Halt,
 
-    /// TODO
+    /// Waits for a joypad input, see `Cpu::stop`
     Stop,
-
-    /// TODO
-    Interrupt,
 }
 
 impl Cpu {
@@ -53,9 +65,36 @@ impl Cpu {
             registers: Registers::new(),
             state: State::Running,
             memory,
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
         }
     }
 
+    /// Initializes CPU and *memory* as the boot ROM leaves them, to start the cartridge without
+    /// one
+    pub fn post_boot(mut memory: Memory) -> Self {
+        memory.post_boot();
+        let mut cpu = Self::new(memory);
+        cpu.registers = Registers::post_boot(cpu.memory.model());
+        cpu
+    }
+
+    /// Returns the memory bus
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Returns the memory bus, mutably
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Returns true in STOP mode, when the system clock is stopped
+    pub fn stopped(&self) -> bool {
+        matches!(self.state, State::Stop)
+    }
+
     /// Reads from the 8-bit immediate value from `Program Counter`.  
     /// Increments the `Program Counter` by 1.  
     fn read_imm8(&mut self) -> u8 {
@@ -95,30 +134,94 @@ impl Cpu {
         }
     }
 
-    /// TODO
-    pub fn step(&mut self) {
+    /// Services the pending interrupt with the highest priority if interrupts are enabled,
+    /// otherwise executes the next instruction.
+    /// In HALT mode, waits for an interrupt to be pending. In STOP mode, waits for a joypad input
+    /// line to go low.  
+    /// Returns the number of T-cycles consumed. Conditional instructions take longer when their
+    /// condition is true.
+    pub fn step(&mut self) -> u32 {
         match self.state {
             State::Running => {
+                if self.ime && self.memory.interrupts.pending() != 0 {
+                    self.dispatch();
+                    return INTERRUPT_CYCLES;
+                }
+                let enable_ime = self.ime_pending;
                 let (opcode, size) = match self.fetch(self.registers.pc) {
                     Ok(t) => t,
                     Err(msg) => panic!("{}", msg),
                 };
-                self.registers.pc += size;
+                self.registers.pc += size - std::mem::take(&mut self.halt_bug) as u16;
                 let instruction = Cpu::decode(opcode);
+                let taken = self.check_condition(instruction.operation);
                 self.execute(instruction);
+                if enable_ime && self.ime_pending {
+                    self.ime = true;
+                    self.ime_pending = false;
+                }
+                match taken {
+                    true => instruction.cycles[0].cycles(),
+                    false => instruction.cycles[1].cycles(),
+                }
             }
             State::Halt => {
-                todo!();
-            }
-            State::Interrupt => {
-                todo!();
+                if self.memory.interrupts.pending() != 0 {
+                    self.state = State::Running;
+                }
+                IDLE_CYCLES
             }
             State::Stop => {
-                todo!();
+                if self.memory.read8(P1) & 0x0F != 0x0F {
+                    self.state = State::Running;
+                }
+                IDLE_CYCLES
             }
         }
     }
 
+    /// Returns true if the condition of *operation* holds, or if *operation* is unconditional.
+    /// Must be called before the operation is executed, as executing it may update the flags.
+    fn check_condition(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::Jp(condition, _)
+            | Operation::Jr(condition)
+            | Operation::Call(condition, _)
+            | Operation::Ret(condition) => self.registers.f.check_condition(condition),
+            _ => true,
+        }
+    }
+
+    /// Pushes `Program Counter` on the stack and jumps to the vector of the pending interrupt with
+    /// the highest priority, clearing its request and the Interrupt Master Enable flag.
+    /// The interrupt is chosen after the upper byte of `Program Counter` is pushed: if that write
+    /// lands on `IE` and disables every pending interrupt, the dispatch is canceled and
+    /// `Program Counter` is set to `0x0000`.
+    /// When the HALT bug hits an `EI` followed by `HALT`, the address of `HALT` is pushed so that
+    /// it is executed again on return.
+    fn dispatch(&mut self) {
+        self.ime = false;
+        let mut pc = self.registers.pc;
+        if std::mem::take(&mut self.halt_bug) {
+            pc = pc.wrapping_sub(1);
+        }
+        let [lo, hi] = pc.to_le_bytes();
+
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.memory.write8(self.registers.sp, hi);
+        let interrupt = self.memory.interrupts.next();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.memory.write8(self.registers.sp, lo);
+
+        self.registers.pc = match interrupt {
+            Some(interrupt) => {
+                self.memory.interrupts.acknowledge(interrupt);
+                interrupt.vector()
+            }
+            None => 0x0000,
+        };
+    }
+
     fn execute(&mut self, instruction: Instruction) {
         match instruction.operation {
             Operation::Load8(dst, src) => self.load8(dst, src),
@@ -853,34 +956,47 @@ impl Cpu {
     }
 
     /// Stops the system clock and enters HALT mode.  
-    /// HALT mode is canceled by an interrupt or a reset signal.  
+    /// HALT mode is canceled when an interrupt is pending, whether or not interrupts are enabled.
+    /// If interrupts are enabled, the interrupt is serviced, otherwise execution resumes after
+    /// `HALT`.  
     /// Although the system clock is stopped in this state, the oscillator circuit and LCD
     /// controller continue to operate.  
+    /// If interrupts are disabled and an interrupt is already pending, HALT mode is not entered
+    /// and the HALT bug occurs: the byte following `HALT` is read twice.
     fn halt(&mut self) {
-        self.state = State::Halt;
-        todo!();
+        if !self.ime && self.memory.interrupts.pending() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.state = State::Halt;
+        }
     }
 
     /// Does nothing.  
-    fn nop(&mut self) {
-        todo!();
-    }
+    fn nop(&mut self) {}
+
     /// Stops both the system clock and the oscillator circuit.  
     /// Stop mode stops the LCD controller.  
-    /// Stop mode is canceled by a reset signal.
+    /// Stop mode is canceled when one of the joypad input lines of `P1` goes low.  
+    /// The byte following `STOP` is skipped and the divider register `DIV` is reset.  
+    /// On the CGB, if a speed switch is prepared in `KEY1`, the CPU speed is switched instead of
+    /// entering Stop mode.
     fn stop(&mut self) {
-        self.state = State::Stop;
-        todo!();
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        self.memory.write8(DIV, 0x00);
+        if !self.memory.switch_speed() {
+            self.state = State::Stop;
+        }
     }
 
-    /// Disables interrupts
+    /// Disables interrupts, canceling a pending `EI`.
     fn di(&mut self) {
-        todo!();
+        self.ime = false;
+        self.ime_pending = false;
     }
 
-    /// Enables interrupts
+    /// Enables interrupts once the next instruction is executed.
     fn ei(&mut self) {
-        todo!();
+        self.ime_pending = true;
     }
 
     // Jump instructions
@@ -933,7 +1049,7 @@ impl Cpu {
         let address = self.memory.read16(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(2);
         self.registers.pc = address;
-        //ime = 1;
+        self.ime = true;
     }
 
     /// Loads the `Program Counter` into the memory stack and loads the page0 memory address onto
@@ -1046,6 +1162,29 @@ impl Cpu {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::gameboy::SpeedMode;
+    use crate::hardware::cartridge::header::{build_rom, checksum};
+    use crate::hardware::cartridge::Cartridge;
+    use crate::hardware::interrupts::Interrupt;
+    use crate::hardware::ppu::Renderer;
+
+    /// Constructs a memory bus with a ROM-only cartridge whose first bytes are *data*.
+    fn memory(data: Vec<u8>) -> Memory {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[..data.len()].copy_from_slice(&data);
+        Memory::new(Cartridge::new(rom).unwrap(), Renderer::Scanline)
+    }
+
+    /// Constructs a memory bus with *data* mapped both in ROM and at the start of Work RAM, so
+    /// that instructions writing through an address can be checked.
+    fn memory_with_wram(data: Vec<u8>) -> Memory {
+        let mut memory = memory(data.clone());
+        for (offset, byte) in data.into_iter().enumerate() {
+            memory.write8(0xC000 + offset as u16, byte);
+        }
+        memory
+    }
+
     #[test]
     fn test_get_operand8() {
         let mut cpu = Cpu {
@@ -1062,7 +1201,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
         };
         assert_eq!(cpu.get_operand8(Operand8::A), cpu.registers.a);
         assert_eq!(cpu.get_operand8(Operand8::E), cpu.registers.e);
@@ -1089,7 +1231,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
         };
         assert_eq!(
             cpu.get_operand16(Operand16::AF),
@@ -1126,7 +1271,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
         };
         assert_eq!(
             cpu.get_address(At::BC),
@@ -1156,7 +1304,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
         };
         cpu.pop(Operand16::BC);
         assert_eq!(cpu.registers.read16(Register16::BC), cpu.memory.read16(0));
@@ -1188,7 +1339,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
         };
         cpu.pop(Operand16::SP);
     }
@@ -1205,20 +1359,35 @@ mod tests {
                 f: Flags::empty(),
                 h: 6,
                 l: 7,
-                sp: 11,
+                sp: 0xC00B,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
         cpu.push(Operand16::BC);
-        assert_eq!(cpu.registers.read16(Register16::BC), cpu.memory.read16(10));
+        assert_eq!(
+            cpu.registers.read16(Register16::BC),
+            cpu.memory.read16(0xC00A)
+        );
         cpu.push(Operand16::HL);
-        assert_eq!(cpu.registers.read16(Register16::HL), cpu.memory.read16(8));
+        assert_eq!(
+            cpu.registers.read16(Register16::HL),
+            cpu.memory.read16(0xC008)
+        );
         cpu.push(Operand16::DE);
-        assert_eq!(cpu.registers.read16(Register16::DE), cpu.memory.read16(6));
+        assert_eq!(
+            cpu.registers.read16(Register16::DE),
+            cpu.memory.read16(0xC006)
+        );
         cpu.push(Operand16::AF);
-        assert_eq!(cpu.registers.read16(Register16::AF), cpu.memory.read16(4));
+        assert_eq!(
+            cpu.registers.read16(Register16::AF),
+            cpu.memory.read16(0xC004)
+        );
     }
     #[test]
     #[should_panic(expected = "Not a pair of 8-bit registers")]
@@ -1237,7 +1406,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![0, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43]),
         };
         cpu.push(Operand16::SP);
     }
@@ -1258,7 +1430,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.load16(Operand16::BC, Operand16::SP);
@@ -1291,7 +1466,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
         cpu.load16(Operand16::AF, Operand16::AF);
     }
@@ -1312,7 +1490,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
         cpu.load_u8(Operand8::L, cpu.memory.read8(0));
         assert_eq!(cpu.registers.l, 10);
@@ -1344,7 +1525,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
         cpu.load_u8(Operand8::Imm8, cpu.memory.read8(0));
     }
@@ -1359,13 +1543,16 @@ mod tests {
                 d: 0,
                 e: 16,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 11,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.dec8(Operand8::A);
@@ -1383,7 +1570,7 @@ mod tests {
         assert!(!cpu.registers.f.contains(Flags::C));
 
         cpu.dec8(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read8(3), 238);
+        assert_eq!(cpu.memory.read8(0xC003), 238);
         assert!(!cpu.registers.f.contains(Flags::Z));
         assert!(cpu.registers.f.contains(Flags::N));
         assert!(!cpu.registers.f.contains(Flags::H));
@@ -1407,13 +1594,16 @@ mod tests {
                 d: 0xFF,
                 e: 15,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 11,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.inc8(Operand8::A);
@@ -1431,7 +1621,7 @@ mod tests {
         assert!(!cpu.registers.f.contains(Flags::C));
 
         cpu.inc8(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read8(3), 240);
+        assert_eq!(cpu.memory.read8(0xC003), 240);
         assert!(!cpu.registers.f.contains(Flags::Z));
         assert!(!cpu.registers.f.contains(Flags::N));
         assert!(cpu.registers.f.contains(Flags::H));
@@ -1461,7 +1651,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.cp(Operand8::A);
@@ -1509,7 +1702,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.or(Operand8::A);
@@ -1549,7 +1745,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.xor(Operand8::B);
@@ -1590,7 +1789,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.and(Operand8::H);
@@ -1631,7 +1833,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         assert_eq!(cpu.sub_u8(0xff), 11);
@@ -1674,7 +1879,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.sbc(Operand8::D);
@@ -1721,7 +1929,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.add_u8_to_a(2);
@@ -1763,7 +1974,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.add8(Operand8::B);
@@ -1804,7 +2018,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.adc(Operand8::B);
@@ -1845,7 +2062,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.cpl();
@@ -1887,7 +2107,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         assert!(!cpu.registers.f.contains(Flags::Z));
@@ -1925,7 +2148,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![10, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         assert!(!cpu.registers.f.contains(Flags::Z));
@@ -1963,7 +2189,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.load_hl();
@@ -1990,7 +2219,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rlca();
@@ -2017,7 +2249,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rla();
@@ -2044,7 +2279,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rrca();
@@ -2071,7 +2309,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 239, 94, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rr(Operand8::A);
@@ -2098,7 +2339,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rlc(Operand8::B);
@@ -2126,13 +2370,16 @@ mod tests {
                 d: 0,
                 e: 16,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 0xFFF8,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 17, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![2, 255, 147, 17, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rl(Operand8::B);
@@ -2143,7 +2390,7 @@ mod tests {
         assert!(cpu.registers.f.contains(Flags::C));
 
         cpu.rl(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read16(3), 0x22);
+        assert_eq!(cpu.memory.read16(0xC003), 0x22);
         assert!(!cpu.registers.f.contains(Flags::Z));
         assert!(!cpu.registers.f.contains(Flags::N));
         assert!(!cpu.registers.f.contains(Flags::H));
@@ -2166,7 +2413,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rrc(Operand8::B);
@@ -2194,13 +2444,16 @@ mod tests {
                 d: 0,
                 e: 16,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 0xFFF8,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 138, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![2, 255, 147, 138, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rr(Operand8::A);
@@ -2211,7 +2464,7 @@ mod tests {
         assert!(cpu.registers.f.contains(Flags::C));
 
         cpu.rr(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read16(3), 0x45);
+        assert_eq!(cpu.memory.read16(0xC003), 0x45);
         assert!(!cpu.registers.f.contains(Flags::Z));
         assert!(!cpu.registers.f.contains(Flags::N));
         assert!(!cpu.registers.f.contains(Flags::H));
@@ -2228,13 +2481,16 @@ mod tests {
                 d: 0x80,
                 e: 16,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 0xFFF8,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 255, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![2, 255, 147, 255, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.sla(Operand8::D);
@@ -2245,7 +2501,7 @@ mod tests {
         assert!(cpu.registers.f.contains(Flags::C));
 
         cpu.sla(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read16(3), 0xFE);
+        assert_eq!(cpu.memory.read16(0xC003), 0xFE);
         assert!(!cpu.registers.f.contains(Flags::Z));
         assert!(!cpu.registers.f.contains(Flags::N));
         assert!(!cpu.registers.f.contains(Flags::H));
@@ -2262,13 +2518,16 @@ mod tests {
                 d: 0x8A,
                 e: 16,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 0xFFF8,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 1, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![2, 255, 147, 1, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.sra(Operand8::D);
@@ -2279,7 +2538,7 @@ mod tests {
         assert!(!cpu.registers.f.contains(Flags::C));
 
         cpu.sra(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read16(3), 0x00);
+        assert_eq!(cpu.memory.read16(0xC003), 0x00);
         assert!(cpu.registers.f.contains(Flags::Z));
         assert!(!cpu.registers.f.contains(Flags::N));
         assert!(!cpu.registers.f.contains(Flags::H));
@@ -2296,13 +2555,16 @@ mod tests {
                 d: 0x8A,
                 e: 16,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 0xFFF8,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 255, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![2, 255, 147, 255, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.srl(Operand8::A);
@@ -2313,7 +2575,7 @@ mod tests {
         assert!(cpu.registers.f.contains(Flags::C));
 
         cpu.srl(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read16(3), 0x7F);
+        assert_eq!(cpu.memory.read16(0xC003), 0x7F);
         assert!(!cpu.registers.f.contains(Flags::Z));
         assert!(!cpu.registers.f.contains(Flags::N));
         assert!(!cpu.registers.f.contains(Flags::H));
@@ -2329,13 +2591,16 @@ mod tests {
                 d: 0x8A,
                 e: 16,
                 f: Flags::empty(),
-                h: 0,
+                h: 0xC0,
                 l: 3,
                 sp: 0xFFF8,
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.swap(Operand8::A);
@@ -2346,7 +2611,7 @@ mod tests {
         assert!(!cpu.registers.f.contains(Flags::C));
 
         cpu.swap(Operand8::Addr(At::HL));
-        assert_eq!(cpu.memory.read16(3), 0x0F);
+        assert_eq!(cpu.memory.read16(0xC003), 0x0F);
         assert!(!cpu.registers.f.contains(Flags::Z));
         assert!(!cpu.registers.f.contains(Flags::N));
         assert!(!cpu.registers.f.contains(Flags::H));
@@ -2369,7 +2634,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.bit(Bit::Seven, Operand8::A);
@@ -2403,7 +2671,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.set(Bit::Two, Operand8::A);
@@ -2437,7 +2708,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.res(Bit::Seven, Operand8::A);
@@ -2471,7 +2745,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         assert!(!cpu.registers.f.contains(Flags::Z));
@@ -2508,7 +2785,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         assert!(!cpu.registers.f.contains(Flags::Z));
@@ -2545,7 +2825,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 255, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.jp(Condition::Always, Operand16::HL);
@@ -2579,7 +2862,10 @@ mod tests {
                 pc: 0,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 55, 147, 0xF0, 2, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![2, 55, 147, 0xF0, 2, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.jr(Condition::Always);
@@ -2608,15 +2894,18 @@ mod tests {
                 f: Flags::empty(),
                 h: 0,
                 l: 0x3B,
-                sp: 0x3,
+                sp: 0xC003,
                 pc: 0x800,
             },
             state: State::Running,
-            memory: Memory::new(vec![2, 55, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory_with_wram(vec![2, 55, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
         cpu.call(Condition::Always, Operand16::DE);
         assert_eq!(cpu.registers.pc, 0x4);
-        assert_eq!(cpu.registers.sp, 0x1);
+        assert_eq!(cpu.registers.sp, 0xC001);
         assert_eq!(cpu.memory.read16(cpu.registers.sp), 0x803);
     }
 
@@ -2636,7 +2925,10 @@ mod tests {
                 pc: 0x0,
             },
             state: State::Running,
-            memory: Memory::new(vec![5, 0, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![5, 0, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
         cpu.ret(Condition::Always);
         //assert_eq!(cpu.registers.pc, 0x03);
@@ -2659,10 +2951,223 @@ mod tests {
                 pc: 0x0,
             },
             state: State::Running,
-            memory: Memory::new(vec![5, 0, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
+            ime: false,
+            ime_pending: false,
+            halt_bug: false,
+            memory: memory(vec![5, 0, 147, 0xF0, 0, 38, 23, 3, 34, 213, 99, 43, 13]),
         };
 
         cpu.rst(Page0::Byte1);
         assert_eq!(cpu.registers.pc, 0x0008);
     }
+
+    #[test]
+    fn test_ei_delay() {
+        // EI, INC B, INC B
+        let mut cpu = Cpu::new(memory(vec![0xFB, 0x04, 0x04]));
+        cpu.registers.sp = 0xD000;
+        cpu.memory.interrupts.enable = 0x01;
+        cpu.memory.interrupts.request(Interrupt::VBlank);
+
+        cpu.step();
+        assert!(!cpu.ime);
+        cpu.step();
+        assert!(cpu.ime);
+        assert_eq!(cpu.registers.b, 1);
+
+        cpu.step();
+        assert!(!cpu.ime);
+        assert_eq!(cpu.registers.b, 1);
+        assert_eq!(cpu.registers.pc, 0x0040);
+        assert_eq!(cpu.registers.sp, 0xCFFE);
+        assert_eq!(cpu.memory.read16(0xCFFE), 0x0002);
+        assert_eq!(cpu.memory.interrupts.read_flag(), 0xE0);
+    }
+
+    #[test]
+    fn test_di_cancels_ei() {
+        // EI, DI, INC B
+        let mut cpu = Cpu::new(memory(vec![0xFB, 0xF3, 0x04]));
+        cpu.memory.interrupts.enable = 0x01;
+        cpu.memory.interrupts.request(Interrupt::VBlank);
+
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert!(!cpu.ime);
+        assert_eq!(cpu.registers.pc, 0x0003);
+    }
+
+    #[test]
+    fn test_dispatch_priority() {
+        let mut cpu = Cpu::new(memory(vec![0x04]));
+        cpu.registers.sp = 0xD000;
+        cpu.ime = true;
+        cpu.memory.interrupts.enable = 0x1C;
+        cpu.memory.interrupts.write_flag(0x1E);
+
+        cpu.step();
+        assert_eq!(cpu.registers.pc, Interrupt::Timer.vector());
+        assert_eq!(cpu.memory.interrupts.read_flag(), 0xFA);
+    }
+
+    #[test]
+    fn test_reti() {
+        let mut cpu = Cpu::new(memory(vec![0x04]));
+        cpu.registers.sp = 0xD000;
+        cpu.memory.write16(0xD000, 0x1234);
+
+        cpu.reti();
+        assert!(cpu.ime);
+        assert_eq!(cpu.registers.pc, 0x1234);
+        assert_eq!(cpu.registers.sp, 0xD002);
+    }
+
+    #[test]
+    fn test_dispatch_ie_push() {
+        let mut cpu = Cpu::new(memory(vec![0x04]));
+        cpu.registers.sp = 0x0000;
+        cpu.registers.pc = 0x0200;
+        cpu.ime = true;
+        cpu.memory.interrupts.enable = 0x01;
+        cpu.memory.interrupts.request(Interrupt::VBlank);
+
+        // The upper byte of PC overwrites IE with 0x02, disabling VBlank
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0000);
+        assert_eq!(cpu.memory.interrupts.enable, 0x02);
+        assert_eq!(cpu.memory.interrupts.read_flag(), 0xE1);
+
+        // Writing 0x02 to IE leaves STAT enabled, which is serviced instead
+        cpu.registers.sp = 0x0000;
+        cpu.registers.pc = 0x0200;
+        cpu.ime = true;
+        cpu.memory.interrupts.enable = 0x01;
+        cpu.memory.interrupts.request(Interrupt::Stat);
+        cpu.step();
+        assert_eq!(cpu.registers.pc, Interrupt::Stat.vector());
+    }
+
+    #[test]
+    fn test_halt_wake_without_ime() {
+        // HALT, INC B
+        let mut cpu = Cpu::new(memory(vec![0x76, 0x04]));
+        cpu.memory.interrupts.enable = 0x04;
+
+        cpu.step();
+        assert!(matches!(cpu.state, State::Halt));
+        cpu.step();
+        assert!(matches!(cpu.state, State::Halt));
+
+        cpu.memory.interrupts.request(Interrupt::Timer);
+        cpu.step();
+        assert!(matches!(cpu.state, State::Running));
+        cpu.step();
+        assert_eq!(cpu.registers.b, 1);
+        assert_eq!(cpu.registers.pc, 0x0002);
+    }
+
+    #[test]
+    fn test_halt_wake_with_ime() {
+        // HALT, INC B
+        let mut cpu = Cpu::new(memory(vec![0x76, 0x04]));
+        cpu.registers.sp = 0xD000;
+        cpu.ime = true;
+        cpu.memory.interrupts.enable = 0x01;
+
+        cpu.step();
+        cpu.memory.interrupts.request(Interrupt::VBlank);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0040);
+        assert_eq!(cpu.memory.read16(0xCFFE), 0x0001);
+    }
+
+    #[test]
+    fn test_halt_bug() {
+        // HALT, INC B, INC C
+        let mut cpu = Cpu::new(memory(vec![0x76, 0x04, 0x0C]));
+        cpu.memory.interrupts.enable = 0x01;
+        cpu.memory.interrupts.request(Interrupt::VBlank);
+
+        cpu.step();
+        assert!(matches!(cpu.state, State::Running));
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.b, 2);
+        assert_eq!(cpu.registers.c, 1);
+        assert_eq!(cpu.registers.pc, 0x0003);
+    }
+
+    #[test]
+    fn test_halt_bug_after_ei() {
+        // EI, HALT
+        let mut cpu = Cpu::new(memory(vec![0xFB, 0x76]));
+        cpu.registers.sp = 0xD000;
+        cpu.memory.interrupts.enable = 0x01;
+        cpu.memory.interrupts.request(Interrupt::VBlank);
+
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0040);
+        assert_eq!(cpu.memory.read16(0xCFFE), 0x0001);
+    }
+
+    #[test]
+    fn test_stop() {
+        // STOP, 0x00
+        let mut cpu = Cpu::new(memory(vec![0x10, 0x00, 0x04]));
+
+        cpu.step();
+        assert!(matches!(cpu.state, State::Stop));
+        assert_eq!(cpu.registers.pc, 0x0002);
+
+        cpu.memory.interrupts.enable = 0x1F;
+        cpu.memory.interrupts.write_flag(0x1F);
+        cpu.step();
+        assert!(matches!(cpu.state, State::Stop));
+    }
+
+    #[test]
+    fn test_stop_speed_switch() {
+        // STOP, 0x00, INC B
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[..3].copy_from_slice(&[0x10, 0x00, 0x04]);
+        rom[0x0143] = 0xC0;
+        rom[0x014D] = checksum(&rom);
+        let mut cpu = Cpu::new(Memory::new(
+            Cartridge::new(rom).unwrap(),
+            Renderer::Scanline,
+        ));
+        cpu.memory.write8(0xFF4D, 0x01);
+
+        cpu.step();
+        assert!(matches!(cpu.state, State::Running));
+        assert_eq!(cpu.memory.speed(), SpeedMode::DOUBLE);
+        cpu.step();
+        assert_eq!(cpu.registers.b, 1);
+    }
+
+    #[test]
+    fn test_post_boot() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[0x0143] = 0x80;
+        rom[0x014D] = checksum(&rom);
+        let cpu = Cpu::post_boot(Memory::new(
+            Cartridge::new(rom).unwrap(),
+            Renderer::Scanline,
+        ));
+
+        assert_eq!(cpu.registers.pc, 0x0100);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+        assert_eq!(cpu.registers.a, 0x11);
+        assert_eq!(cpu.memory.read8(0xFF40), 0x91);
+        assert_eq!(cpu.memory.read8(0xFF47), 0xFC);
+
+        let cpu = Cpu::post_boot(memory(vec![]));
+        assert_eq!(cpu.registers.a, 0x01);
+        assert_eq!(cpu.registers.read16(Register16::AF), 0x01B0);
+    }
 }
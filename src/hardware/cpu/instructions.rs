@@ -38,6 +38,22 @@ pub enum Clock {
     ThirtyTwo,
 }
 
+impl Clock {
+    /// Returns the number of T-cycles the clock represents.
+    pub const fn cycles(&self) -> u32 {
+        match self {
+            Clock::None => 0,
+            Clock::Four => 4,
+            Clock::Eight => 8,
+            Clock::Twelve => 12,
+            Clock::Sixteen => 16,
+            Clock::Twenty => 20,
+            Clock::TwentyFour => 24,
+            Clock::ThirtyTwo => 32,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum Page0 {
     Byte0 = 0x0000,
@@ -5,6 +5,7 @@
 //! Also implements the four 16-bit registers `AF`, `BC`, `DE` and `HL`.  
 //!
 
+use crate::gameboy::Model;
 use crate::hardware::cpu::instructions::Operand16;
 use crate::hardware::cpu::registers::flags::Flags;
 
@@ -96,6 +97,28 @@ impl Registers {
         }
     }
 
+    /// Constructs the registers as the boot ROM of *model* leaves them, when jumping to the
+    /// cartridge entry point.  
+    /// `A` tells the model to the cartridge: `0x01` on a DMG, `0x11` on a CGB.  
+    pub fn post_boot(model: Model) -> Self {
+        let [a, f, b, c, d, e, h, l] = match model {
+            Model::DMG => [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::CGB => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        };
+        Self {
+            a,
+            b,
+            c,
+            d,
+            e,
+            f: Flags::from_bits_truncate(f),
+            h,
+            l,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+
     /// Reads the 16-bit value of *r16* from the values stored in the 8-bit register pair.
     /// The first 8-bit register of the pair becomes the most significant byte of the 16-bit
     /// returned value.  
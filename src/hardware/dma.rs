@@ -0,0 +1,125 @@
+//! OAM DMA.
+//!
+//! Writing the DMA register (`0xFF46`) copies the 160 bytes at `XX00-XX9F` into the Object
+//! Attribute Memory, where `XX` is the value written. Sources at `0xE000` and above read the
+//! Work RAM, as the echo RAM does.
+//!
+//! The transfer starts one M-cycle after the write, then copies one byte per M-cycle for 160
+//! M-cycles. Meanwhile the external bus is busy, so the CPU can only access the High RAM and the
+//! I/O registers, every other read returns `0xFF`.
+//!
+//! Writing the register during a transfer restarts it from the first byte with the new source.
+//!
+
+/// Address of the DMA register.
+pub const DMA: u16 = 0xFF46;
+
+/// Number of bytes copied, the size of the Object Attribute Memory.
+const LENGTH: u16 = 0xA0;
+
+#[derive(Default)]
+pub struct Dma {
+    /// The DMA register, the upper byte of the source address.
+    register: u8,
+
+    /// Source address of a transfer requested during the last M-cycle.
+    starting: Option<u16>,
+
+    /// Source address of the transfer in progress.
+    source: Option<u16>,
+
+    /// Index of the next byte copied.
+    index: u16,
+}
+
+impl Dma {
+    /// Constructs an idle DMA controller.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the DMA register.
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    /// Writes *value* to the DMA register, requesting a transfer from `value * 0x100`.
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+        let source = (value as u16) << 8;
+        self.starting = Some(match source {
+            0xE000..=0xFFFF => source - 0x2000,
+            _ => source,
+        });
+    }
+
+    /// Returns true while a transfer holds the external bus.
+    pub fn active(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Advances the DMA by one M-cycle.
+    /// Returns the source address and the OAM index of the byte to copy during this M-cycle.
+    pub fn step(&mut self) -> Option<(u16, usize)> {
+        if let Some(source) = self.starting.take() {
+            self.source = Some(source);
+            self.index = 0;
+            return None;
+        }
+
+        let source = self.source?;
+        let transfer = (source + self.index, self.index as usize);
+        self.index += 1;
+        if self.index == LENGTH {
+            self.source = None;
+        }
+        Some(transfer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer() {
+        let mut dma = Dma::new();
+
+        dma.write(0xC1);
+        assert_eq!(dma.read(), 0xC1);
+        assert!(!dma.active());
+        assert_eq!(dma.step(), None);
+        assert!(dma.active());
+
+        assert_eq!(dma.step(), Some((0xC100, 0)));
+        for index in 1..0x9F {
+            assert_eq!(dma.step(), Some((0xC100 + index as u16, index)));
+        }
+        assert_eq!(dma.step(), Some((0xC19F, 0x9F)));
+        assert!(!dma.active());
+        assert_eq!(dma.step(), None);
+    }
+
+    #[test]
+    fn test_echo_source() {
+        let mut dma = Dma::new();
+
+        dma.write(0xFE);
+        dma.step();
+        assert_eq!(dma.step(), Some((0xDE00, 0)));
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut dma = Dma::new();
+
+        dma.write(0xC0);
+        for _ in 0..50 {
+            dma.step();
+        }
+        dma.write(0xD0);
+        assert!(dma.active());
+        assert_eq!(dma.step(), None);
+        assert_eq!(dma.step(), Some((0xD000, 0)));
+    }
+}
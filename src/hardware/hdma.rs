@@ -0,0 +1,186 @@
+//! CGB Video RAM DMA.
+//!
+//! Copies blocks of 16 bytes from ROM or RAM into the Video RAM bank selected by VBK. Its
+//! registers are:
+//!
+//! | Address  | Register | Content                                                       |
+//! |----------|----------|---------------------------------------------------------------|
+//! | `0xFF51` | HDMA1    | Source, upper byte                                            |
+//! | `0xFF52` | HDMA2    | Source, lower byte, the lower 4 bits are ignored              |
+//! | `0xFF53` | HDMA3    | Destination, upper byte, the upper 3 bits are ignored         |
+//! | `0xFF54` | HDMA4    | Destination, lower byte, the lower 4 bits are ignored         |
+//! | `0xFF55` | HDMA5    | Bit 7: mode, bits 0-6: number of blocks minus 1               |
+//!
+//! Writing HDMA5 starts a transfer:
+//! - with bit 7 cleared, a general purpose transfer copies every block at once, while the CPU is
+//!   stalled.
+//! - with bit 7 set, an HBlank transfer copies one block at the start of each HBlank, stalling
+//!   the CPU for that time. Writing HDMA5 with bit 7 cleared during an HBlank transfer
+//!   terminates it.
+//!
+//! Each block takes 8 M-cycles at normal speed and 16 M-cycles in double speed, the same real
+//! time. Reading HDMA5 returns the number of blocks left minus 1, with bit 7 set once the
+//! transfer is over or terminated, so that `0xFF` reports a completed transfer. The other
+//! registers are write only.
+//!
+
+/// Address of the source upper byte register.
+pub const HDMA1: u16 = 0xFF51;
+
+/// Address of the source lower byte register.
+pub const HDMA2: u16 = 0xFF52;
+
+/// Address of the destination upper byte register.
+pub const HDMA3: u16 = 0xFF53;
+
+/// Address of the destination lower byte register.
+pub const HDMA4: u16 = 0xFF54;
+
+/// Address of the length, mode and start register.
+pub const HDMA5: u16 = 0xFF55;
+
+/// Number of bytes in one block.
+pub const BLOCK_SIZE: u16 = 0x10;
+
+/// T-cycles needed to copy one block at normal speed.
+pub const BLOCK_CYCLES: u32 = 32;
+
+/// HDMA5: the transfer mode, HBlank if set, general purpose otherwise.
+const HDMA5_HBLANK: u8 = 0b1000_0000;
+
+/// HDMA5: the number of blocks minus 1.
+const HDMA5_LENGTH: u8 = 0b0111_1111;
+
+/// Enumerates the transfer modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    General,
+    HBlank,
+}
+
+#[derive(Default)]
+pub struct Hdma {
+    /// Address of the next byte copied.
+    source: u16,
+
+    /// Address in Video RAM of the next byte written.
+    destination: u16,
+
+    /// Number of blocks left to copy.
+    blocks: u8,
+
+    /// The mode of the transfer in progress.
+    mode: Option<Mode>,
+}
+
+impl Hdma {
+    /// Constructs an idle Video RAM DMA.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the mode of the transfer in progress, if any.
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    /// Reads the register at *address*, in the range `0xFF51-0xFF55`.
+    pub fn read(&self, address: u16) -> u8 {
+        let length = self.blocks.wrapping_sub(1) & HDMA5_LENGTH;
+        match (address, self.mode) {
+            (HDMA5, Some(_)) => length,
+            (HDMA5, None) => HDMA5_HBLANK | length,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes *value* to the register at *address*, in the range `0xFF51-0xFF55`.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            HDMA1 => self.source = (value as u16) << 8 | (self.source & 0x00FF),
+            HDMA2 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3 => self.destination = ((value & 0x1F) as u16) << 8 | (self.destination & 0x00FF),
+            HDMA4 => self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5 if self.mode == Some(Mode::HBlank) && value & HDMA5_HBLANK == 0 => {
+                self.mode = None;
+            }
+            HDMA5 => {
+                self.blocks = (value & HDMA5_LENGTH) + 1;
+                self.mode = match value & HDMA5_HBLANK {
+                    0 => Some(Mode::General),
+                    _ => Some(Mode::HBlank),
+                };
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns the source address and the Video RAM address of the next block to copy, then
+    /// moves to the following block.
+    pub fn next_block(&mut self) -> Option<(u16, u16)> {
+        self.mode?;
+        let block = (self.source, 0x8000 | self.destination);
+
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = (self.destination + BLOCK_SIZE) & 0x1FF0;
+        self.blocks -= 1;
+        if self.blocks == 0 {
+            self.mode = None;
+        }
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Constructs a Video RAM DMA copying from `0xC123` to `0x9FE0`.
+    fn hdma() -> Hdma {
+        let mut hdma = Hdma::new();
+        hdma.write(HDMA1, 0xC1);
+        hdma.write(HDMA2, 0x23);
+        hdma.write(HDMA3, 0xFF);
+        hdma.write(HDMA4, 0xE5);
+        hdma
+    }
+
+    #[test]
+    fn test_registers() {
+        let hdma = hdma();
+
+        assert_eq!(hdma.read(HDMA1), 0xFF);
+        assert_eq!(hdma.read(HDMA4), 0xFF);
+        assert_eq!(hdma.read(HDMA5), 0xFF);
+        assert_eq!(hdma.mode(), None);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut hdma = hdma();
+
+        hdma.write(HDMA5, 0x02);
+        assert_eq!(hdma.mode(), Some(Mode::General));
+        assert_eq!(hdma.read(HDMA5), 0x02);
+        assert_eq!(hdma.next_block(), Some((0xC120, 0x9FE0)));
+        assert_eq!(hdma.next_block(), Some((0xC130, 0x9FF0)));
+        assert_eq!(hdma.read(HDMA5), 0x00);
+        assert_eq!(hdma.next_block(), Some((0xC140, 0x8000)));
+        assert_eq!(hdma.read(HDMA5), 0xFF);
+        assert_eq!(hdma.next_block(), None);
+    }
+
+    #[test]
+    fn test_hblank_termination() {
+        let mut hdma = hdma();
+
+        hdma.write(HDMA5, 0x83);
+        assert_eq!(hdma.mode(), Some(Mode::HBlank));
+        hdma.next_block();
+        assert_eq!(hdma.read(HDMA5), 0x02);
+
+        hdma.write(HDMA5, 0x00);
+        assert_eq!(hdma.mode(), None);
+        assert_eq!(hdma.read(HDMA5), 0x82);
+    }
+}
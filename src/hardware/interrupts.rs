@@ -0,0 +1,146 @@
+//! Interrupt controller.
+//!
+//! Peripherals request an interrupt by setting its bit in the Interrupt Flag register `IF`
+//! (`0xFF0F`). The CPU services a requested interrupt when its bit is also set in the Interrupt
+//! Enable register `IE` (`0xFFFF`) and the Interrupt Master Enable flag is set.
+//!
+//! When several interrupts are pending, the lowest bit has the highest priority:
+//!
+//! | Bit | Interrupt | Vector   |
+//! |-----|-----------|----------|
+//! | 0   | VBlank    | `0x0040` |
+//! | 1   | STAT      | `0x0048` |
+//! | 2   | Timer     | `0x0050` |
+//! | 3   | Serial    | `0x0058` |
+//! | 4   | Joypad    | `0x0060` |
+//!
+//! The upper 3 bits of `IF` are not wired and always read as 1.
+//!
+
+/// Mask of the bits of `IF` and `IE` wired to an interrupt source.
+const SOURCES: u8 = 0b0001_1111;
+
+/// Enumerates the interrupt sources, in priority order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    /// All interrupt sources, from the highest priority to the lowest.
+    const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// Returns the bit of the interrupt in `IF` and `IE`.
+    pub const fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Returns the address the CPU jumps to when servicing the interrupt.
+    pub const fn vector(self) -> u16 {
+        0x0040 + 8 * self as u16
+    }
+}
+
+#[derive(Default)]
+pub struct Interrupts {
+    /// The Interrupt Enable register `IE`.
+    /// All 8 bits are readable and writable, only the lower 5 are wired.
+    pub enable: u8,
+
+    /// The requested interrupts, the lower 5 bits of `IF`.
+    flag: u8,
+}
+
+impl Interrupts {
+    /// Constructs the controller with no interrupt enabled nor requested.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the bit of *interrupt* in `IF`.
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.flag |= interrupt.mask();
+    }
+
+    /// Clears the bit of *interrupt* in `IF`, once the CPU services it.
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.flag &= !interrupt.mask();
+    }
+
+    /// Returns the interrupts both requested and enabled.
+    pub fn pending(&self) -> u8 {
+        self.enable & self.flag & SOURCES
+    }
+
+    /// Returns the pending interrupt with the highest priority, if any.
+    pub fn next(&self) -> Option<Interrupt> {
+        let pending = self.pending();
+        Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+    }
+
+    /// Reads the `IF` register.
+    pub fn read_flag(&self) -> u8 {
+        self.flag | !SOURCES
+    }
+
+    /// Writes the `IF` register.
+    pub fn write_flag(&mut self, value: u8) {
+        self.flag = value & SOURCES;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector() {
+        assert_eq!(Interrupt::VBlank.vector(), 0x0040);
+        assert_eq!(Interrupt::Stat.vector(), 0x0048);
+        assert_eq!(Interrupt::Timer.vector(), 0x0050);
+        assert_eq!(Interrupt::Serial.vector(), 0x0058);
+        assert_eq!(Interrupt::Joypad.vector(), 0x0060);
+    }
+
+    #[test]
+    fn test_flag_register() {
+        let mut interrupts = Interrupts::new();
+
+        assert_eq!(interrupts.read_flag(), 0xE0);
+        interrupts.request(Interrupt::Timer);
+        assert_eq!(interrupts.read_flag(), 0xE4);
+        interrupts.write_flag(0xFF);
+        assert_eq!(interrupts.read_flag(), 0xFF);
+        interrupts.acknowledge(Interrupt::VBlank);
+        assert_eq!(interrupts.read_flag(), 0xFE);
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut interrupts = Interrupts::new();
+
+        interrupts.request(Interrupt::Joypad);
+        interrupts.request(Interrupt::Stat);
+        assert_eq!(interrupts.next(), None);
+
+        interrupts.enable = 0x1F;
+        assert_eq!(interrupts.next(), Some(Interrupt::Stat));
+        interrupts.acknowledge(Interrupt::Stat);
+        assert_eq!(interrupts.next(), Some(Interrupt::Joypad));
+
+        interrupts.enable = 0xE0;
+        assert_eq!(interrupts.pending(), 0);
+    }
+}
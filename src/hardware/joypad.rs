@@ -0,0 +1,171 @@
+//! Joypad.
+//!
+//! The 8 buttons are wired in a matrix of two groups of 4 input lines, read through the joypad
+//! register `P1` (`0xFF00`):
+//!
+//! | Bit | Content                        | Direction group | Action group |
+//! |-----|--------------------------------|-----------------|--------------|
+//! | 5   | Select the action group        |                 |              |
+//! | 4   | Select the direction group     |                 |              |
+//! | 3   | Input line 3, read only        | Down            | Start        |
+//! | 2   | Input line 2, read only        | Up              | Select       |
+//! | 1   | Input line 1, read only        | Left            | B            |
+//! | 0   | Input line 0, read only        | Right           | A            |
+//!
+//! Every bit is active low: a group is selected when its bit is cleared, and an input line
+//! reads 0 when a button of a selected group is pressed. When both groups are selected, the
+//! lines combine both. The upper 2 bits are not wired and read as 1.
+//!
+//! Whenever an input line goes from high to low, the joypad interrupt is requested.
+//!
+
+use super::interrupts::{Interrupt, Interrupts};
+
+/// Address of the joypad register.
+pub const P1: u16 = 0xFF00;
+
+/// P1: the bits selecting the groups.
+const P1_SELECT: u8 = 0b0011_0000;
+
+/// P1: selects the direction group when cleared.
+const P1_DIRECTIONS: u8 = 0b0001_0000;
+
+/// P1: selects the action group when cleared.
+const P1_ACTIONS: u8 = 0b0010_0000;
+
+/// P1: the input lines.
+const P1_LINES: u8 = 0b0000_1111;
+
+/// Enumerates the buttons, the direction group first, in input line order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// Returns the bit of the button in the pressed buttons, the direction group in the lower
+    /// nibble and the action group in the upper one.
+    const fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+#[derive(Default)]
+pub struct Joypad {
+    /// The group selection bits of `P1`, as written.
+    select: u8,
+
+    /// The pressed buttons, one bit per [Button].
+    pressed: u8,
+}
+
+impl Joypad {
+    /// Constructs a joypad with no button pressed and both groups selected.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `P1`.
+    pub fn read(&self) -> u8 {
+        !P1_LINES & !P1_SELECT | self.select | !self.lines() & P1_LINES
+    }
+
+    /// Writes *value* to `P1`, only the selection bits are writable.
+    /// Selecting a group with a pressed button requests the joypad interrupt.
+    pub fn write(&mut self, value: u8, interrupts: &mut Interrupts) {
+        let before = self.lines();
+        self.select = value & P1_SELECT;
+        self.request_on_press(before, interrupts);
+    }
+
+    /// Presses *button*, requesting the joypad interrupt if its group is selected.
+    pub fn press(&mut self, button: Button, interrupts: &mut Interrupts) {
+        let before = self.lines();
+        self.pressed |= button.mask();
+        self.request_on_press(before, interrupts);
+    }
+
+    /// Releases *button*.
+    pub fn release(&mut self, button: Button) {
+        self.pressed &= !button.mask();
+    }
+
+    /// Returns the input lines pulled low by the pressed buttons of the selected groups, as set
+    /// bits.
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & P1_DIRECTIONS == 0 {
+            lines |= self.pressed & P1_LINES;
+        }
+        if self.select & P1_ACTIONS == 0 {
+            lines |= self.pressed >> 4;
+        }
+        lines
+    }
+
+    /// Requests the joypad interrupt if an input line went low since *before*.
+    fn request_on_press(&self, before: u8, interrupts: &mut Interrupts) {
+        if self.lines() & !before != 0 {
+            interrupts.request(Interrupt::Joypad);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+
+        assert_eq!(joypad.read(), 0xCF);
+        joypad.press(Button::Down, &mut interrupts);
+        joypad.press(Button::A, &mut interrupts);
+        assert_eq!(joypad.read(), 0xC6);
+
+        joypad.write(P1_ACTIONS, &mut interrupts);
+        assert_eq!(joypad.read(), 0xE7);
+        joypad.write(P1_DIRECTIONS, &mut interrupts);
+        assert_eq!(joypad.read(), 0xDE);
+        joypad.write(P1_SELECT, &mut interrupts);
+        assert_eq!(joypad.read(), 0xFF);
+
+        joypad.write(P1_ACTIONS, &mut interrupts);
+        joypad.release(Button::Down);
+        assert_eq!(joypad.read(), 0xEF);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.enable = Interrupt::Joypad.mask();
+
+        // Not selected
+        joypad.write(P1_ACTIONS, &mut interrupts);
+        joypad.press(Button::Start, &mut interrupts);
+        assert_eq!(interrupts.pending(), 0);
+
+        // Selecting the group pulls the line low
+        joypad.write(P1_DIRECTIONS, &mut interrupts);
+        assert_eq!(interrupts.pending(), Interrupt::Joypad.mask());
+        interrupts.acknowledge(Interrupt::Joypad);
+
+        // The line is already low
+        joypad.press(Button::Start, &mut interrupts);
+        joypad.release(Button::Start);
+        assert_eq!(interrupts.pending(), 0);
+
+        joypad.press(Button::Select, &mut interrupts);
+        assert_eq!(interrupts.pending(), Interrupt::Joypad.mask());
+    }
+}
@@ -1,48 +1,735 @@
+//! Memory bus.
+//!
+//! Decodes the 16-bit address space of the GameBoy and routes every access to the region that
+//! owns it.
+//!
+//! | Range           | Region                                                  |
+//! |-----------------|---------------------------------------------------------|
+//! | `0x0000-0x3FFF` | ROM bank 0, always mapped                               |
+//! | `0x4000-0x7FFF` | ROM bank N, switchable                                  |
+//! | `0x8000-0x9FFF` | Video RAM (tile data and background maps)               |
+//! | `0xA000-0xBFFF` | Cartridge RAM                                           |
+//! | `0xC000-0xCFFF` | Work RAM bank 0                                         |
+//! | `0xD000-0xDFFF` | Work RAM bank 1, switchable from 1 to 7 on the CGB      |
+//! | `0xE000-0xFDFF` | Echo RAM, mirror of `0xC000-0xDDFF`                     |
+//! | `0xFE00-0xFE9F` | Object Attribute Memory                                 |
+//! | `0xFEA0-0xFEFF` | Unused, reads return `0xFF` and writes are ignored      |
+//! | `0xFF00-0xFF7F` | I/O registers, see below                                |
+//! | `0xFF80-0xFFFE` | High RAM                                                |
+//! | `0xFFFF`        | Interrupt Enable register                               |
+//!
+//! The I/O registers of the following components are routed to them:
+//! - `0xFF10-0xFF3F`: the APU, see [super::apu], which also owns the wave RAM.
+//! - `0xFF00`: the joypad, see [super::joypad].
+//! - `0xFF01-0xFF02`: the serial port, see [super::serial].
+//! - `0xFF04-0xFF07`: the timer, see [super::timer].
+//! - `0xFF40-0xFF45`, `0xFF47-0xFF4B`, and on the CGB `0xFF4F` and `0xFF68-0xFF6B`: the PPU, see
+//!   [super::ppu], which also owns the Video RAM and the Object Attribute Memory.
+//! - `0xFF0F`: the Interrupt Flag register, see [super::interrupts].
+//! - `0xFF46`: the OAM DMA, see [super::dma]. While it runs, the CPU can only access the High RAM
+//!   and the I/O registers.
+//! - `0xFF51-0xFF55` on the CGB: the Video RAM DMA, see [super::hdma]. The CPU cycles it stalls
+//!   are collected with [Memory::take_stall].
+//!
+//! While a boot ROM is mapped, it hides the start of the cartridge ROM, until a non-zero value is
+//! written to BANK (`0xFF50`), see [super::bootrom].
+//!
+//! The hardware model follows the boot ROM if there is one, otherwise the CGB flag of the
+//! cartridge header: games supporting the CGB run on a CGB, which selects the Work RAM bank
+//! mapped at `0xD000` with SVBK (`0xFF70`).
+//!
+//! The CGB also switches the CPU to double speed: setting bit 0 of KEY1 (`0xFF4D`) prepares the
+//! switch, which the next `STOP` instruction performs. Bit 7 of KEY1 reports the current speed.
+//! The timer, the serial port and the OAM DMA follow the CPU clock, while the PPU, the APU and
+//! the cartridge clock keep their normal rate.
+//!
+
+use super::apu::Apu;
+use super::bootrom::{self, Bootrom};
+use super::cartridge::header::CgbSupport;
+use super::cartridge::Cartridge;
+use super::dma::{Dma, DMA};
+use super::hdma::{self, Hdma};
+use super::interrupts::Interrupts;
+use super::joypad::{Joypad, P1};
+use super::ppu::{Ppu, Renderer};
+use super::serial::{self, Serial};
+use super::timer::Timer;
+use crate::gameboy::{Model, SpeedMode};
+
+use std::mem;
+
+/// Size of one Work RAM bank.
+const WRAM_BANK_SIZE: usize = 0x1000;
+
+/// Number of Work RAM banks on the CGB, the DMG only uses the first 2.
+const WRAM_BANKS: usize = 8;
+
+/// Address of the Work RAM bank register, CGB only.
+const SVBK: u16 = 0xFF70;
+
+/// Address of the speed switch register, CGB only.
+const KEY1: u16 = 0xFF4D;
+
+/// KEY1: the current speed, double if set.
+const KEY1_SPEED: u8 = 0b1000_0000;
+
+/// KEY1: the speed switch is prepared.
+const KEY1_PREPARE: u8 = 0b0000_0001;
+
+/// Size of the I/O registers area.
+const IO_SIZE: usize = 0x80;
+
+/// Size of the High RAM.
+const HRAM_SIZE: usize = 0x7F;
+
+/// The I/O registers as the boot ROM leaves them, by address. The sound is powered on first so
+/// that the APU takes the other writes, and its channels are set up without being triggered.
+const POST_BOOT_IO: [(u16, u8); 17] = [
+    (0xFF26, 0x80), // NR52
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF16, 0x3F), // NR21
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF20, 0xFF), // NR41
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF40, 0x91), // LCDC
+    (0xFF47, 0xFC), // BGP
+    (0xFF48, 0xFF), // OBP0
+    (0xFF49, 0xFF), // OBP1
+];
+
+/// Value returned when reading an address nothing drives.
+const OPEN_BUS: u8 = 0xFF;
+
 pub struct Memory {
-    memory: Vec<u8>,
-    //0x0000 - 0x00FF : Boot ROM then interrupt table
-    //0x0000 - 0x3FFF : Bank0, always the same memory, cannot be switched out later
-    //0x4000 - 0x7FFF : Bank N, for bank switching
-    //0x8000 - 0x9FFF : Tile RAM
-    //0x9800 - 0x9FFF : Background Map
-    //0xA000 - 0xBFFF : Cartridge RAM
-    //0xC000 - 0xDFFF : Working RAM
-    //0xE000 - 0xFDFF : Echo RAM
-    //0xFE00 - 0xFE9F : Object Attribute Memory
-    //0xFEA0 - 0xFEFF : Unused
-    //0xFF00 - 0xFF7F : I/O registers
-    //0xFF80 - 0xFFFE : High RAM Area
-    //0xFFFF : Interrupt Enabled Register
+    /// The hardware model emulated.
+    model: Model,
+
+    /// The CPU speed mode.
+    speed: SpeedMode,
+
+    /// Set when a speed switch is prepared through KEY1.
+    speed_switch: bool,
+
+    /// Cartridge, mapped at `0x0000-0x7FFF` and `0xA000-0xBFFF`.
+    pub cartridge: Cartridge,
+
+    /// Boot ROM, mapped over the cartridge until unmapped through BANK.
+    bootrom: Option<Bootrom>,
+
+    /// Work RAM, mapped at `0xC000-0xDFFF` and mirrored at `0xE000-0xFDFF`.
+    wram: [u8; WRAM_BANK_SIZE * WRAM_BANKS],
+
+    /// The SVBK register, the Work RAM bank mapped at `0xD000`.
+    svbk: u8,
+
+    /// I/O registers, mapped at `0xFF00-0xFF7F`.
+    io: [u8; IO_SIZE],
+
+    /// High RAM, mapped at `0xFF80-0xFFFE`.
+    hram: [u8; HRAM_SIZE],
+
+    /// PPU, owning the Video RAM at `0x8000-0x9FFF` and the Object Attribute Memory at
+    /// `0xFE00-0xFE9F`, its registers are mapped at `0xFF40-0xFF4B`.
+    pub ppu: Ppu,
+
+    /// Joypad, its register is mapped at `0xFF00`.
+    pub joypad: Joypad,
+
+    /// Serial port, its registers are mapped at `0xFF01-0xFF02`.
+    pub serial: Serial,
+
+    /// Timer, its registers are mapped at `0xFF04-0xFF07`.
+    pub timer: Timer,
+
+    /// APU, its registers and the wave RAM are mapped at `0xFF10-0xFF3F`.
+    pub apu: Apu,
+
+    /// OAM DMA, its register is mapped at `0xFF46`.
+    pub dma: Dma,
+
+    /// Video RAM DMA, CGB only, its registers are mapped at `0xFF51-0xFF55`.
+    pub hdma: Hdma,
+
+    /// CPU T-cycles stalled by the Video RAM DMA, not yet run.
+    stall: u32,
+
+    /// Interrupt controller, its `IF` register is mapped at `0xFF0F` and `IE` at `0xFFFF`.
+    pub interrupts: Interrupts,
 }
 
 impl Memory {
-    pub fn new(data: Vec<u8>) -> Self {
-        Self { memory: data }
+    /// Constructs the memory bus with *cartridge* plugged in, the PPU drawing with *renderer*.
+    /// The hardware model is a CGB if the cartridge supports it, a DMG otherwise.
+    /// Every RAM region starts cleared.
+    pub fn new(cartridge: Cartridge, renderer: Renderer) -> Self {
+        let model = match cartridge.header.cgb {
+            CgbSupport::None => Model::DMG,
+            CgbSupport::Compatible | CgbSupport::Only => Model::CGB,
+        };
+        Self::with_model(cartridge, renderer, model)
+    }
+
+    /// Constructs the memory bus with *cartridge* plugged in and *bootrom* mapped, the PPU
+    /// drawing with *renderer*. The hardware model is the one of the boot ROM.
+    /// The DMG compatibility mode of the CGB is not emulated: a cartridge without CGB support
+    /// runs on a DMG without the CGB boot ROM instead.
+    /// Every RAM region starts cleared.
+    pub fn with_bootrom(cartridge: Cartridge, renderer: Renderer, bootrom: Bootrom) -> Self {
+        if bootrom.model() == Model::CGB && cartridge.header.cgb == CgbSupport::None {
+            return Self::with_model(cartridge, renderer, Model::DMG);
+        }
+        let mut memory = Self::with_model(cartridge, renderer, bootrom.model());
+        memory.bootrom = Some(bootrom);
+        memory
     }
 
-    /// Reads the 8-bit value at address pc
-    pub fn read8(&self, pc: u16) -> u8 {
-        self.memory[pc as usize]
+    /// Constructs the memory bus of *model* with *cartridge* plugged in, the PPU drawing with
+    /// *renderer*.
+    fn with_model(cartridge: Cartridge, renderer: Renderer, model: Model) -> Self {
+        Self {
+            model,
+            speed: SpeedMode::NORMAL,
+            speed_switch: false,
+            cartridge,
+            bootrom: None,
+            wram: [0; WRAM_BANK_SIZE * WRAM_BANKS],
+            svbk: 0,
+            io: [0; IO_SIZE],
+            hram: [0; HRAM_SIZE],
+            ppu: Ppu::new(renderer, model),
+            joypad: Joypad::new(),
+            serial: Serial::new(model),
+            timer: Timer::new(),
+            apu: Apu::new(),
+            dma: Dma::new(),
+            hdma: Hdma::new(),
+            stall: 0,
+            interrupts: Interrupts::new(),
+        }
     }
 
-    /// Reads the 16-bit value at address pc
-    /// Returns a native endian value
-    pub fn read16(&self, pc: u16) -> u16 {
-        let hi = self.read8(pc);
-        let lo = self.read8(pc + 1);
-        u16::from_le_bytes([hi, lo])
+    /// Writes the I/O registers as the boot ROM leaves them, to start the cartridge without one.
+    pub fn post_boot(&mut self) {
+        for (address, value) in POST_BOOT_IO {
+            self.write8(address, value);
+        }
     }
 
-    /// Writes at address pc the u8 value given as parameter
-    pub fn write8(&mut self, pc: u16, value: u8) {
-        self.memory[pc as usize] = value;
+    /// Returns the hardware model emulated.
+    pub fn model(&self) -> Model {
+        self.model
     }
 
-    /// Write at address pc the u16 value converted into little endian
-    pub fn write16(&mut self, pc: u16, value: u16) {
+    /// Returns true if a boot ROM is mapped, the CPU then starting in it.
+    pub fn bootrom_mapped(&self) -> bool {
+        self.bootrom.is_some()
+    }
+
+    /// Returns true if a boot ROM is mapped at *address*.
+    fn bootrom_maps(&self, address: u16) -> bool {
+        matches!(&self.bootrom, Some(bootrom) if bootrom.maps(address))
+    }
+
+    /// Reads the 8-bit value of the boot ROM at *address*, which must be mapped.
+    fn read_bootrom(&self, address: u16) -> u8 {
+        self.bootrom
+            .as_ref()
+            .map_or(OPEN_BUS, |bootrom| bootrom.read(address))
+    }
+
+    /// Returns the CPU speed mode.
+    pub fn speed(&self) -> SpeedMode {
+        self.speed
+    }
+
+    /// Switches the CPU speed if a switch was prepared through KEY1, as `STOP` does.
+    /// Returns true if the speed was switched.
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch {
+            return false;
+        }
+        self.speed_switch = false;
+        self.speed = match self.speed {
+            SpeedMode::NORMAL => SpeedMode::DOUBLE,
+            SpeedMode::DOUBLE => SpeedMode::NORMAL,
+        };
+        true
+    }
+
+    /// Reads the 8-bit value at *address*.
+    /// Reading from the unused area, or outside of the High RAM and the I/O registers during an
+    /// OAM DMA, returns `0xFF`.
+    pub fn read8(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0xFEFF if self.dma.active() => OPEN_BUS,
+            _ => self.read_bus(address),
+        }
+    }
+
+    /// Reads the 8-bit value at *address*, regardless of the OAM DMA.
+    fn read_bus(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x08FF if self.bootrom_maps(address) => self.read_bootrom(address),
+            0x0000..=0x7FFF => self.cartridge.read_rom(address),
+            0x8000..=0x9FFF => self.ppu.read_vram(address),
+            0xA000..=0xBFFF => self.cartridge.read_ram(address),
+            0xC000..=0xFDFF => self.wram[self.wram_index(address)],
+            0xFE00..=0xFE9F => self.ppu.read_oam(address),
+            0xFEA0..=0xFEFF => OPEN_BUS,
+            P1 => self.joypad.read(),
+            serial::SB..=serial::SC => self.serial.read(address),
+            0xFF04..=0xFF07 => self.timer.read(address),
+            0xFF0F => self.interrupts.read_flag(),
+            0xFF10..=0xFF3F => self.apu.read(address),
+            DMA => self.dma.read(),
+            bootrom::BANK => OPEN_BUS,
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B | 0xFF4F | 0xFF68..=0xFF6B => self.ppu.read(address),
+            SVBK if self.model == Model::CGB => self.svbk | 0xF8,
+            KEY1 if self.model == Model::CGB => self.read_key1(),
+            hdma::HDMA1..=hdma::HDMA5 if self.model == Model::CGB => self.hdma.read(address),
+            0xFF03..=0xFF7F => self.io[(address - 0xFF00) as usize],
+            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize],
+            0xFFFF => self.interrupts.enable,
+        }
+    }
+
+    /// Reads the 16-bit little endian value at *address*.
+    /// Returns a native endian value.
+    pub fn read16(&self, address: u16) -> u16 {
+        let lo = self.read8(address);
+        let hi = self.read8(address.wrapping_add(1));
+        u16::from_le_bytes([lo, hi])
+    }
+
+    /// Writes the 8-bit *value* at *address*.
+    /// Writes to the ROM area are sent to the cartridge, writes to the unused area, or outside of
+    /// the High RAM and the I/O registers during an OAM DMA, are ignored.
+    pub fn write8(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0xFEFF if self.dma.active() => (),
+            0x0000..=0x7FFF => self.cartridge.write_rom(address, value),
+            0x8000..=0x9FFF => self.ppu.write_vram(address, value),
+            0xA000..=0xBFFF => self.cartridge.write_ram(address, value),
+            0xC000..=0xFDFF => self.wram[self.wram_index(address)] = value,
+            0xFE00..=0xFE9F => self.ppu.write_oam(address, value),
+            0xFEA0..=0xFEFF => (),
+            P1 => self.joypad.write(value, &mut self.interrupts),
+            serial::SB..=serial::SC => self.serial.write(address, value),
+            0xFF04..=0xFF07 => self.timer.write(address, value),
+            0xFF0F => self.interrupts.write_flag(value),
+            0xFF10..=0xFF3F => self.apu.write(address, value),
+            DMA => self.dma.write(value),
+            bootrom::BANK if value != 0 => self.bootrom = None,
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B | 0xFF4F | 0xFF68..=0xFF6B => {
+                self.ppu.write(address, value)
+            }
+            SVBK if self.model == Model::CGB => self.svbk = value & 0x07,
+            KEY1 if self.model == Model::CGB => self.speed_switch = value & KEY1_PREPARE != 0,
+            hdma::HDMA1..=hdma::HDMA5 if self.model == Model::CGB => {
+                self.write_hdma(address, value)
+            }
+            0xFF03..=0xFF7F => self.io[(address - 0xFF00) as usize] = value,
+            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize] = value,
+            0xFFFF => self.interrupts.enable = value,
+        }
+    }
+
+    /// Returns the CPU T-cycles stalled by the Video RAM DMA since the last call.
+    /// They must be run by ticking the bus again.
+    pub fn take_stall(&mut self) -> u32 {
+        mem::take(&mut self.stall)
+    }
+
+    /// Writes *value* to the Video RAM DMA register at *address*.
+    /// A general purpose transfer is run at once, an HBlank transfer started while the LCD is
+    /// off copies its first block at once.
+    fn write_hdma(&mut self, address: u16, value: u8) {
+        self.hdma.write(address, value);
+        match self.hdma.mode() {
+            Some(hdma::Mode::General) => while self.copy_hdma_block() {},
+            Some(hdma::Mode::HBlank) if address == hdma::HDMA5 && !self.ppu.enabled() => {
+                self.copy_hdma_block();
+            }
+            _ => (),
+        }
+    }
+
+    /// Copies the next block of the Video RAM DMA, and stalls the CPU for its duration.
+    /// Returns false if no block was left.
+    fn copy_hdma_block(&mut self) -> bool {
+        let (source, destination) = match self.hdma.next_block() {
+            Some(block) => block,
+            None => return false,
+        };
+        for offset in 0..hdma::BLOCK_SIZE {
+            let value = self.read_bus(source.wrapping_add(offset));
+            self.ppu.write_vram(destination + offset, value);
+        }
+        self.stall += match self.speed {
+            SpeedMode::NORMAL => hdma::BLOCK_CYCLES,
+            SpeedMode::DOUBLE => hdma::BLOCK_CYCLES * 2,
+        };
+        true
+    }
+
+    /// Reads the KEY1 register, the unused bits read as 1.
+    fn read_key1(&self) -> u8 {
+        let speed = match self.speed {
+            SpeedMode::NORMAL => 0,
+            SpeedMode::DOUBLE => KEY1_SPEED,
+        };
+        let prepare = match self.speed_switch {
+            true => KEY1_PREPARE,
+            false => 0,
+        };
+        !(KEY1_SPEED | KEY1_PREPARE) | speed | prepare
+    }
+
+    /// Returns the index in Work RAM of *address*, in `0xC000-0xFDFF`.
+    /// The upper half maps the bank selected by SVBK on the CGB, where bank 0 selects bank 1.
+    fn wram_index(&self, address: u16) -> usize {
+        let offset = (address as usize - 0xC000) % (WRAM_BANK_SIZE * 2);
+        if offset < WRAM_BANK_SIZE {
+            return offset;
+        }
+        let bank = match self.model {
+            Model::DMG => 1,
+            Model::CGB => (self.svbk as usize).max(1),
+        };
+        bank * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
+    }
+
+    /// Advances the components on the bus by *cycles* CPU T-cycles.
+    /// In double speed mode, the components on the normal clock advance by half as many cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        let normal_cycles = match self.speed {
+            SpeedMode::NORMAL => cycles,
+            SpeedMode::DOUBLE => cycles / 2,
+        };
+        self.cartridge.tick(normal_cycles);
+        self.timer.tick(cycles, &mut self.interrupts);
+        self.serial.tick(cycles, &mut self.interrupts);
+        self.ppu.tick(normal_cycles, &mut self.interrupts);
+        self.apu.tick(normal_cycles);
+        if self.ppu.take_hblank() && self.hdma.mode() == Some(hdma::Mode::HBlank) {
+            self.copy_hdma_block();
+        }
+        for _ in 0..cycles / 4 {
+            if let Some((source, index)) = self.dma.step() {
+                let value = self.read_bus(source);
+                self.ppu.write_oam_dma(index, value);
+            }
+        }
+    }
+
+    /// Writes at *address* the 16-bit *value* converted into little endian.
+    pub fn write16(&mut self, address: u16, value: u16) {
         let bytes = value.to_le_bytes();
 
-        self.memory[pc as usize] = bytes[0];
-        self.memory[pc as usize + 1] = bytes[1];
+        self.write8(address, bytes[0]);
+        self.write8(address.wrapping_add(1), bytes[1]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cartridge::header::{build_rom, checksum};
+    use super::super::interrupts::Interrupt;
+    use super::super::joypad::Button;
+    use super::*;
+
+    /// Constructs a memory bus with a 32 KiB ROM-only cartridge plugged in.
+    fn memory() -> Memory {
+        Memory::new(
+            Cartridge::new(build_rom(0x00, 0x00, 0x00)).unwrap(),
+            Renderer::Scanline,
+        )
+    }
+
+    /// Constructs a memory bus with a 32 KiB ROM-only cartridge supporting the CGB plugged in.
+    fn cgb_memory() -> Memory {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[0x0143] = 0x80;
+        rom[0x014D] = checksum(&rom);
+        Memory::new(Cartridge::new(rom).unwrap(), Renderer::Scanline)
+    }
+
+    #[test]
+    fn test_rom() {
+        let memory = memory();
+
+        assert_eq!(memory.read8(0x0000), 0);
+        assert_eq!(memory.read8(0x4000), 1);
+        assert_eq!(memory.read8(0x0147), 0x00);
+    }
+
+    #[test]
+    fn test_bootrom() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[0x0143] = 0x80;
+        rom[0x014D] = checksum(&rom);
+        let cartridge = Cartridge::new(rom).unwrap();
+        let bootrom = Bootrom::unverified(vec![0xAA; 0x100]).unwrap();
+        let mut memory = Memory::with_bootrom(cartridge, Renderer::Scanline, bootrom);
+
+        assert_eq!(memory.model(), Model::DMG);
+        assert_eq!(memory.read8(0x0000), 0xAA);
+        assert_eq!(memory.read8(0x0143), 0x80);
+        assert_eq!(memory.read8(0x0200), 0x00);
+        memory.write8(bootrom::BANK, 0x00);
+        assert_eq!(memory.read8(0x00FF), 0xAA);
+        memory.write8(bootrom::BANK, 0x01);
+        assert_eq!(memory.read8(0x0000), 0x00);
+
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[0x0143] = 0xC0;
+        rom[0x014D] = checksum(&rom);
+        let cartridge = Cartridge::new(rom).unwrap();
+        let bootrom = Bootrom::unverified(vec![0xBB; 0x900]).unwrap();
+        let mut memory = Memory::with_bootrom(cartridge, Renderer::Scanline, bootrom);
+
+        assert_eq!(memory.model(), Model::CGB);
+        assert_eq!(memory.read8(0x0000), 0xBB);
+        assert_eq!(memory.read8(0x0147), 0x00);
+        assert_eq!(memory.read8(0x08FF), 0xBB);
+        assert_eq!(memory.read8(0x0900), 0x00);
+        memory.write8(bootrom::BANK, 0x11);
+        assert_eq!(memory.read8(0x0200), 0x00);
+
+        // Without the DMG compatibility mode, a DMG cartridge runs on a DMG without the boot ROM
+        let cartridge = Cartridge::new(build_rom(0x00, 0x00, 0x00)).unwrap();
+        let bootrom = Bootrom::unverified(vec![0xBB; 0x900]).unwrap();
+        let memory = Memory::with_bootrom(cartridge, Renderer::Scanline, bootrom);
+
+        assert_eq!(memory.model(), Model::DMG);
+        assert!(!memory.bootrom_mapped());
+        assert_eq!(memory.read8(0x0000), 0x00);
+    }
+
+    #[test]
+    fn test_write8_to_rom_is_ignored() {
+        let mut memory = memory();
+
+        memory.write8(0x4000, 0xAB);
+        assert_eq!(memory.read8(0x4000), 1);
+    }
+
+    #[test]
+    fn test_echo_ram() {
+        let mut memory = memory();
+
+        memory.write8(0xC123, 0x42);
+        assert_eq!(memory.read8(0xE123), 0x42);
+
+        memory.write8(0xFDFF, 0x24);
+        assert_eq!(memory.read8(0xDDFF), 0x24);
+    }
+
+    #[test]
+    fn test_unused_area() {
+        let mut memory = memory();
+
+        memory.write8(0xFEA0, 0x42);
+        assert_eq!(memory.read8(0xFEA0), OPEN_BUS);
+    }
+
+    #[test]
+    fn test_regions() {
+        let mut memory = memory();
+
+        for address in [0x8000, 0xC000, 0xFE00, 0xFF01, 0xFF80, 0xFFFF] {
+            memory.write8(address, 0x5A);
+            assert_eq!(memory.read8(address), 0x5A);
+        }
+    }
+
+    #[test]
+    fn test_p1() {
+        let mut memory = memory();
+
+        memory.write8(P1, 0x10);
+        assert_eq!(memory.read8(P1), 0xDF);
+        memory.joypad.press(Button::B, &mut memory.interrupts);
+        assert_eq!(memory.read8(P1), 0xDD);
+        assert_eq!(memory.read8(0xFF0F), 0xF0 | Interrupt::Joypad.mask());
+    }
+
+    #[test]
+    fn test_interrupt_registers() {
+        let mut memory = memory();
+
+        memory.write8(0xFF0F, 0x01);
+        assert_eq!(memory.read8(0xFF0F), 0xE1);
+
+        memory.write8(0xFFFF, 0x1F);
+        assert_eq!(memory.interrupts.pending(), 0x01);
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        let mut memory = memory();
+
+        memory.write8(0xFF07, 0x05);
+        memory.write8(0xFF05, 0xFF);
+        memory.write8(0xFFFF, 0x04);
+        memory.tick(20);
+        assert_eq!(memory.read8(0xFF0F), 0xE4);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut memory = memory();
+        for index in 0..0xA0 {
+            memory.write8(0xC100 + index, index as u8 ^ 0x5A);
+        }
+        memory.write8(0xFF80, 0x42);
+
+        memory.write8(0xFF46, 0xC1);
+        assert_eq!(memory.read8(0xFF46), 0xC1);
+        memory.tick(4);
+        assert_eq!(memory.read8(0xC100), OPEN_BUS);
+        assert_eq!(memory.read8(0xFF80), 0x42);
+        assert_eq!(memory.read8(0xFF46), 0xC1);
+        memory.write8(0xC000, 0x99);
+        memory.write8(0xFF81, 0x99);
+
+        memory.tick(159 * 4);
+        assert_eq!(memory.read8(0xC100), OPEN_BUS);
+        memory.tick(4);
+        assert_eq!(memory.read8(0xC100), 0x5A);
+        assert_eq!(memory.read8(0xC000), 0x00);
+        assert_eq!(memory.read8(0xFF81), 0x99);
+        for index in 0..0xA0 {
+            assert_eq!(memory.read8(0xFE00 + index), index as u8 ^ 0x5A);
+        }
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let mut memory = memory();
+        memory.write8(0xD000, 0x11);
+        memory.write8(SVBK, 0x03);
+        assert_eq!(memory.read8(0xD000), 0x11);
+
+        let mut memory = cgb_memory();
+        assert_eq!(memory.model(), Model::CGB);
+        assert_eq!(memory.read8(SVBK), 0xF8);
+
+        memory.write8(0xD000, 0x11);
+        memory.write8(SVBK, 0x02);
+        assert_eq!(memory.read8(SVBK), 0xFA);
+        assert_eq!(memory.read8(0xD000), 0x00);
+        memory.write8(0xD000, 0x22);
+        memory.write8(0xC000, 0x33);
+
+        memory.write8(SVBK, 0x00);
+        assert_eq!(memory.read8(0xD000), 0x11);
+        assert_eq!(memory.read8(0xF000), 0x11);
+        memory.write8(SVBK, 0x02);
+        assert_eq!(memory.read8(0xD000), 0x22);
+        assert_eq!(memory.read8(0xC000), 0x33);
+    }
+
+    #[test]
+    fn test_speed_switch() {
+        let mut memory = memory();
+        memory.write8(KEY1, 0x01);
+        assert_eq!(memory.read8(KEY1), 0x01);
+        assert!(!memory.switch_speed());
+
+        let mut memory = cgb_memory();
+        assert_eq!(memory.read8(KEY1), 0x7E);
+        assert!(!memory.switch_speed());
+
+        memory.write8(KEY1, 0x01);
+        assert_eq!(memory.read8(KEY1), 0x7F);
+        assert!(memory.switch_speed());
+        assert_eq!(memory.speed(), SpeedMode::DOUBLE);
+        assert_eq!(memory.read8(KEY1), 0xFE);
+    }
+
+    #[test]
+    fn test_double_speed_clocks() {
+        let mut memory = cgb_memory();
+        memory.write8(0xFF40, 0x80);
+        memory.write8(KEY1, 0x01);
+        memory.switch_speed();
+
+        memory.tick(456);
+        assert_eq!(memory.read8(0xFF44), 0);
+        memory.tick(456);
+        assert_eq!(memory.read8(0xFF44), 1);
+        assert_eq!(memory.read8(0xFF04), 3);
+    }
+
+    #[test]
+    fn test_general_hdma() {
+        let mut memory = cgb_memory();
+        for offset in 0..0x20 {
+            memory.write8(0xC000 + offset, offset as u8 + 1);
+        }
+        memory.write8(0xFF51, 0xC0);
+        memory.write8(0xFF52, 0x00);
+        memory.write8(0xFF53, 0x01);
+        memory.write8(0xFF54, 0x00);
+
+        memory.write8(0xFF55, 0x01);
+        assert_eq!(memory.read8(0xFF55), 0xFF);
+        assert_eq!(memory.take_stall(), 64);
+        assert_eq!(memory.take_stall(), 0);
+        assert_eq!(memory.read8(0x8100), 0x01);
+        assert_eq!(memory.read8(0x811F), 0x20);
+
+        memory.write8(KEY1, 0x01);
+        memory.switch_speed();
+        memory.write8(0xFF55, 0x00);
+        assert_eq!(memory.take_stall(), 64);
+    }
+
+    #[test]
+    fn test_hblank_hdma() {
+        let mut memory = cgb_memory();
+        for offset in 0..0x30 {
+            memory.write8(0xC000 + offset, offset as u8 + 1);
+        }
+        memory.write8(0xFF51, 0xC0);
+        memory.write8(0xFF53, 0x00);
+        memory.write8(0xFF40, 0x80);
+
+        memory.write8(0xFF55, 0x82);
+        assert_eq!(memory.read8(0xFF55), 0x02);
+        memory.tick(252);
+        assert_eq!(memory.read8(0xFF55), 0x01);
+        assert_eq!(memory.take_stall(), 32);
+
+        memory.tick(456);
+        assert_eq!(memory.read8(0xFF55), 0x00);
+        memory.write8(0xFF55, 0x00);
+        assert_eq!(memory.read8(0xFF55), 0x80);
+
+        memory.tick(456);
+        assert_eq!(memory.read8(0xFF55), 0x80);
+        memory.write8(0xFF40, 0x00);
+        assert_eq!(memory.read8(0x8000), 0x01);
+        assert_eq!(memory.read8(0x801F), 0x20);
+        assert_eq!(memory.read8(0x8020), 0x00);
+    }
+
+    #[test]
+    fn test_read16_write16() {
+        let mut memory = memory();
+
+        memory.write16(0xC000, 0xBEEF);
+        assert_eq!(memory.read8(0xC000), 0xEF);
+        assert_eq!(memory.read8(0xC001), 0xBE);
+        assert_eq!(memory.read16(0xC000), 0xBEEF);
     }
 }
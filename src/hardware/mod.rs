@@ -1,2 +1,12 @@
+pub mod apu;
+pub mod bootrom;
+pub mod cartridge;
 pub mod cpu;
+pub mod dma;
+pub mod hdma;
+pub mod interrupts;
+pub mod joypad;
 pub mod memory;
+pub mod ppu;
+pub mod serial;
+pub mod timer;
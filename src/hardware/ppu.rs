@@ -0,0 +1,910 @@
+//! Pixel Processing Unit.
+//!
+//! The PPU draws the screen line by line. Each of the 154 lines lasts 456 dots (T-cycles at
+//! normal speed): the 144 visible lines walk through modes 2, 3 and 0, while lines 144-153 are
+//! spent in mode 1.
+//!
+//! | Mode | Name     | Duration  | Access                                    |
+//! |------|----------|-----------|-------------------------------------------|
+//! | 2    | OAM scan | 80 dots   | OAM is blocked                            |
+//! | 3    | Drawing  | 172+ dots | OAM and VRAM are blocked                  |
+//! | 0    | HBlank   | 204- dots | Free                                      |
+//! | 1    | VBlank   | 10 lines  | Free                                      |
+//!
+//! The PPU registers are mapped at:
+//!
+//! | Address  | Register | Content                                                       |
+//! |----------|----------|---------------------------------------------------------------|
+//! | `0xFF40` | LCDC     | LCD control, see below                                        |
+//! | `0xFF41` | STAT     | Bits 3-6: STAT interrupt sources, bit 2: LY=LYC, bits 0-1: mode|
+//! | `0xFF42` | SCY      | Background vertical scroll                                    |
+//! | `0xFF43` | SCX      | Background horizontal scroll                                  |
+//! | `0xFF44` | LY       | Current line, read only                                       |
+//! | `0xFF45` | LYC      | Line compared with LY                                         |
+//! | `0xFF47` | BGP      | Background and window palette                                 |
+//! | `0xFF48` | OBP0     | Object palette 0                                              |
+//! | `0xFF49` | OBP1     | Object palette 1                                              |
+//! | `0xFF4A` | WY       | Window vertical position                                      |
+//! | `0xFF4B` | WX       | Window horizontal position, plus 7                            |
+//! | `0xFF4F` | VBK      | CGB only, bit 0: Video RAM bank                               |
+//! | `0xFF68` | BCPS     | CGB only, background palette specification, see [palette]    |
+//! | `0xFF69` | BCPD     | CGB only, background palette data                             |
+//! | `0xFF6A` | OCPS     | CGB only, object palette specification                        |
+//! | `0xFF6B` | OCPD     | CGB only, object palette data                                 |
+//!
+//! LCDC bits, from 7 to 0: LCD enable, window tile map, window enable, background and window
+//! tile data, background tile map, object size, object enable, background and window enable.
+//!
+//! On the CGB, the Video RAM has 2 banks. Bank 1 holds more tile data, and an attribute map
+//! alongside each tile map of bank 0:
+//!
+//! | Bit | Background attribute                                 | Object attribute (CGB)        |
+//! |-----|------------------------------------------------------|-------------------------------|
+//! | 7   | Colors 1-3 drawn over objects                        | Drawn behind colors 1-3       |
+//! | 6   | Vertical flip                                        | Vertical flip                 |
+//! | 5   | Horizontal flip                                      | Horizontal flip               |
+//! | 3   | Tile data bank                                       | Tile data bank                |
+//! | 0-2 | Color palette                                        | Color palette                 |
+//!
+//! CGB colors come from the palette RAMs instead of BGP, OBP0 and OBP1. Objects overlap in OAM
+//! order instead of by X, and LCDC bit 0 no longer hides the background: when cleared, objects
+//! are always drawn over it.
+//!
+//! The VBlank interrupt is requested when line 144 starts. The STAT interrupt sources, enabled by
+//! STAT bits 3 to 6, are ORed into a single internal STAT line:
+//!
+//! | STAT bit | Source                   |
+//! |----------|--------------------------|
+//! | 6        | LY=LYC                   |
+//! | 5        | Mode 2, OAM scan         |
+//! | 4        | Mode 1, VBlank           |
+//! | 3        | Mode 0, HBlank           |
+//!
+//! The STAT interrupt is only requested on the rising edge of that line, so a source becoming
+//! active while another one already holds the line high is blocked. On the DMG, writing STAT
+//! enables every source for one cycle, which may request a spurious STAT interrupt.
+//!
+//! Lines are drawn into a 160x144 framebuffer of `0xRRGGBB` colors by one of two renderers,
+//! selected when the PPU is constructed:
+//! - [scanline] draws each line at once at the end of mode 3, which always lasts 172 dots.
+//! - [fifo] draws each line one dot at a time through the pixel FIFO, so that registers written
+//!   during mode 3 take effect mid-line, and mode 3 lasts from 172 to 289 dots.
+//!
+
+use std::mem;
+
+use super::interrupts::{Interrupt, Interrupts};
+use crate::gameboy::Model;
+
+mod fifo;
+mod palette;
+mod scanline;
+
+use fifo::Fifo;
+use palette::PaletteRam;
+
+/// Width of the screen, in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+
+/// Height of the screen, in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// Address of the LCD control register.
+pub const LCDC: u16 = 0xFF40;
+
+/// Address of the LCD status register.
+pub const STAT: u16 = 0xFF41;
+
+/// Address of the background vertical scroll register.
+pub const SCY: u16 = 0xFF42;
+
+/// Address of the background horizontal scroll register.
+pub const SCX: u16 = 0xFF43;
+
+/// Address of the current line register.
+pub const LY: u16 = 0xFF44;
+
+/// Address of the line compare register.
+pub const LYC: u16 = 0xFF45;
+
+/// Address of the background and window palette.
+pub const BGP: u16 = 0xFF47;
+
+/// Address of the object palette 0.
+pub const OBP0: u16 = 0xFF48;
+
+/// Address of the object palette 1.
+pub const OBP1: u16 = 0xFF49;
+
+/// Address of the window vertical position register.
+pub const WY: u16 = 0xFF4A;
+
+/// Address of the window horizontal position register.
+pub const WX: u16 = 0xFF4B;
+
+/// Address of the Video RAM bank register.
+pub const VBK: u16 = 0xFF4F;
+
+/// Address of the background palette specification register.
+pub const BCPS: u16 = 0xFF68;
+
+/// Address of the background palette data register.
+pub const BCPD: u16 = 0xFF69;
+
+/// Address of the object palette specification register.
+pub const OCPS: u16 = 0xFF6A;
+
+/// Address of the object palette data register.
+pub const OCPD: u16 = 0xFF6B;
+
+/// Size of one Video RAM bank.
+const VRAM_SIZE: usize = 0x2000;
+
+/// Offset in Video RAM of the tile map at `0x9800`.
+const MAP_LOW: usize = 0x1800;
+
+/// Offset in Video RAM of the tile map at `0x9C00`.
+const MAP_HIGH: usize = 0x1C00;
+
+/// Offset in Video RAM of the signed tile data area base, `0x9000`.
+const SIGNED_TILE_BASE: i32 = 0x1000;
+
+/// Size of one tile in Video RAM: 8 lines of 2 bytes.
+const TILE_SIZE: usize = 16;
+
+/// Size of the Object Attribute Memory.
+const OAM_SIZE: usize = 0xA0;
+
+/// Number of dots in one line.
+const DOTS_PER_LINE: u32 = 456;
+
+/// Number of dots spent in mode 2.
+const OAM_SCAN_DOTS: u32 = 80;
+
+/// Number of dots spent in mode 3.
+const DRAWING_DOTS: u32 = 172;
+
+/// Number of lines in one frame, including the VBlank lines.
+const LINES: u8 = 154;
+
+/// Maximum number of objects drawn on one line.
+const OBJECTS_PER_LINE: usize = 10;
+
+/// Value returned when reading blocked memory.
+const OPEN_BUS: u8 = 0xFF;
+
+/// The colors of the four DMG shades, from white to black.
+pub const DMG_COLORS: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+/// LCDC: LCD enable.
+const LCD_ENABLE: u8 = 0b1000_0000;
+
+/// LCDC: window tile map, `0x9C00` if set, otherwise `0x9800`.
+const WINDOW_MAP: u8 = 0b0100_0000;
+
+/// LCDC: window enable.
+const WINDOW_ENABLE: u8 = 0b0010_0000;
+
+/// LCDC: background and window tile data, `0x8000` unsigned if set, otherwise `0x8800` signed.
+const TILE_DATA: u8 = 0b0001_0000;
+
+/// LCDC: background tile map, `0x9C00` if set, otherwise `0x9800`.
+const BG_MAP: u8 = 0b0000_1000;
+
+/// LCDC: object size, 8x16 if set, otherwise 8x8.
+const OBJ_SIZE: u8 = 0b0000_0100;
+
+/// LCDC: object enable.
+const OBJ_ENABLE: u8 = 0b0000_0010;
+
+/// LCDC: background and window enable.
+const BG_ENABLE: u8 = 0b0000_0001;
+
+/// STAT: the writable interrupt source bits.
+const STAT_SOURCES: u8 = 0b0111_1000;
+
+/// STAT: the LY=LYC flag.
+const STAT_COINCIDENCE: u8 = 0b0000_0100;
+
+/// STAT: LY=LYC interrupt source.
+const STAT_LYC: u8 = 0b0100_0000;
+
+/// STAT: mode 2 interrupt source.
+const STAT_OAM: u8 = 0b0010_0000;
+
+/// STAT: mode 1 interrupt source.
+const STAT_VBLANK: u8 = 0b0001_0000;
+
+/// STAT: mode 0 interrupt source.
+const STAT_HBLANK: u8 = 0b0000_1000;
+
+/// Object and background attributes: the background and window colors 1-3 are drawn over the
+/// object.
+const ATTR_PRIORITY: u8 = 0b1000_0000;
+
+/// Object and background attributes: vertical flip.
+const ATTR_FLIP_Y: u8 = 0b0100_0000;
+
+/// Object and background attributes: horizontal flip.
+const ATTR_FLIP_X: u8 = 0b0010_0000;
+
+/// Object attributes: the object uses OBP1 if set, otherwise OBP0, DMG only.
+const OBJ_PALETTE: u8 = 0b0001_0000;
+
+/// Object and background attributes: the tile data is in Video RAM bank 1, CGB only.
+const ATTR_BANK: u8 = 0b0000_1000;
+
+/// Object and background attributes: the color palette, CGB only.
+const ATTR_PALETTE: u8 = 0b0000_0111;
+
+/// Enumerates the PPU modes, reported in the lower 2 bits of STAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    HBlank,
+    VBlank,
+    OamScan,
+    Drawing,
+}
+
+/// Enumerates the renderers drawing the lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    Scanline,
+    Fifo,
+}
+
+/// An entry of the Object Attribute Memory.
+#[derive(Debug, Clone, Copy)]
+struct Object {
+    /// Vertical position on screen, plus 16.
+    y: u8,
+
+    /// Horizontal position on screen, plus 8.
+    x: u8,
+
+    /// Tile index, in the `0x8000` tile data area.
+    tile: u8,
+
+    /// Attribute flags.
+    flags: u8,
+}
+
+pub struct Ppu {
+    /// The hardware model emulated.
+    model: Model,
+
+    /// Video RAM, 2 banks on the CGB, mapped at `0x8000-0x9FFF`.
+    vram: [u8; VRAM_SIZE * 2],
+
+    /// The VBK register, the Video RAM bank mapped.
+    vbk: u8,
+
+    /// Object Attribute Memory, mapped at `0xFE00-0xFE9F`.
+    oam: [u8; OAM_SIZE],
+
+    /// The LCDC register.
+    lcdc: u8,
+
+    /// The interrupt source bits of the STAT register.
+    stat: u8,
+
+    /// The SCY register.
+    scy: u8,
+
+    /// The SCX register.
+    scx: u8,
+
+    /// The LY register.
+    ly: u8,
+
+    /// The LYC register.
+    lyc: u8,
+
+    /// The BGP register.
+    bgp: u8,
+
+    /// The OBP0 register.
+    obp0: u8,
+
+    /// The OBP1 register.
+    obp1: u8,
+
+    /// The WY register.
+    wy: u8,
+
+    /// The WX register.
+    wx: u8,
+
+    /// The background palette RAM, CGB only.
+    bg_palettes: PaletteRam,
+
+    /// The object palette RAM, CGB only.
+    obj_palettes: PaletteRam,
+
+    /// The renderer drawing the lines.
+    renderer: Renderer,
+
+    /// The pixel FIFO and its fetchers, only used by [Renderer::Fifo].
+    fifo: Fifo,
+
+    /// The current mode.
+    mode: Mode,
+
+    /// The internal STAT line, the interrupt sources enabled in STAT ORed together.
+    stat_line: bool,
+
+    /// Set when STAT was written since the last dot, every source is enabled for one cycle.
+    stat_written: bool,
+
+    /// Set when an HBlank started since the last call to [Ppu::take_hblank].
+    hblank: bool,
+
+    /// Dots elapsed since the beginning of the current line.
+    dot: u32,
+
+    /// The line of the window drawn next, only incremented on lines showing the window.
+    window_line: u8,
+
+    /// Objects found by the OAM scan of the current line, in OAM order.
+    objects: Vec<Object>,
+
+    /// The frame being drawn, one `0xRRGGBB` color per pixel.
+    framebuffer: Vec<u32>,
+
+    /// The last complete frame.
+    frame: Vec<u32>,
+}
+
+impl Ppu {
+    /// Constructs a PPU of *model* drawing with *renderer*, with the LCD off and a blank screen.
+    pub fn new(renderer: Renderer, model: Model) -> Self {
+        Self {
+            model,
+            vram: [0; VRAM_SIZE * 2],
+            vbk: 0,
+            oam: [0; OAM_SIZE],
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            bg_palettes: PaletteRam::new(),
+            obj_palettes: PaletteRam::new(),
+            renderer,
+            fifo: Fifo::default(),
+            mode: Mode::HBlank,
+            stat_line: false,
+            stat_written: false,
+            hblank: false,
+            dot: 0,
+            window_line: 0,
+            objects: Vec::with_capacity(OBJECTS_PER_LINE),
+            framebuffer: vec![DMG_COLORS[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame: vec![DMG_COLORS[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    /// Returns the last complete frame, one `0xRRGGBB` color per pixel, line by line.
+    pub fn frame(&self) -> &[u32] {
+        &self.frame
+    }
+
+    /// Advances the PPU by *cycles* dots, requesting the VBlank and STAT interrupts on
+    /// *interrupts*.
+    pub fn tick(&mut self, cycles: u32, interrupts: &mut Interrupts) {
+        if !self.enabled() {
+            self.stat_written = false;
+            return;
+        }
+        if mem::take(&mut self.stat_written) && self.model == Model::DMG {
+            self.update_stat_line(STAT_SOURCES, interrupts);
+        }
+        self.update_stat_line(self.stat, interrupts);
+
+        for _ in 0..cycles {
+            self.dot += 1;
+            match self.mode {
+                Mode::OamScan if self.dot == OAM_SCAN_DOTS => {
+                    self.scan_oam();
+                    if self.renderer == Renderer::Fifo {
+                        self.start_fifo();
+                    }
+                    self.mode = Mode::Drawing;
+                }
+                Mode::Drawing => {
+                    let done = match self.renderer {
+                        Renderer::Scanline => self.dot == OAM_SCAN_DOTS + DRAWING_DOTS,
+                        Renderer::Fifo => self.fifo_dot(),
+                    };
+                    if done {
+                        if self.renderer == Renderer::Scanline {
+                            self.render_scanline();
+                        }
+                        self.mode = Mode::HBlank;
+                        self.hblank = true;
+                    }
+                }
+                Mode::HBlank | Mode::VBlank if self.dot == DOTS_PER_LINE => {
+                    self.dot = 0;
+                    self.next_line(interrupts);
+                }
+                _ => (),
+            }
+            self.update_stat_line(self.stat, interrupts);
+        }
+    }
+
+    /// Reads the byte at *address* in Video RAM, `0xFF` while the PPU is drawing.
+    pub fn read_vram(&self, address: u16) -> u8 {
+        match self.mode {
+            Mode::Drawing => OPEN_BUS,
+            _ => self.vram[self.vram_index(address)],
+        }
+    }
+
+    /// Writes *value* at *address* in Video RAM, ignored while the PPU is drawing.
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        if self.mode != Mode::Drawing {
+            self.vram[self.vram_index(address)] = value;
+        }
+    }
+
+    /// Reads the byte at *address* in OAM, `0xFF` during the OAM scan and while drawing.
+    pub fn read_oam(&self, address: u16) -> u8 {
+        match self.mode {
+            Mode::OamScan | Mode::Drawing => OPEN_BUS,
+            _ => self.oam[(address - 0xFE00) as usize],
+        }
+    }
+
+    /// Writes *value* at *address* in OAM, ignored during the OAM scan and while drawing.
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        if !matches!(self.mode, Mode::OamScan | Mode::Drawing) {
+            self.oam[(address - 0xFE00) as usize] = value;
+        }
+    }
+
+    /// Writes *value* at *index* in OAM for the OAM DMA, which ignores the PPU mode.
+    pub fn write_oam_dma(&mut self, index: usize, value: u8) {
+        self.oam[index] = value;
+    }
+
+    /// Reads the register at *address*, in the range `0xFF40-0xFF4B`, or one of the CGB
+    /// registers VBK, BCPS, BCPD, OCPS and OCPD.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            LCDC => self.lcdc,
+            STAT => 0x80 | self.stat | self.coincidence() | self.mode as u8,
+            SCY => self.scy,
+            SCX => self.scx,
+            LY => self.ly,
+            LYC => self.lyc,
+            BGP => self.bgp,
+            OBP0 => self.obp0,
+            OBP1 => self.obp1,
+            WY => self.wy,
+            WX => self.wx,
+            VBK if self.cgb() => self.vbk | 0xFE,
+            BCPS if self.cgb() => self.bg_palettes.read_spec(),
+            OCPS if self.cgb() => self.obj_palettes.read_spec(),
+            BCPD | OCPD if self.cgb() && self.mode == Mode::Drawing => OPEN_BUS,
+            BCPD if self.cgb() => self.bg_palettes.read_data(),
+            OCPD if self.cgb() => self.obj_palettes.read_data(),
+            _ => OPEN_BUS,
+        }
+    }
+
+    /// Writes *value* to the register at *address*, in the range `0xFF40-0xFF4B`, or one of the
+    /// CGB registers VBK, BCPS, BCPD, OCPS and OCPD.
+    /// LY and the lower 3 bits of STAT are read only.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            LCDC => self.write_lcdc(value),
+            STAT => {
+                self.stat = value & STAT_SOURCES;
+                self.stat_written = true;
+            }
+            SCY => self.scy = value,
+            SCX => self.scx = value,
+            LYC => self.lyc = value,
+            BGP => self.bgp = value,
+            OBP0 => self.obp0 = value,
+            OBP1 => self.obp1 = value,
+            WY => self.wy = value,
+            WX => self.wx = value,
+            VBK if self.cgb() => self.vbk = value & 0x01,
+            BCPS if self.cgb() => self.bg_palettes.write_spec(value),
+            OCPS if self.cgb() => self.obj_palettes.write_spec(value),
+            BCPD | OCPD if self.cgb() && self.mode == Mode::Drawing => (),
+            BCPD if self.cgb() => self.bg_palettes.write_data(value),
+            OCPD if self.cgb() => self.obj_palettes.write_data(value),
+            _ => (),
+        }
+    }
+
+    /// Returns true when emulating the CGB.
+    fn cgb(&self) -> bool {
+        self.model == Model::CGB
+    }
+
+    /// Returns the index in Video RAM of *address*, in the bank selected by VBK.
+    fn vram_index(&self, address: u16) -> usize {
+        self.vbk as usize * VRAM_SIZE + (address - 0x8000) as usize
+    }
+
+    /// Returns the color of the background or window color index *color*, with the CGB
+    /// background *attributes*.
+    fn background_rgb(&self, attributes: u8, color: u8) -> u32 {
+        match self.model {
+            Model::DMG => DMG_COLORS[shade(self.bgp, color)],
+            Model::CGB => self.bg_palettes.color(attributes & ATTR_PALETTE, color),
+        }
+    }
+
+    /// Returns the color of the object color index *color*, with the object attribute *flags*.
+    fn object_rgb(&self, flags: u8, color: u8) -> u32 {
+        match self.model {
+            Model::DMG => {
+                let palette = match flags & OBJ_PALETTE {
+                    0 => self.obp0,
+                    _ => self.obp1,
+                };
+                DMG_COLORS[shade(palette, color)]
+            }
+            Model::CGB => self.obj_palettes.color(flags & ATTR_PALETTE, color),
+        }
+    }
+
+    /// Returns true if the background or window color index *background*, with the CGB
+    /// background *attributes*, is drawn over an object pixel with the attribute *flags*.
+    fn background_over_object(&self, background: u8, attributes: u8, flags: u8) -> bool {
+        if background == 0 {
+            return false;
+        }
+        match self.model {
+            Model::DMG => flags & ATTR_PRIORITY != 0,
+            Model::CGB => self.lcdc & BG_ENABLE != 0 && (attributes | flags) & ATTR_PRIORITY != 0,
+        }
+    }
+
+    /// Returns the offset in Video RAM of the bank holding the tile data selected by
+    /// *attributes*.
+    fn tile_bank(&self, attributes: u8) -> usize {
+        match self.cgb() && attributes & ATTR_BANK != 0 {
+            true => VRAM_SIZE,
+            false => 0,
+        }
+    }
+
+    /// Returns true if an HBlank started since the last call, for the HBlank DMA.
+    pub fn take_hblank(&mut self) -> bool {
+        mem::take(&mut self.hblank)
+    }
+
+    /// Returns true if the LCD is on.
+    pub fn enabled(&self) -> bool {
+        self.lcdc & LCD_ENABLE != 0
+    }
+
+    /// Returns the LY=LYC flag of STAT.
+    fn coincidence(&self) -> u8 {
+        match self.ly == self.lyc {
+            true => STAT_COINCIDENCE,
+            false => 0,
+        }
+    }
+
+    /// Updates the internal STAT line from the interrupt *sources*, and requests the STAT
+    /// interrupt on *interrupts* on its rising edge.
+    fn update_stat_line(&mut self, sources: u8, interrupts: &mut Interrupts) {
+        let mode = match self.mode {
+            Mode::HBlank => STAT_HBLANK,
+            Mode::VBlank => STAT_VBLANK,
+            Mode::OamScan => STAT_OAM,
+            Mode::Drawing => 0,
+        };
+        let lyc = match self.ly == self.lyc {
+            true => STAT_LYC,
+            false => 0,
+        };
+
+        let line = sources & (mode | lyc) != 0;
+        if line && !self.stat_line {
+            interrupts.request(Interrupt::Stat);
+        }
+        self.stat_line = line;
+    }
+
+    /// Writes the LCDC register.
+    /// Turning the LCD off resets LY and blanks the screen, turning it on starts a new frame.
+    fn write_lcdc(&mut self, value: u8) {
+        let was_enabled = self.enabled();
+        self.lcdc = value;
+
+        match (was_enabled, self.enabled()) {
+            (true, false) => {
+                self.ly = 0;
+                self.dot = 0;
+                self.mode = Mode::HBlank;
+                self.stat_line = false;
+                self.frame.fill(DMG_COLORS[0]);
+            }
+            (false, true) => {
+                self.ly = 0;
+                self.dot = 0;
+                self.window_line = 0;
+                self.mode = Mode::OamScan;
+            }
+            _ => (),
+        }
+    }
+
+    /// Moves to the next line, entering VBlank after the last visible line and starting a new
+    /// frame after the last VBlank line.
+    /// Requests the VBlank interrupt on *interrupts* when entering VBlank.
+    fn next_line(&mut self, interrupts: &mut Interrupts) {
+        self.ly += 1;
+        if self.ly == LINES {
+            self.ly = 0;
+            self.window_line = 0;
+        }
+
+        self.mode = match self.ly as usize {
+            SCREEN_HEIGHT => {
+                mem::swap(&mut self.framebuffer, &mut self.frame);
+                interrupts.request(Interrupt::VBlank);
+                Mode::VBlank
+            }
+            0..=143 => Mode::OamScan,
+            _ => Mode::VBlank,
+        };
+    }
+
+    /// Selects the first 10 objects in OAM order overlapping the current line.
+    fn scan_oam(&mut self) {
+        let height = self.object_height();
+        let line = self.ly as u16 + 16;
+
+        self.objects.clear();
+        for entry in self.oam.chunks_exact(4) {
+            let y = entry[0] as u16;
+            if line >= y && line < y + height as u16 {
+                self.objects.push(Object {
+                    y: entry[0],
+                    x: entry[1],
+                    tile: entry[2],
+                    flags: entry[3],
+                });
+                if self.objects.len() == OBJECTS_PER_LINE {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns the height of objects, 8 or 16 pixels.
+    fn object_height(&self) -> u8 {
+        match self.lcdc & OBJ_SIZE {
+            0 => 8,
+            _ => 16,
+        }
+    }
+}
+
+/// Returns the row drawn on line *ly* of an object at vertical position *y* and *height* pixels
+/// tall, flipped vertically if *flip*. The height can shrink after the object was selected, the
+/// row then wraps within the object instead of going past its end.
+fn object_row(ly: u8, y: u8, height: u8, flip: bool) -> u8 {
+    let row = (ly + 16 - y) & (height - 1);
+    match flip {
+        true => height - 1 - row,
+        false => row,
+    }
+}
+
+/// Returns the shade of color index *color* through the DMG *palette*.
+fn shade(palette: u8, color: u8) -> usize {
+    ((palette >> (color * 2)) & 0x03) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Constructs a PPU with the LCD on and the identity palettes.
+    fn ppu() -> Ppu {
+        let mut ppu = Ppu::new(Renderer::Scanline, Model::DMG);
+        ppu.write(BGP, 0b11_10_01_00);
+        ppu.write(OBP0, 0b11_10_01_00);
+        ppu.write(OBP1, 0b00_01_10_11);
+        ppu.write(LCDC, LCD_ENABLE | TILE_DATA | BG_ENABLE);
+        ppu
+    }
+
+    #[test]
+    fn test_mode_timing() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        assert_eq!(ppu.mode, Mode::OamScan);
+        ppu.tick(80, &mut interrupts);
+        assert_eq!(ppu.mode, Mode::Drawing);
+        ppu.tick(172, &mut interrupts);
+        assert_eq!(ppu.mode, Mode::HBlank);
+        ppu.tick(204, &mut interrupts);
+        assert_eq!(ppu.mode, Mode::OamScan);
+        assert_eq!(ppu.read(LY), 1);
+
+        ppu.tick(143 * DOTS_PER_LINE, &mut interrupts);
+        assert_eq!(ppu.mode, Mode::VBlank);
+        assert_eq!(ppu.read(LY), 144);
+        assert_eq!(ppu.read(STAT) & 0x03, 1);
+
+        ppu.tick(10 * DOTS_PER_LINE, &mut interrupts);
+        assert_eq!(ppu.mode, Mode::OamScan);
+        assert_eq!(ppu.read(LY), 0);
+    }
+
+    #[test]
+    fn test_stat() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        ppu.write(STAT, 0xFF);
+        ppu.write(LYC, 1);
+        assert_eq!(ppu.read(STAT), 0xFA);
+
+        ppu.tick(DOTS_PER_LINE, &mut interrupts);
+        assert_eq!(ppu.read(STAT), 0xFE);
+
+        ppu.write(LY, 42);
+        assert_eq!(ppu.read(LY), 1);
+    }
+
+    #[test]
+    fn test_vblank_interrupt() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        ppu.tick(144 * DOTS_PER_LINE - 1, &mut interrupts);
+        assert_eq!(interrupts.read_flag() & 0x01, 0);
+        ppu.tick(1, &mut interrupts);
+        assert_eq!(interrupts.read_flag() & 0x01, 0x01);
+    }
+
+    #[test]
+    fn test_stat_interrupt() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        ppu.write(STAT, STAT_LYC);
+        ppu.write(LYC, 2);
+        ppu.tick(DOTS_PER_LINE, &mut interrupts);
+        interrupts.write_flag(0x00);
+
+        ppu.tick(DOTS_PER_LINE - 1, &mut interrupts);
+        assert_eq!(interrupts.read_flag() & 0x02, 0);
+        ppu.tick(1, &mut interrupts);
+        assert_eq!(interrupts.read_flag() & 0x02, 0x02);
+    }
+
+    #[test]
+    fn test_stat_blocking() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        ppu.write(STAT, STAT_HBLANK | STAT_OAM);
+        ppu.tick(OAM_SCAN_DOTS + DRAWING_DOTS, &mut interrupts);
+        interrupts.write_flag(0x00);
+
+        ppu.tick(1, &mut interrupts);
+        ppu.tick(
+            DOTS_PER_LINE - OAM_SCAN_DOTS - DRAWING_DOTS,
+            &mut interrupts,
+        );
+        assert_eq!(ppu.mode, Mode::OamScan);
+        assert_eq!(interrupts.read_flag() & 0x02, 0);
+    }
+
+    #[test]
+    fn test_stat_write_spurious_interrupt() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        ppu.write(LYC, 42);
+        ppu.tick(OAM_SCAN_DOTS, &mut interrupts);
+        ppu.write(STAT, 0x00);
+        ppu.tick(1, &mut interrupts);
+        assert_eq!(interrupts.read_flag() & 0x02, 0);
+
+        ppu.tick(DRAWING_DOTS, &mut interrupts);
+        ppu.write(STAT, 0x00);
+        ppu.tick(1, &mut interrupts);
+        assert_eq!(interrupts.read_flag() & 0x02, 0x02);
+    }
+
+    #[test]
+    fn test_lcd_off() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        ppu.tick(DOTS_PER_LINE * 3 + 100, &mut interrupts);
+        ppu.write(LCDC, 0x00);
+        assert_eq!(ppu.read(LY), 0);
+        assert_eq!(ppu.read(STAT) & 0x03, 0);
+
+        ppu.tick(DOTS_PER_LINE, &mut interrupts);
+        assert_eq!(ppu.read(LY), 0);
+    }
+
+    #[test]
+    fn test_blocked_memory() {
+        let mut ppu = ppu();
+        let mut interrupts = Interrupts::new();
+
+        ppu.write_oam(0xFE00, 0x42);
+        assert_eq!(ppu.read_oam(0xFE00), OPEN_BUS);
+
+        ppu.tick(80, &mut interrupts);
+        ppu.write_vram(0x8000, 0x42);
+        assert_eq!(ppu.read_vram(0x8000), OPEN_BUS);
+
+        ppu.tick(172, &mut interrupts);
+        ppu.write_vram(0x8000, 0x42);
+        ppu.write_oam(0xFE00, 0x24);
+        assert_eq!(ppu.read_vram(0x8000), 0x42);
+        assert_eq!(ppu.read_oam(0xFE00), 0x24);
+    }
+
+    #[test]
+    fn test_vram_banks() {
+        let mut ppu = Ppu::new(Renderer::Scanline, Model::DMG);
+        ppu.write(VBK, 0x01);
+        assert_eq!(ppu.read(VBK), OPEN_BUS);
+
+        let mut ppu = Ppu::new(Renderer::Scanline, Model::CGB);
+        ppu.write_vram(0x8000, 0x11);
+        ppu.write(VBK, 0xFF);
+        assert_eq!(ppu.read(VBK), 0xFF);
+        assert_eq!(ppu.read_vram(0x8000), 0x00);
+        ppu.write_vram(0x8000, 0x22);
+
+        ppu.write(VBK, 0x00);
+        assert_eq!(ppu.read(VBK), 0xFE);
+        assert_eq!(ppu.read_vram(0x8000), 0x11);
+        assert_eq!(ppu.vram[VRAM_SIZE], 0x22);
+    }
+
+    #[test]
+    fn test_scan_oam() {
+        let mut ppu = Ppu::new(Renderer::Scanline, Model::DMG);
+        for index in 0..12 {
+            ppu.oam[index * 4] = 16;
+            ppu.oam[index * 4 + 1] = index as u8;
+        }
+        ppu.oam[0] = 17;
+        ppu.write(LCDC, LCD_ENABLE);
+
+        ppu.scan_oam();
+        assert_eq!(ppu.objects.len(), 10);
+        assert_eq!(ppu.objects[0].x, 1);
+        assert_eq!(ppu.objects[9].x, 10);
+    }
+
+    #[test]
+    fn test_object_row() {
+        assert_eq!(object_row(3, 16, 8, false), 3);
+        assert_eq!(object_row(3, 16, 8, true), 4);
+        assert_eq!(object_row(12, 16, 16, true), 3);
+
+        // Selected 16 pixels tall on its row 12, then drawn 8 pixels tall
+        assert_eq!(object_row(12, 16, 8, false), 4);
+        assert_eq!(object_row(12, 16, 8, true), 3);
+    }
+}
@@ -0,0 +1,486 @@
+//! Pixel FIFO renderer.
+//!
+//! Draws the line one dot at a time during mode 3, the way the hardware does, so that register
+//! writes made while a line is drawn affect the pixels not yet drawn.
+//!
+//! A fetcher reads the background or window tiles from Video RAM, 2 dots per step:
+//!
+//! | Step      | Dots | Action                                                     |
+//! |-----------|------|------------------------------------------------------------|
+//! | Tile      | 2    | Reads the tile index from the tile map                     |
+//! | Data low  | 2    | Reads the low bit plane of the tile line                   |
+//! | Data high | 2    | Reads the high bit plane of the tile line                  |
+//! | Push      | 1+   | Pushes 8 pixels once the background FIFO is empty          |
+//!
+//! Every dot, a pixel is shifted out of the background FIFO, mixed with the object FIFO, and
+//! sent to the LCD. The length of mode 3 therefore varies:
+//! - the first tile of a line is fetched twice.
+//! - `SCX % 8` pixels are shifted out and discarded at the start of the line.
+//! - the window start empties the background FIFO and restarts the fetcher.
+//! - each object stalls the FIFO for 6 dots, plus up to 5 dots waiting for the background fetch
+//!   to complete.
+//!
+//! On the CGB, the tile step also reads the tile attributes from Video RAM bank 1, and an object
+//! pixel replaces the one already in the object FIFO when it comes earlier in OAM.
+//!
+
+use std::collections::VecDeque;
+
+use super::{
+    object_row, Object, Ppu, ATTR_FLIP_X, ATTR_FLIP_Y, BG_ENABLE, BG_MAP, MAP_HIGH, MAP_LOW,
+    OBJ_ENABLE, SCREEN_WIDTH, SIGNED_TILE_BASE, TILE_DATA, TILE_SIZE, VRAM_SIZE, WINDOW_ENABLE,
+    WINDOW_MAP,
+};
+
+/// Dots the FIFO is stalled while an object is fetched.
+const OBJECT_FETCH_DOTS: u8 = 6;
+
+/// Enumerates the steps of the background fetcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+/// A pixel of the object FIFO.
+#[derive(Debug, Clone, Copy, Default)]
+struct ObjectPixel {
+    /// Color index, 0 is transparent.
+    color: u8,
+
+    /// Attribute flags of the object.
+    flags: u8,
+
+    /// Index of the object in [Ppu::objects], its OAM order.
+    index: usize,
+}
+
+#[derive(Default)]
+pub struct Fifo {
+    /// Background and window color indices waiting to be shifted out, with their CGB attributes.
+    background: VecDeque<(u8, u8)>,
+
+    /// Object pixels, aligned on the background FIFO.
+    objects: VecDeque<ObjectPixel>,
+
+    /// The current fetcher step.
+    step: Option<Step>,
+
+    /// Dots spent in the current fetcher step.
+    step_dots: u8,
+
+    /// Column of the next tile fetched, relative to the background scroll or the window.
+    tile_x: u8,
+
+    /// The tile index being fetched.
+    tile: u8,
+
+    /// The CGB attributes of the tile being fetched.
+    attributes: u8,
+
+    /// The low bit plane being fetched.
+    low: u8,
+
+    /// The high bit plane being fetched.
+    high: u8,
+
+    /// Set until the first tile of the line, which is fetched twice, is thrown away.
+    first_fetch: bool,
+
+    /// Pixels to shift out and discard before drawing.
+    discard: u8,
+
+    /// Dots left before the FIFO resumes after an object fetch.
+    stall: u8,
+
+    /// Column of the next pixel sent to the LCD.
+    x: u8,
+
+    /// Set once the window started on this line.
+    window: bool,
+
+    /// Set for the objects of the line already fetched, in the order of [Ppu::objects].
+    fetched: Vec<bool>,
+}
+
+impl Ppu {
+    /// Resets the FIFO and the fetcher at the start of mode 3.
+    pub(super) fn start_fifo(&mut self) {
+        self.fifo = Fifo {
+            step: Some(Step::Tile),
+            first_fetch: true,
+            discard: self.scx % 8,
+            fetched: vec![false; self.objects.len()],
+            ..Fifo::default()
+        };
+    }
+
+    /// Advances the FIFO and the fetcher by one dot.
+    /// Returns true once the last pixel of the line has been drawn.
+    pub(super) fn fifo_dot(&mut self) -> bool {
+        if self.fifo.stall > 0 {
+            self.fifo.stall -= 1;
+            return false;
+        }
+        self.start_window();
+        self.fetch_background();
+        if self.fetch_object() {
+            return false;
+        }
+        self.shift_out();
+
+        if self.fifo.x as usize == SCREEN_WIDTH {
+            if self.fifo.window {
+                self.window_line += 1;
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Restarts the fetcher on the window if it starts at the current pixel.
+    fn start_window(&mut self) {
+        let cgb = self.cgb();
+        let fifo = &mut self.fifo;
+        if fifo.window
+            || self.lcdc & WINDOW_ENABLE == 0
+            || (self.lcdc & BG_ENABLE == 0 && !cgb)
+            || self.ly < self.wy
+            || fifo.discard > 0
+            || fifo.x as u16 + 7 < self.wx as u16
+        {
+            return;
+        }
+
+        fifo.window = true;
+        fifo.background.clear();
+        fifo.step = Some(Step::Tile);
+        fifo.step_dots = 0;
+        fifo.tile_x = 0;
+        fifo.discard = 7u8.saturating_sub(self.wx);
+    }
+
+    /// Fetches the next object starting at the current pixel, if any, and stalls the FIFO.
+    /// Returns true if an object was fetched.
+    fn fetch_object(&mut self) -> bool {
+        if self.lcdc & OBJ_ENABLE == 0 || self.fifo.discard > 0 || self.fifo.background.is_empty() {
+            return false;
+        }
+        let x = self.fifo.x as usize;
+        let next = (0..self.objects.len())
+            .filter(|index| !self.fifo.fetched[*index])
+            .filter(|index| {
+                let object = self.objects[*index];
+                object.x > 0 && object.x as usize <= x + 8
+            })
+            .min_by_key(|index| self.objects[*index].x);
+        let index = match next {
+            Some(index) => index,
+            None => return false,
+        };
+        self.fifo.fetched[index] = true;
+
+        let object = self.objects[index];
+        let skipped = (x + 8 - object.x as usize) as u8;
+        let pixels: Vec<ObjectPixel> = (skipped..8)
+            .map(|column| ObjectPixel {
+                color: self.object_color(&object, column),
+                flags: object.flags,
+                index,
+            })
+            .collect();
+        let cgb = self.cgb();
+
+        let fifo = &mut self.fifo;
+        while fifo.objects.len() < pixels.len() {
+            fifo.objects.push_back(ObjectPixel::default());
+        }
+        for (slot, pixel) in fifo.objects.iter_mut().zip(pixels) {
+            if slot.color == 0 || (cgb && pixel.color != 0 && pixel.index < slot.index) {
+                *slot = pixel;
+            }
+        }
+
+        let alignment = (x as u8).wrapping_add(self.scx) % 8;
+        fifo.stall = OBJECT_FETCH_DOTS + 5u8.saturating_sub(alignment) - 1;
+        true
+    }
+
+    /// Advances the background fetcher by one dot.
+    fn fetch_background(&mut self) {
+        self.fifo.step_dots += 1;
+        let step = match self.fifo.step {
+            Some(Step::Push) => Step::Push,
+            Some(step) if self.fifo.step_dots == 2 => step,
+            _ => return,
+        };
+        self.fifo.step_dots = 0;
+
+        match step {
+            Step::Tile => {
+                let address = self.tile_map_address();
+                self.fifo.tile = self.vram[address];
+                self.fifo.attributes = match self.cgb() {
+                    true => self.vram[VRAM_SIZE + address],
+                    false => 0,
+                };
+                self.fifo.step = Some(Step::DataLow);
+            }
+            Step::DataLow => {
+                self.fifo.low = self.vram[self.tile_data_address()];
+                self.fifo.step = Some(Step::DataHigh);
+            }
+            Step::DataHigh => {
+                self.fifo.high = self.vram[self.tile_data_address() + 1];
+                self.fifo.step = Some(Step::Push);
+            }
+            Step::Push if self.fifo.first_fetch => {
+                self.fifo.first_fetch = false;
+                self.fifo.step = Some(Step::Tile);
+            }
+            Step::Push if self.fifo.background.is_empty() => {
+                let fifo = &mut self.fifo;
+                for column in 0..8 {
+                    let bit = match fifo.attributes & ATTR_FLIP_X {
+                        0 => 7 - column,
+                        _ => column,
+                    };
+                    let color = ((fifo.high >> bit) & 1) << 1 | (fifo.low >> bit) & 1;
+                    fifo.background.push_back((color, fifo.attributes));
+                }
+                fifo.tile_x = fifo.tile_x.wrapping_add(1);
+                fifo.step = Some(Step::Tile);
+            }
+            Step::Push => (),
+        }
+    }
+
+    /// Shifts one pixel out of the FIFO, and sends it to the LCD unless it is discarded.
+    fn shift_out(&mut self) {
+        let (background, attributes) = match self.fifo.background.pop_front() {
+            Some(pixel) => pixel,
+            None => return,
+        };
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return;
+        }
+
+        let background = match self.lcdc & BG_ENABLE {
+            0 if !self.cgb() => 0,
+            _ => background,
+        };
+        let object = self.fifo.objects.pop_front().unwrap_or_default();
+        let color = match object {
+            ObjectPixel { color: 0, .. } => self.background_rgb(attributes, background),
+            ObjectPixel { flags, .. }
+                if self.background_over_object(background, attributes, flags) =>
+            {
+                self.background_rgb(attributes, background)
+            }
+            ObjectPixel { color, flags, .. } => self.object_rgb(flags, color),
+        };
+
+        self.framebuffer[self.ly as usize * SCREEN_WIDTH + self.fifo.x as usize] = color;
+        self.fifo.x += 1;
+    }
+
+    /// Returns the address in Video RAM of the tile index fetched next.
+    fn tile_map_address(&self) -> usize {
+        let (map, x, y) = match self.fifo.window {
+            true => (WINDOW_MAP, self.fifo.tile_x, self.window_line),
+            false => (
+                BG_MAP,
+                (self.scx / 8).wrapping_add(self.fifo.tile_x),
+                self.ly.wrapping_add(self.scy),
+            ),
+        };
+        let map = match self.lcdc & map {
+            0 => MAP_LOW,
+            _ => MAP_HIGH,
+        };
+        map + (y as usize / 8) * 32 + (x as usize % 32)
+    }
+
+    /// Returns the address in Video RAM of the low bit plane of the tile line fetched next.
+    fn tile_data_address(&self) -> usize {
+        let y = match self.fifo.window {
+            true => self.window_line,
+            false => self.ly.wrapping_add(self.scy),
+        };
+        let tile = self.fifo.tile;
+        let address = match self.lcdc & TILE_DATA {
+            0 => (SIGNED_TILE_BASE + tile as i8 as i32 * TILE_SIZE as i32) as usize,
+            _ => tile as usize * TILE_SIZE,
+        };
+        let row = match self.fifo.attributes & ATTR_FLIP_Y {
+            0 => y % 8,
+            _ => 7 - y % 8,
+        };
+        self.tile_bank(self.fifo.attributes) + address + row as usize * 2
+    }
+
+    /// Returns the color index of *object* at *column* on the current line.
+    fn object_color(&self, object: &Object, column: u8) -> u8 {
+        let height = self.object_height();
+        let row = object_row(self.ly, object.y, height, object.flags & ATTR_FLIP_Y != 0);
+        let column = match object.flags & ATTR_FLIP_X {
+            0 => column,
+            _ => 7 - column,
+        };
+        let tile = match height {
+            16 => object.tile & 0xFE,
+            _ => object.tile,
+        };
+        let address = self.tile_bank(object.flags) + tile as usize * TILE_SIZE + row as usize * 2;
+        let bit = 7 - column;
+        ((self.vram[address + 1] >> bit) & 1) << 1 | (self.vram[address] >> bit) & 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::interrupts::Interrupts;
+    use super::super::{
+        Mode, Renderer, BCPD, BCPS, BGP, DMG_COLORS, LCDC, LCD_ENABLE, OBP0, OCPD, OCPS, SCX, SCY,
+        WX, WY,
+    };
+    use super::*;
+    use crate::gameboy::Model;
+
+    /// Constructs a PPU of *model* using *renderer*, showing a background, a window and a few
+    /// objects.
+    fn ppu(renderer: Renderer, model: Model) -> Ppu {
+        let mut ppu = Ppu::new(renderer, model);
+        for (index, byte) in ppu.vram[..0x1800].iter_mut().enumerate() {
+            *byte = (index * 7 + index / 16) as u8;
+        }
+        for (index, byte) in ppu.vram[0x1800..0x2000].iter_mut().enumerate() {
+            *byte = (index % 251) as u8;
+        }
+        for (index, byte) in ppu.vram[0x2000..].iter_mut().enumerate() {
+            *byte = (index * 13 + index / 7) as u8;
+        }
+        ppu.write(BCPS, 0x80);
+        ppu.write(OCPS, 0x80);
+        for index in 0..64 {
+            ppu.write(BCPD, index * 4 + 3);
+            ppu.write(OCPD, index * 3 + 1);
+        }
+        for index in 0..12 {
+            let object = [16 + index * 11, index * 13, index * 3, (index % 8) << 5];
+            ppu.oam[index as usize * 4..index as usize * 4 + 4].copy_from_slice(&object);
+        }
+        ppu.write(BGP, 0b11_10_01_00);
+        ppu.write(OBP0, 0b00_10_01_11);
+        ppu.write(SCX, 13);
+        ppu.write(SCY, 7);
+        ppu.write(WX, 87);
+        ppu.write(WY, 50);
+        ppu.write(
+            LCDC,
+            LCD_ENABLE | WINDOW_MAP | WINDOW_ENABLE | BG_ENABLE | OBJ_ENABLE | BG_MAP,
+        );
+        ppu
+    }
+
+    /// Returns the number of dots the PPU spends in mode 3 on the current line.
+    fn drawing_dots(ppu: &mut Ppu) -> u32 {
+        let mut interrupts = Interrupts::new();
+        while ppu.mode != Mode::Drawing {
+            ppu.tick(1, &mut interrupts);
+        }
+        let mut dots = 0;
+        while ppu.mode == Mode::Drawing {
+            ppu.tick(1, &mut interrupts);
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn test_same_frame_as_scanline() {
+        for model in [Model::DMG, Model::CGB] {
+            let mut scanline = ppu(Renderer::Scanline, model);
+            let mut fifo = ppu(Renderer::Fifo, model);
+
+            scanline.tick(70224, &mut Interrupts::new());
+            fifo.tick(70224, &mut Interrupts::new());
+            assert!(scanline.frame() == fifo.frame());
+        }
+    }
+
+    #[test]
+    fn test_fine_scroll_penalty() {
+        let mut ppu = Ppu::new(Renderer::Fifo, Model::DMG);
+        ppu.write(LCDC, LCD_ENABLE | BG_ENABLE);
+        let base = drawing_dots(&mut ppu);
+        assert!((172..=174).contains(&base));
+
+        ppu.write(SCX, 3);
+        assert_eq!(drawing_dots(&mut ppu), base + 3);
+    }
+
+    #[test]
+    fn test_window_penalty() {
+        let mut ppu = Ppu::new(Renderer::Fifo, Model::DMG);
+        ppu.write(LCDC, LCD_ENABLE | BG_ENABLE);
+        let base = drawing_dots(&mut ppu);
+
+        ppu.write(WX, 50);
+        ppu.write(LCDC, LCD_ENABLE | BG_ENABLE | WINDOW_ENABLE);
+        assert_eq!(drawing_dots(&mut ppu), base + 6);
+    }
+
+    #[test]
+    fn test_object_penalty() {
+        let mut ppu = Ppu::new(Renderer::Fifo, Model::DMG);
+        ppu.oam[..4].copy_from_slice(&[16, 8, 0, 0]);
+        ppu.write(LCDC, LCD_ENABLE | BG_ENABLE);
+        let base = drawing_dots(&mut ppu);
+
+        ppu.write(LCDC, LCD_ENABLE | BG_ENABLE | OBJ_ENABLE);
+        assert_eq!(drawing_dots(&mut ppu), base + 11);
+    }
+
+    #[test]
+    fn test_mid_line_palette_change() {
+        let mut ppu = Ppu::new(Renderer::Fifo, Model::DMG);
+        for row in 0..8 {
+            ppu.vram[TILE_SIZE + row * 2] = 0xFF;
+        }
+        ppu.vram[MAP_LOW..MAP_LOW + 32].fill(1);
+        ppu.write(BGP, 0b11_10_01_00);
+        ppu.write(LCDC, LCD_ENABLE | BG_ENABLE | TILE_DATA);
+
+        let mut interrupts = Interrupts::new();
+        ppu.tick(80 + 100, &mut interrupts);
+        ppu.write(BGP, 0b11_11_11_00);
+        ppu.tick(70224 - 180, &mut interrupts);
+
+        assert_eq!(ppu.frame()[0], DMG_COLORS[1]);
+        assert_eq!(ppu.frame()[159], DMG_COLORS[3]);
+        assert_eq!(ppu.frame()[SCREEN_WIDTH], DMG_COLORS[3]);
+    }
+
+    #[test]
+    fn test_object_size_change() {
+        let mut ppu = Ppu::new(Renderer::Fifo, Model::DMG);
+        for row in 0..8 {
+            ppu.vram[TILE_SIZE * 3 + row * 2] = 0x80;
+            ppu.vram[TILE_SIZE * 3 + row * 2 + 1] = 0x80;
+        }
+        ppu.write(LCDC, LCD_ENABLE | OBJ_ENABLE);
+
+        // Selected 16 pixels tall on its row 12, then drawn 8 pixels tall
+        let object = Object {
+            y: 4,
+            x: 8,
+            tile: 3,
+            flags: ATTR_FLIP_Y,
+        };
+        assert_eq!(ppu.object_color(&object, 0), 3);
+    }
+}
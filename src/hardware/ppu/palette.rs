@@ -0,0 +1,120 @@
+//! CGB color palettes.
+//!
+//! The CGB stores 8 background and 8 object palettes of 4 colors each in two 64-byte palette
+//! RAMs. Each color is a little endian RGB555 value:
+//!
+//! | Bits  | Content |
+//! |-------|---------|
+//! | 0-4   | Red     |
+//! | 5-9   | Green   |
+//! | 10-14 | Blue    |
+//!
+//! A palette RAM is accessed through two registers: the specification register (BCPS or OCPS)
+//! selects the byte in bits 0-5 and enables auto-increment in bit 7, the data register (BCPD or
+//! OCPD) reads or writes the selected byte. With auto-increment enabled, writing the data
+//! register selects the next byte, wrapping around after the last one.
+//!
+
+/// Size of a palette RAM: 8 palettes of 4 colors of 2 bytes.
+const PALETTE_RAM_SIZE: usize = 64;
+
+/// The RGB555 white, the color the boot ROM leaves in the palettes.
+const WHITE: u16 = 0x7FFF;
+
+/// Specification register: the index of the selected byte.
+const SPEC_INDEX: u8 = 0b0011_1111;
+
+/// Specification register: auto-increment enable.
+const SPEC_INCREMENT: u8 = 0b1000_0000;
+
+pub struct PaletteRam {
+    /// The specification register.
+    spec: u8,
+
+    /// The 8 palettes of 4 RGB555 colors.
+    data: [u8; PALETTE_RAM_SIZE],
+}
+
+impl PaletteRam {
+    /// Constructs a palette RAM with every color white.
+    pub fn new() -> Self {
+        let mut data = [0; PALETTE_RAM_SIZE];
+        for color in data.chunks_exact_mut(2) {
+            color.copy_from_slice(&WHITE.to_le_bytes());
+        }
+        Self { spec: 0, data }
+    }
+
+    /// Reads the specification register, bit 6 is not wired and reads as 1.
+    pub fn read_spec(&self) -> u8 {
+        self.spec | 0b0100_0000
+    }
+
+    /// Writes *value* to the specification register.
+    pub fn write_spec(&mut self, value: u8) {
+        self.spec = value & (SPEC_INCREMENT | SPEC_INDEX);
+    }
+
+    /// Reads the selected byte.
+    pub fn read_data(&self) -> u8 {
+        self.data[(self.spec & SPEC_INDEX) as usize]
+    }
+
+    /// Writes *value* to the selected byte, then selects the next one if auto-increment is
+    /// enabled.
+    pub fn write_data(&mut self, value: u8) {
+        let index = self.spec & SPEC_INDEX;
+        self.data[index as usize] = value;
+        if self.spec & SPEC_INCREMENT != 0 {
+            self.spec = SPEC_INCREMENT | (index + 1) & SPEC_INDEX;
+        }
+    }
+
+    /// Returns the `0xRRGGBB` color of color index *color* in *palette*.
+    pub fn color(&self, palette: u8, color: u8) -> u32 {
+        let index = palette as usize * 8 + color as usize * 2;
+        let rgb555 = u16::from_le_bytes([self.data[index], self.data[index + 1]]);
+
+        // Scale the 5-bit channels to 8 bits, so that 0x1F maps to 0xFF
+        let channel = |shift: u16| {
+            let value = ((rgb555 >> shift) & 0x1F) as u32;
+            value << 3 | value >> 2
+        };
+        channel(0) << 16 | channel(5) << 8 | channel(10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_increment() {
+        let mut palettes = PaletteRam::new();
+
+        palettes.write_spec(0xBE);
+        assert_eq!(palettes.read_spec(), 0xFE);
+        palettes.write_data(0x12);
+        palettes.write_data(0x34);
+        assert_eq!(palettes.read_spec(), 0xC0);
+        assert_eq!(palettes.data[0x3E..], [0x12, 0x34]);
+
+        palettes.write_spec(0x3F);
+        assert_eq!(palettes.read_data(), 0x34);
+        palettes.write_data(0x56);
+        assert_eq!(palettes.read_spec(), 0x7F);
+        assert_eq!(palettes.read_data(), 0x56);
+    }
+
+    #[test]
+    fn test_color() {
+        let mut palettes = PaletteRam::new();
+
+        palettes.write_spec(SPEC_INCREMENT | 10);
+        // Red 0x1F, green 0x10, blue 0x01
+        palettes.write_data(0x1F);
+        palettes.write_data(0x06);
+        assert_eq!(palettes.color(1, 1), 0xFF8408);
+        assert_eq!(palettes.color(1, 0), 0xFFFFFF);
+    }
+}
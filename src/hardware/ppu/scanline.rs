@@ -0,0 +1,391 @@
+//! Scanline renderer.
+//!
+//! Draws a whole line at once at the end of mode 3, from the registers as they are at that time.
+//! Mid-line register writes are not visible.
+//!
+//! Each pixel is the composition of three layers:
+//! - the background, scrolled by SCX and SCY, wrapping around its 256x256 tile map.
+//! - the window, drawn from WX - 7 and WY without scrolling, over the background.
+//! - the objects selected by the OAM scan. The object with the smallest X wins over the others,
+//!   OAM order breaking ties, and transparent object pixels let the next object show through.
+//!   Objects flagged behind the background only show over background color 0.
+//!
+//! On the CGB, background tiles also read their attributes from Video RAM bank 1, and objects
+//! overlap in OAM order only.
+//!
+
+use super::{
+    object_row, Object, Ppu, ATTR_FLIP_X, ATTR_FLIP_Y, BG_ENABLE, BG_MAP, MAP_HIGH, MAP_LOW,
+    OBJ_ENABLE, SCREEN_WIDTH, SIGNED_TILE_BASE, TILE_DATA, TILE_SIZE, VRAM_SIZE, WINDOW_ENABLE,
+    WINDOW_MAP,
+};
+
+impl Ppu {
+    /// Renders the current line into the framebuffer.
+    pub(super) fn render_scanline(&mut self) {
+        let line = self.ly as usize * SCREEN_WIDTH;
+        let mut colors = [0u8; SCREEN_WIDTH];
+        let mut attributes = [0u8; SCREEN_WIDTH];
+
+        if self.lcdc & BG_ENABLE != 0 || self.cgb() {
+            self.render_background(&mut colors, &mut attributes);
+            self.render_window(&mut colors, &mut attributes);
+        }
+
+        for (x, color) in colors.iter().enumerate() {
+            self.framebuffer[line + x] = self.background_rgb(attributes[x], *color);
+        }
+
+        if self.lcdc & OBJ_ENABLE != 0 {
+            self.render_objects(&colors, &attributes);
+        }
+    }
+
+    /// Fills *colors* and *attributes* with the background color indices and attributes of the
+    /// current line.
+    fn render_background(
+        &self,
+        colors: &mut [u8; SCREEN_WIDTH],
+        attributes: &mut [u8; SCREEN_WIDTH],
+    ) {
+        let map = match self.lcdc & BG_MAP {
+            0 => MAP_LOW,
+            _ => MAP_HIGH,
+        };
+        let y = self.ly.wrapping_add(self.scy);
+
+        for x in 0..SCREEN_WIDTH {
+            (colors[x], attributes[x]) =
+                self.tile_map_pixel(map, (x as u8).wrapping_add(self.scx), y);
+        }
+    }
+
+    /// Overwrites *colors* and *attributes* with the window color indices and attributes, if the
+    /// window shows on the current line.
+    fn render_window(
+        &mut self,
+        colors: &mut [u8; SCREEN_WIDTH],
+        attributes: &mut [u8; SCREEN_WIDTH],
+    ) {
+        if self.lcdc & WINDOW_ENABLE == 0 || self.ly < self.wy || self.wx > 166 {
+            return;
+        }
+        let map = match self.lcdc & WINDOW_MAP {
+            0 => MAP_LOW,
+            _ => MAP_HIGH,
+        };
+        let start = self.wx.saturating_sub(7) as usize;
+
+        for x in start..SCREEN_WIDTH {
+            let window_x = (x + 7 - self.wx as usize) as u8;
+            (colors[x], attributes[x]) = self.tile_map_pixel(map, window_x, self.window_line);
+        }
+        self.window_line += 1;
+    }
+
+    /// Draws the objects selected by the OAM scan over the current line, given the background
+    /// and window color indices *colors* and *attributes*.
+    fn render_objects(&mut self, colors: &[u8; SCREEN_WIDTH], attributes: &[u8; SCREEN_WIDTH]) {
+        let line = self.ly as usize * SCREEN_WIDTH;
+        let mut objects = self.objects.clone();
+        if !self.cgb() {
+            objects.sort_by_key(|object| object.x);
+        }
+
+        for (x, background) in colors.iter().enumerate() {
+            let pixel = objects
+                .iter()
+                .filter_map(|object| self.object_pixel(object, x).map(|color| (object, color)))
+                .find(|(_, color)| *color != 0);
+
+            if let Some((object, color)) = pixel {
+                if self.background_over_object(*background, attributes[x], object.flags) {
+                    continue;
+                }
+                self.framebuffer[line + x] = self.object_rgb(object.flags, color);
+            }
+        }
+    }
+
+    /// Returns the color index of *object* at screen column *x* on the current line, or None if
+    /// the object does not cover *x*.
+    fn object_pixel(&self, object: &Object, x: usize) -> Option<u8> {
+        let column = (x + 8)
+            .checked_sub(object.x as usize)
+            .filter(|column| *column < 8)?;
+        let height = self.object_height();
+        let row = object_row(self.ly, object.y, height, object.flags & ATTR_FLIP_Y != 0);
+        let column = match object.flags & ATTR_FLIP_X {
+            0 => column as u8,
+            _ => 7 - column as u8,
+        };
+        let tile = match height {
+            16 => object.tile & 0xFE,
+            _ => object.tile,
+        };
+        let address = self.tile_bank(object.flags) + tile as usize * TILE_SIZE;
+        Some(self.tile_pixel(address, row, column))
+    }
+
+    /// Returns the color index and the CGB attributes of the pixel at (*x*, *y*) of the 256x256
+    /// background pictured by the tile *map*.
+    fn tile_map_pixel(&self, map: usize, x: u8, y: u8) -> (u8, u8) {
+        let index = map + (y as usize / 8) * 32 + x as usize / 8;
+        let tile = self.vram[index];
+        let attributes = match self.cgb() {
+            true => self.vram[VRAM_SIZE + index],
+            false => 0,
+        };
+        let address = match self.lcdc & TILE_DATA {
+            0 => (SIGNED_TILE_BASE + tile as i8 as i32 * TILE_SIZE as i32) as usize,
+            _ => tile as usize * TILE_SIZE,
+        };
+
+        let row = match attributes & ATTR_FLIP_Y {
+            0 => y % 8,
+            _ => 7 - y % 8,
+        };
+        let column = match attributes & ATTR_FLIP_X {
+            0 => x % 8,
+            _ => 7 - x % 8,
+        };
+        let color = self.tile_pixel(self.tile_bank(attributes) + address, row, column);
+        (color, attributes)
+    }
+
+    /// Returns the color index of the pixel at *row* and *column* of the tile at *address* in
+    /// Video RAM.
+    fn tile_pixel(&self, address: usize, row: u8, column: u8) -> u8 {
+        let address = address + row as usize * 2;
+        let bit = 7 - column;
+        let low = (self.vram[address] >> bit) & 1;
+        let high = (self.vram[address + 1] >> bit) & 1;
+        high << 1 | low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        Renderer, ATTR_BANK, ATTR_PRIORITY, BGP, DMG_COLORS, LCDC, LCD_ENABLE, OBJ_PALETTE,
+        OBJ_SIZE, OBP0, OBP1, SCX, WX, WY,
+    };
+    use super::*;
+    use crate::gameboy::Model;
+
+    const WHITE: u32 = DMG_COLORS[0];
+    const LIGHT: u32 = DMG_COLORS[1];
+    const DARK: u32 = DMG_COLORS[2];
+    const BLACK: u32 = DMG_COLORS[3];
+
+    /// Constructs a PPU with the LCD on, tile data at `0x8000` and the identity palettes.
+    /// Tile 1 is filled with color 1, tile 2 with color 2, and tile 3 has its left column set to
+    /// color 3.
+    fn ppu(lcdc: u8) -> Ppu {
+        let mut ppu = Ppu::new(Renderer::Scanline, Model::DMG);
+        ppu.write(BGP, 0b11_10_01_00);
+        ppu.write(OBP0, 0b11_10_01_00);
+        ppu.write(OBP1, 0b01_10_11_00);
+        ppu.write(LCDC, LCD_ENABLE | TILE_DATA | lcdc);
+        for row in 0..8 {
+            ppu.vram[TILE_SIZE + row * 2] = 0xFF;
+            ppu.vram[TILE_SIZE * 2 + row * 2 + 1] = 0xFF;
+            ppu.vram[TILE_SIZE * 3 + row * 2] = 0x80;
+            ppu.vram[TILE_SIZE * 3 + row * 2 + 1] = 0x80;
+        }
+        ppu
+    }
+
+    /// Returns the rendered line *y*.
+    fn line(ppu: &Ppu, y: usize) -> &[u32] {
+        &ppu.framebuffer[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH]
+    }
+
+    /// Writes an object at *index* in OAM.
+    fn object(ppu: &mut Ppu, index: usize, x: u8, y: u8, tile: u8, flags: u8) {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y, x, tile, flags]);
+    }
+
+    /// Constructs a CGB PPU like [ppu], with every color of the palette RAMs different.
+    fn cgb_ppu(lcdc: u8) -> Ppu {
+        let mut ppu = ppu(lcdc);
+        ppu.model = Model::CGB;
+        ppu.bg_palettes.write_spec(0x80);
+        ppu.obj_palettes.write_spec(0x80);
+        for index in 0..64 {
+            ppu.bg_palettes.write_data(index);
+            ppu.obj_palettes.write_data(!index);
+        }
+        ppu
+    }
+
+    #[test]
+    fn test_background() {
+        let mut ppu = ppu(BG_ENABLE);
+        ppu.vram[MAP_LOW + 1] = 1;
+        ppu.write(SCX, 4);
+
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0..4], [WHITE; 4]);
+        assert_eq!(line(&ppu, 0)[4..12], [LIGHT; 8]);
+        assert_eq!(line(&ppu, 0)[12], WHITE);
+    }
+
+    #[test]
+    fn test_signed_tile_data() {
+        let mut ppu = ppu(BG_ENABLE);
+        ppu.write(LCDC, LCD_ENABLE | BG_ENABLE);
+        ppu.vram[MAP_LOW] = 0x80;
+        ppu.vram[0x0800] = 0xFF;
+
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0..8], [LIGHT; 8]);
+    }
+
+    #[test]
+    fn test_background_disabled() {
+        let mut ppu = ppu(0);
+        ppu.vram[MAP_LOW] = 1;
+
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], WHITE);
+    }
+
+    #[test]
+    fn test_window() {
+        let mut ppu = ppu(BG_ENABLE | WINDOW_ENABLE | WINDOW_MAP);
+        ppu.vram[MAP_HIGH] = 2;
+        ppu.write(WX, 17);
+        ppu.write(WY, 1);
+
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[10], WHITE);
+        assert_eq!(ppu.window_line, 0);
+
+        ppu.ly = 1;
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 1)[9], WHITE);
+        assert_eq!(line(&ppu, 1)[10..18], [DARK; 8]);
+        assert_eq!(line(&ppu, 1)[18], WHITE);
+        assert_eq!(ppu.window_line, 1);
+    }
+
+    #[test]
+    fn test_objects() {
+        let mut ppu = ppu(OBJ_ENABLE);
+        object(&mut ppu, 0, 8, 16, 3, 0);
+        object(&mut ppu, 1, 20, 16, 3, ATTR_FLIP_X | OBJ_PALETTE);
+
+        ppu.scan_oam();
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], BLACK);
+        assert_eq!(line(&ppu, 0)[1], WHITE);
+        assert_eq!(line(&ppu, 0)[19], LIGHT);
+        assert_eq!(line(&ppu, 0)[12], WHITE);
+    }
+
+    #[test]
+    fn test_object_priority() {
+        let mut ppu = ppu(OBJ_ENABLE);
+        object(&mut ppu, 0, 9, 16, 1, 0);
+        object(&mut ppu, 1, 8, 16, 2, 0);
+        object(&mut ppu, 2, 8, 16, 1, 0);
+
+        ppu.scan_oam();
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0..8], [DARK; 8]);
+        assert_eq!(line(&ppu, 0)[8], LIGHT);
+    }
+
+    #[test]
+    fn test_object_transparency() {
+        let mut ppu = ppu(OBJ_ENABLE);
+        object(&mut ppu, 0, 8, 16, 3, 0);
+        object(&mut ppu, 1, 9, 16, 2, 0);
+
+        ppu.scan_oam();
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], BLACK);
+        assert_eq!(line(&ppu, 0)[1], DARK);
+    }
+
+    #[test]
+    fn test_object_behind_background() {
+        let mut ppu = ppu(BG_ENABLE | OBJ_ENABLE);
+        ppu.vram[MAP_LOW] = 1;
+        object(&mut ppu, 0, 8, 16, 2, ATTR_PRIORITY);
+        object(&mut ppu, 1, 16, 16, 2, ATTR_PRIORITY);
+
+        ppu.scan_oam();
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0..8], [LIGHT; 8]);
+        assert_eq!(line(&ppu, 0)[8..16], [DARK; 8]);
+    }
+
+    #[test]
+    fn test_cgb_background_attributes() {
+        let mut ppu = cgb_ppu(BG_ENABLE);
+        ppu.vram[MAP_LOW..MAP_LOW + 3].copy_from_slice(&[3, 3, 1]);
+        ppu.vram[VRAM_SIZE + MAP_LOW] = 0x05;
+        ppu.vram[VRAM_SIZE + MAP_LOW + 1] = ATTR_FLIP_X | 0x02;
+        ppu.vram[VRAM_SIZE + MAP_LOW + 2] = ATTR_BANK;
+        for row in 0..8 {
+            ppu.vram[VRAM_SIZE + TILE_SIZE + row * 2 + 1] = 0xFF;
+        }
+
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], ppu.bg_palettes.color(5, 3));
+        assert_eq!(line(&ppu, 0)[1], ppu.bg_palettes.color(5, 0));
+        assert_eq!(line(&ppu, 0)[14], ppu.bg_palettes.color(2, 0));
+        assert_eq!(line(&ppu, 0)[15], ppu.bg_palettes.color(2, 3));
+        assert_eq!(line(&ppu, 0)[16..24], [ppu.bg_palettes.color(0, 2); 8]);
+    }
+
+    #[test]
+    fn test_cgb_priority() {
+        let mut ppu = cgb_ppu(BG_ENABLE | OBJ_ENABLE);
+        ppu.vram[MAP_LOW..MAP_LOW + 2].copy_from_slice(&[1, 1]);
+        ppu.vram[VRAM_SIZE + MAP_LOW + 1] = ATTR_PRIORITY;
+        object(&mut ppu, 0, 9, 16, 2, 0x01);
+        object(&mut ppu, 1, 8, 16, 3, 0x02);
+        object(&mut ppu, 2, 16, 16, 2, 0x03);
+        let object = ppu.obj_palettes.color(1, 2);
+
+        ppu.scan_oam();
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], ppu.obj_palettes.color(2, 3));
+        assert_eq!(line(&ppu, 0)[1..8], [object; 7]);
+        assert_eq!(line(&ppu, 0)[8], ppu.bg_palettes.color(0, 1));
+
+        ppu.write(LCDC, LCD_ENABLE | TILE_DATA | OBJ_ENABLE);
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[8], object);
+    }
+
+    #[test]
+    fn test_tall_objects() {
+        let mut ppu = ppu(OBJ_ENABLE | OBJ_SIZE);
+        object(&mut ppu, 0, 8, 8, 3, 0);
+
+        ppu.scan_oam();
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], BLACK);
+
+        object(&mut ppu, 0, 8, 8, 3, ATTR_FLIP_Y);
+        ppu.scan_oam();
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], DARK);
+    }
+
+    #[test]
+    fn test_object_size_change() {
+        let mut ppu = ppu(OBJ_ENABLE | OBJ_SIZE);
+        object(&mut ppu, 0, 8, 4, 3, ATTR_FLIP_Y);
+
+        // Selected 16 pixels tall on its row 12, then drawn 8 pixels tall
+        ppu.scan_oam();
+        ppu.write(LCDC, LCD_ENABLE | TILE_DATA | OBJ_ENABLE);
+        ppu.render_scanline();
+        assert_eq!(line(&ppu, 0)[0], BLACK);
+    }
+}
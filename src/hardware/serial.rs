@@ -0,0 +1,522 @@
+//! Serial port.
+//!
+//! Exchanges bytes with a link partner one bit at a time, most significant bit first. Its
+//! registers are:
+//!
+//! | Address  | Register | Content                                                       |
+//! |----------|----------|---------------------------------------------------------------|
+//! | `0xFF01` | SB       | The byte being exchanged                                      |
+//! | `0xFF02` | SC       | Bit 7: transfer, bit 1: fast clock (CGB), bit 0: clock source |
+//!
+//! Setting bit 7 of SC starts a transfer. With the internal clock (bit 0 set), the GameBoy
+//! drives the exchange at 8192 Hz, or 262144 Hz with the fast clock of the CGB, both doubled in
+//! double speed. With the external clock, the link partner drives it, and the transfer waits
+//! until it does. Each clock shifts the outgoing bit out of SB and the incoming bit in; without
+//! a partner, the incoming bits read 1.
+//!
+//! After 8 bits, bit 7 of SC is cleared and the serial interrupt is requested. The bytes sent
+//! are collected with [Serial::take_output], which is how test ROMs report their results.
+//!
+//! The partner can be another GameBoy connected through a [Link]. Bytes then travel whole and
+//! the transfers of both GameBoys are lock-stepped, so that latency only delays them:
+//! - Starting a transfer with the internal clock sends it to the partner, and the transfer
+//!   completes once its 8 bits elapsed and the byte of the partner arrived.
+//! - A transfer of the partner is answered with SB once a transfer with the external clock is
+//!   started, completing it at once.
+//! - When both GameBoys use the internal clock, each receives the byte of the other, as with
+//!   the crossed data lines of the cable. The clock master negotiated by the [Link] wins: the
+//!   transfer of the follower completes as soon as the byte of the master arrives.
+//!
+//! The link is only polled while a transfer is in progress, once per bit of the normal clock.
+//!
+//! If the connection fails, the link is dropped and the partner is absent again.
+//!
+//! The partner can also be a [Printer], following the clock of the GameBoy.
+//!
+
+pub mod link;
+pub mod printer;
+
+use super::interrupts::{Interrupt, Interrupts};
+use crate::gameboy::Model;
+use link::{Link, Message};
+use printer::Printer;
+
+use std::mem;
+
+/// Address of the serial data register.
+pub const SB: u16 = 0xFF01;
+
+/// Address of the serial control register.
+pub const SC: u16 = 0xFF02;
+
+/// SC: a transfer is in progress.
+const SC_TRANSFER: u8 = 0b1000_0000;
+
+/// SC: the fast clock, CGB only.
+const SC_FAST: u8 = 0b0000_0010;
+
+/// SC: the internal clock.
+const SC_INTERNAL: u8 = 0b0000_0001;
+
+/// T-cycles per bit with the internal clock, at 8192 Hz.
+const BIT_CYCLES: u32 = 512;
+
+/// T-cycles per bit with the fast internal clock, at 262144 Hz.
+const FAST_BIT_CYCLES: u32 = 16;
+
+/// T-cycles between two polls of the link while a transfer waits for the partner.
+const POLL_CYCLES: u32 = BIT_CYCLES;
+
+/// Enumerates the partners plugged in the serial port.
+pub enum Partner {
+    /// Another GameBoy, through a link cable.
+    Link(Link),
+
+    /// A Game Boy Printer.
+    Printer(Printer),
+}
+
+pub struct Serial {
+    /// The hardware model emulated.
+    model: Model,
+
+    /// The SB register.
+    sb: u8,
+
+    /// The SC register.
+    sc: u8,
+
+    /// The byte sent by the transfer in progress.
+    outgoing: u8,
+
+    /// The bits left to receive from the printer or an absent partner, most significant first.
+    incoming: u8,
+
+    /// Bits left to exchange in the transfer in progress.
+    bits: u8,
+
+    /// T-cycles since the last bit exchanged with the internal clock.
+    cycles: u32,
+
+    /// T-cycles since the link was last polled.
+    poll_cycles: u32,
+
+    /// The bytes sent since the last call to [Serial::take_output].
+    output: Vec<u8>,
+
+    /// The partner plugged in, if any.
+    partner: Option<Partner>,
+
+    /// The message of the partner waiting for a transfer to complete.
+    received: Option<Message>,
+
+    /// The error that dropped the link, until taken by [Serial::take_link_error].
+    link_error: Option<String>,
+}
+
+impl Serial {
+    /// Constructs an idle serial port of *model*.
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            sb: 0,
+            sc: 0,
+            outgoing: 0,
+            incoming: 0xFF,
+            bits: 0,
+            cycles: 0,
+            poll_cycles: 0,
+            output: Vec::new(),
+            partner: None,
+            received: None,
+            link_error: None,
+        }
+    }
+
+    /// Plugs *partner* in.
+    pub fn set_partner(&mut self, partner: Partner) {
+        self.partner = Some(partner);
+        self.received = None;
+    }
+
+    /// Unplugs the partner, returning it.
+    pub fn take_partner(&mut self) -> Option<Partner> {
+        self.received = None;
+        self.partner.take()
+    }
+
+    /// Returns the printer plugged in, if any.
+    pub fn printer_mut(&mut self) -> Option<&mut Printer> {
+        match &mut self.partner {
+            Some(Partner::Printer(printer)) => Some(printer),
+            _ => None,
+        }
+    }
+
+    /// Returns the error that dropped the link since the last call, if any.
+    pub fn take_link_error(&mut self) -> Option<String> {
+        self.link_error.take()
+    }
+
+    /// Reads the register at *address*, SB or SC. The unused bits of SC read as 1.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            SB => self.sb,
+            _ => self.sc | !self.sc_mask(),
+        }
+    }
+
+    /// Writes *value* to the register at *address*, SB or SC.
+    /// Setting bit 7 of SC starts a transfer.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            SB => self.sb = value,
+            _ => {
+                self.sc = value & self.sc_mask();
+                if self.sc & SC_TRANSFER != 0 {
+                    self.outgoing = self.sb;
+                    self.bits = 8;
+                    self.cycles = 0;
+                    self.poll_cycles = 0;
+                    if self.sc & SC_INTERNAL != 0 {
+                        self.incoming = match &mut self.partner {
+                            Some(Partner::Printer(printer)) => printer.exchange(self.outgoing),
+                            _ => 0xFF,
+                        };
+                        self.send(Message::Transfer(self.outgoing));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the writable bits of SC, the fast clock only exists on the CGB.
+    fn sc_mask(&self) -> u8 {
+        match self.model {
+            Model::DMG => SC_TRANSFER | SC_INTERNAL,
+            Model::CGB => SC_TRANSFER | SC_FAST | SC_INTERNAL,
+        }
+    }
+
+    /// Advances the internal clock by *cycles* CPU T-cycles, exchanging the bits due meanwhile
+    /// with the printer or an absent partner, or the bytes due with a linked one.
+    pub fn tick(&mut self, cycles: u32, interrupts: &mut Interrupts) {
+        if self.sc & SC_TRANSFER == 0 {
+            // A transfer of the partner waits in the link for one to start
+            return;
+        }
+        self.poll(cycles);
+        if self.sc & SC_INTERNAL == 0 {
+            // A reply without a transfer of ours is stale
+            if let Some(Message::Transfer(incoming)) = self.received.take() {
+                self.send(Message::Reply(self.outgoing));
+                self.complete(incoming, interrupts);
+            }
+            return;
+        }
+        let period = match self.sc & SC_FAST {
+            0 => BIT_CYCLES,
+            _ => FAST_BIT_CYCLES,
+        };
+        self.cycles += cycles;
+        if let Some(Partner::Link(link)) = &self.partner {
+            let master = link.master();
+            self.cycles = self.cycles.min(period * self.bits as u32);
+            let elapsed = self.cycles == period * self.bits as u32;
+            match self.received {
+                // Both drive the clock, the follower follows the one of the master
+                Some(Message::Transfer(incoming)) if !master => {
+                    self.received = None;
+                    self.complete(incoming, interrupts);
+                }
+                Some(Message::Transfer(incoming) | Message::Reply(incoming)) if elapsed => {
+                    self.received = None;
+                    self.complete(incoming, interrupts);
+                }
+                _ => (),
+            }
+            return;
+        }
+        while self.cycles >= period && self.sc & SC_TRANSFER != 0 {
+            self.cycles -= period;
+            self.shift(interrupts);
+        }
+    }
+
+    /// Shifts the outgoing bit out of SB and the next incoming bit in, completing the transfer
+    /// after the 8th bit.
+    fn shift(&mut self, interrupts: &mut Interrupts) {
+        self.sb = self.sb << 1 | self.incoming >> 7;
+        self.incoming <<= 1;
+        self.bits -= 1;
+        if self.bits == 0 {
+            self.complete(self.sb, interrupts);
+        }
+    }
+
+    /// Completes the transfer in progress, *incoming* being the byte received.
+    fn complete(&mut self, incoming: u8, interrupts: &mut Interrupts) {
+        self.sb = incoming;
+        self.bits = 0;
+        self.sc &= !SC_TRANSFER;
+        self.output.push(self.outgoing);
+        interrupts.request(Interrupt::Serial);
+    }
+
+    /// Sends *message* to the partner, if linked.
+    fn send(&mut self, message: Message) {
+        if let Some(Partner::Link(link)) = &mut self.partner {
+            if let Err(error) = link.send(message) {
+                self.disconnect(error);
+            }
+        }
+    }
+
+    /// Polls the link every [POLL_CYCLES] of *cycles*, until a message of the partner is kept.
+    fn poll(&mut self, cycles: u32) {
+        if self.received.is_some() {
+            return;
+        }
+        self.poll_cycles += cycles;
+        if self.poll_cycles >= POLL_CYCLES {
+            self.poll_cycles = 0;
+            self.receive();
+        }
+    }
+
+    /// Keeps the next message of the partner, if linked and one arrived.
+    fn receive(&mut self) {
+        if let Some(Partner::Link(link)) = &mut self.partner {
+            match link.receive() {
+                Ok(message) => self.received = message,
+                Err(error) => self.disconnect(error),
+            }
+        }
+    }
+
+    /// Drops the link after *error*, the transfer in progress completing without a partner.
+    fn disconnect(&mut self, error: String) {
+        self.partner = None;
+        self.received = None;
+        self.incoming = 0xFF;
+        self.link_error = Some(error);
+    }
+
+    /// Returns the bytes sent since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::take(&mut self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_registers() {
+        let mut serial = Serial::new(Model::DMG);
+
+        assert_eq!(serial.read(SC), 0x7E);
+        serial.write(SB, 0x5A);
+        serial.write(SC, 0xFF);
+        assert_eq!(serial.read(SB), 0x5A);
+        assert_eq!(serial.read(SC), 0xFF);
+
+        let mut serial = Serial::new(Model::CGB);
+        serial.write(SC, 0x02);
+        assert_eq!(serial.read(SC), 0x7E);
+    }
+
+    #[test]
+    fn test_internal_clock() {
+        let mut serial = Serial::new(Model::DMG);
+        let mut interrupts = Interrupts::new();
+        interrupts.enable = Interrupt::Serial.mask();
+
+        serial.write(SB, b'O');
+        serial.write(SC, SC_TRANSFER | SC_INTERNAL);
+        serial.tick(BIT_CYCLES * 2, &mut interrupts);
+        assert_eq!(serial.read(SB), b'O' << 2 | 0x03);
+        assert_eq!(serial.read(SC), 0xFF);
+
+        serial.tick(BIT_CYCLES * 6 - 1, &mut interrupts);
+        assert_eq!(interrupts.pending(), 0);
+        serial.tick(1, &mut interrupts);
+        assert_eq!(serial.read(SB), 0xFF);
+        assert_eq!(serial.read(SC), 0x7F);
+        assert_eq!(interrupts.pending(), Interrupt::Serial.mask());
+        assert_eq!(serial.take_output(), b"O");
+        assert!(serial.take_output().is_empty());
+    }
+
+    #[test]
+    fn test_fast_clock() {
+        let mut serial = Serial::new(Model::CGB);
+        let mut interrupts = Interrupts::new();
+
+        serial.write(SC, SC_TRANSFER | SC_FAST | SC_INTERNAL);
+        serial.tick(FAST_BIT_CYCLES * 8, &mut interrupts);
+        assert_eq!(serial.read(SC), 0x7F);
+    }
+
+    #[test]
+    fn test_external_clock() {
+        let mut serial = Serial::new(Model::DMG);
+        let mut interrupts = Interrupts::new();
+
+        serial.write(SB, 0x42);
+        serial.write(SC, SC_TRANSFER);
+        serial.tick(BIT_CYCLES * 16, &mut interrupts);
+        assert_eq!(serial.read(SB), 0x42);
+        assert_eq!(serial.read(SC), 0xFE);
+        assert!(serial.take_output().is_empty());
+    }
+
+    /// Connects two serial ports, loaded with *bytes*, through a local link.
+    fn linked(bytes: [u8; 2]) -> [Serial; 2] {
+        let (host, partner) = link::pair();
+        let mut serials = [Serial::new(Model::DMG), Serial::new(Model::DMG)];
+        serials[0].set_partner(Partner::Link(host));
+        serials[1].set_partner(Partner::Link(partner));
+        serials[0].write(SB, bytes[0]);
+        serials[1].write(SB, bytes[1]);
+        serials
+    }
+
+    /// Ticks both *serials* a bit at a time until their transfers complete, returning false if
+    /// they did not after a while.
+    fn exchange(serials: &mut [Serial; 2], interrupts: &mut Interrupts) -> bool {
+        for _ in 0..1000 {
+            if serials
+                .iter()
+                .all(|serial| serial.read(SC) & SC_TRANSFER == 0)
+            {
+                return true;
+            }
+            for serial in serials.iter_mut() {
+                serial.tick(BIT_CYCLES, interrupts);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+
+    #[test]
+    fn test_link() {
+        let mut interrupts = Interrupts::new();
+        let mut serials = linked([0x12, 0x34]);
+
+        serials[0].write(SC, SC_TRANSFER | SC_INTERNAL);
+        serials[1].write(SC, SC_TRANSFER);
+        assert!(exchange(&mut serials, &mut interrupts));
+        assert_eq!(serials[0].read(SB), 0x34);
+        assert_eq!(serials[1].read(SB), 0x12);
+        assert_eq!(serials[0].take_output(), [0x12]);
+        assert_eq!(serials[1].take_output(), [0x34]);
+
+        // Both drive the clock
+        serials[0].write(SC, SC_TRANSFER | SC_INTERNAL);
+        serials[1].write(SC, SC_TRANSFER | SC_INTERNAL);
+        assert!(exchange(&mut serials, &mut interrupts));
+        assert_eq!(serials[0].read(SB), 0x12);
+        assert_eq!(serials[1].read(SB), 0x34);
+    }
+
+    #[test]
+    fn test_link_clock_master() {
+        let mut interrupts = Interrupts::new();
+        let [mut master, mut follower] = linked([0xDE, 0xAD]);
+
+        // Both drive the clock, the follower completes with the byte of the master at once
+        master.write(SC, SC_TRANSFER | SC_INTERNAL);
+        follower.write(SC, SC_TRANSFER | SC_INTERNAL);
+        thread::sleep(Duration::from_millis(50));
+        follower.tick(POLL_CYCLES, &mut interrupts);
+        assert_eq!(follower.read(SC), 0x7F);
+        assert_eq!(follower.read(SB), 0xDE);
+
+        // While the master completes after its 8 bits
+        master.tick(POLL_CYCLES, &mut interrupts);
+        assert_eq!(master.read(SC), 0xFF);
+        master.tick(BIT_CYCLES * 8 - POLL_CYCLES, &mut interrupts);
+        assert_eq!(master.read(SC), 0x7F);
+        assert_eq!(master.read(SB), 0xAD);
+    }
+
+    #[test]
+    fn test_link_idle() {
+        let mut interrupts = Interrupts::new();
+        let mut serials = linked([0x11, 0x22]);
+
+        // The transfer of the partner waits in the link until one is started
+        serials[0].write(SC, SC_TRANSFER | SC_INTERNAL);
+        thread::sleep(Duration::from_millis(50));
+        serials[1].tick(BIT_CYCLES * 8, &mut interrupts);
+        assert!(serials[1].received.is_none());
+
+        serials[1].write(SC, SC_TRANSFER);
+        assert!(exchange(&mut serials, &mut interrupts));
+        assert_eq!(serials[0].read(SB), 0x22);
+        assert_eq!(serials[1].read(SB), 0x11);
+    }
+
+    #[test]
+    fn test_link_lock_step() {
+        let mut interrupts = Interrupts::new();
+        let mut serials = linked([0x56, 0x78]);
+
+        // The partner is not ready, the transfer waits for it
+        serials[0].write(SC, SC_TRANSFER | SC_INTERNAL);
+        assert!(!exchange(&mut serials, &mut interrupts));
+        assert_eq!(serials[0].read(SB), 0x56);
+
+        serials[1].write(SC, SC_TRANSFER);
+        assert!(exchange(&mut serials, &mut interrupts));
+        assert_eq!(serials[0].read(SB), 0x78);
+        assert_eq!(serials[1].read(SB), 0x56);
+        assert!(serials[0].take_link_error().is_none());
+    }
+
+    #[test]
+    fn test_link_disconnect() {
+        let mut interrupts = Interrupts::new();
+        let [mut serial, mut partner] = linked([0x9A, 0xBC]);
+
+        serial.write(SC, SC_TRANSFER | SC_INTERNAL);
+        drop(partner.take_partner());
+        for _ in 0..1000 {
+            serial.tick(BIT_CYCLES, &mut interrupts);
+            if serial.read(SC) & SC_TRANSFER == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(serial.read(SB), 0xFF);
+        assert!(serial.take_link_error().is_some());
+        assert!(serial.take_partner().is_none());
+    }
+
+    #[test]
+    fn test_printer() {
+        let mut serial = Serial::new(Model::DMG);
+        let mut interrupts = Interrupts::new();
+        serial.set_partner(Partner::Printer(Printer::new()));
+
+        // Status packet
+        let answers: Vec<u8> = [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x0F, 0x00, 0x00, 0x00]
+            .into_iter()
+            .map(|byte| {
+                serial.write(SB, byte);
+                serial.write(SC, SC_TRANSFER | SC_INTERNAL);
+                serial.tick(BIT_CYCLES * 8, &mut interrupts);
+                serial.read(SB)
+            })
+            .collect();
+        assert_eq!(answers, [0, 0, 0, 0, 0, 0, 0, 0, 0x81, 0x00]);
+        assert!(serial.printer_mut().is_some());
+    }
+}
@@ -0,0 +1,292 @@
+//! Link cable over a local TCP connection.
+//!
+//! One GameBoy hosts the cable by listening on a port of localhost, the other joins it by
+//! connecting to that port. Both first exchange a handshake, `GBMU` followed by the protocol
+//! version and the role claimed, then messages of 2 bytes, a kind and a byte of serial data:
+//!
+//! | Kind   | Message  | Meaning                                                         |
+//! |--------|----------|-----------------------------------------------------------------|
+//! | `0x01` | Transfer | The sender drives the clock and sends the byte of its transfer  |
+//! | `0x02` | Reply    | The sender followed the clock of a transfer and returns its SB  |
+//!
+//! The handshake negotiates the clock master of the cable: the host claims it and the joining
+//! GameBoy follows, the connection failing if both claim the same role. The programs still
+//! choose which side drives each transfer through bit 0 of SC, the clock master only wins when
+//! both drive the clock at once. See [super::Serial] for how the messages lock-step the
+//! transfers of both GameBoys.
+//!
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Handshake sent by both partners on connection, the protocol name then its version, followed
+/// by the role claimed.
+const HANDSHAKE: [u8; 5] = [b'G', b'B', b'M', b'U', 2];
+
+/// Handshake role: the sender is the clock master.
+const ROLE_MASTER: u8 = 0x01;
+
+/// Handshake role: the sender follows the clock master.
+const ROLE_FOLLOWER: u8 = 0x00;
+
+/// Time allowed to the partner to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Message kind: the sender drives the clock.
+const KIND_TRANSFER: u8 = 0x01;
+
+/// Message kind: the sender followed the clock.
+const KIND_REPLY: u8 = 0x02;
+
+/// Size of a message, its kind and its byte of data.
+const MESSAGE_SIZE: usize = 2;
+
+/// Bytes read from the connection at once.
+const READ_SIZE: usize = 64;
+
+/// Enumerates the messages exchanged with the partner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// The partner started a transfer with its internal clock, sending this byte.
+    Transfer(u8),
+
+    /// The partner completed the transfer of the last [Message::Transfer] sent, returning this
+    /// byte.
+    Reply(u8),
+}
+
+pub struct Link {
+    /// The connection to the partner, non-blocking once the handshake is done.
+    stream: TcpStream,
+
+    /// The bytes received and not consumed as messages yet.
+    received: Vec<u8>,
+
+    /// The bytes that could not be sent yet without blocking.
+    sending: Vec<u8>,
+
+    /// Whether this side is the clock master, as negotiated by the handshake.
+    master: bool,
+}
+
+impl Link {
+    /// Listens on *port* of localhost and waits for a partner to join.
+    pub fn host(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .map_err(|error| format!("Could not listen on port {}: {}", port, error))?;
+        let (stream, _) = listener
+            .accept()
+            .map_err(|error| format!("Could not accept a partner: {}", error))?;
+        Self::new(stream, true)
+    }
+
+    /// Connects to the partner hosting on *port* of localhost.
+    pub fn join(port: u16) -> Result<Self, String> {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .map_err(|error| format!("Could not join port {}: {}", port, error))?;
+        Self::new(stream, false)
+    }
+
+    /// Exchanges the handshake on *stream*, claiming the clock master role if *master*, then
+    /// switches it to non-blocking.
+    fn new(mut stream: TcpStream, master: bool) -> Result<Self, String> {
+        let error = |error: std::io::Error| format!("Handshake failed: {}", error);
+        stream.set_nodelay(true).map_err(error)?;
+        stream
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+            .map_err(error)?;
+        let role = match master {
+            true => ROLE_MASTER,
+            false => ROLE_FOLLOWER,
+        };
+        stream.write_all(&HANDSHAKE).map_err(error)?;
+        stream.write_all(&[role]).map_err(error)?;
+        let mut handshake = [0; HANDSHAKE.len()];
+        stream.read_exact(&mut handshake).map_err(error)?;
+        if handshake != HANDSHAKE {
+            return Err("Handshake failed: the partner is not a compatible gbmu".to_string());
+        }
+        let mut partner_role = [0];
+        stream.read_exact(&mut partner_role).map_err(error)?;
+        if partner_role[0] == role {
+            return Err("Handshake failed: both partners claim the same clock role".to_string());
+        }
+        stream.set_nonblocking(true).map_err(error)?;
+        Ok(Self {
+            stream,
+            received: Vec::with_capacity(READ_SIZE),
+            sending: Vec::new(),
+            master,
+        })
+    }
+
+    /// Returns true if this side is the clock master of the cable.
+    pub fn master(&self) -> bool {
+        self.master
+    }
+
+    /// Sends *message* to the partner. The bytes the connection cannot take yet are kept and
+    /// sent by the next calls to [Link::send] or [Link::receive].
+    pub fn send(&mut self, message: Message) -> Result<(), String> {
+        match message {
+            Message::Transfer(data) => self.sending.extend_from_slice(&[KIND_TRANSFER, data]),
+            Message::Reply(data) => self.sending.extend_from_slice(&[KIND_REPLY, data]),
+        }
+        self.flush()
+    }
+
+    /// Sends as many of the bytes kept as the connection takes without blocking.
+    fn flush(&mut self) -> Result<(), String> {
+        while !self.sending.is_empty() {
+            match self.stream.write(&self.sending) {
+                Ok(0) => return Err("The partner disconnected".to_string()),
+                Ok(count) => {
+                    self.sending.drain(..count);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                Err(error) => return Err(format!("Could not send to the partner: {}", error)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the next message of the partner, or `None` if none arrived yet.
+    pub fn receive(&mut self) -> Result<Option<Message>, String> {
+        self.flush()?;
+        while self.received.len() < MESSAGE_SIZE {
+            let mut bytes = [0; READ_SIZE];
+            match self.stream.read(&mut bytes) {
+                Ok(0) => return Err("The partner disconnected".to_string()),
+                Ok(count) => self.received.extend_from_slice(&bytes[..count]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                Err(error) => return Err(format!("Could not receive from the partner: {}", error)),
+            }
+        }
+        let message = match self.received[..MESSAGE_SIZE] {
+            [KIND_TRANSFER, data] => Message::Transfer(data),
+            [KIND_REPLY, data] => Message::Reply(data),
+            _ => {
+                return Err(format!(
+                    "Invalid message {:02X?}",
+                    &self.received[..MESSAGE_SIZE]
+                ))
+            }
+        };
+        self.received.drain(..MESSAGE_SIZE);
+        Ok(Some(message))
+    }
+}
+
+/// Connects two links to each other through localhost.
+#[cfg(test)]
+pub fn pair() -> (Link, Link) {
+    use std::thread;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let joining = thread::spawn(move || Link::join(port).unwrap());
+    let host = Link::new(listener.accept().unwrap().0, true).unwrap();
+    (host, joining.join().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    /// Waits for the next message of *link*.
+    fn wait(link: &mut Link) -> Message {
+        loop {
+            if let Some(message) = link.receive().unwrap() {
+                return message;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_messages() {
+        let (mut host, mut partner) = pair();
+
+        assert_eq!(host.receive(), Ok(None));
+        host.send(Message::Transfer(0x42)).unwrap();
+        host.send(Message::Reply(0xFF)).unwrap();
+        assert_eq!(wait(&mut partner), Message::Transfer(0x42));
+        assert_eq!(wait(&mut partner), Message::Reply(0xFF));
+
+        partner.send(Message::Reply(0x00)).unwrap();
+        assert_eq!(wait(&mut host), Message::Reply(0x00));
+    }
+
+    #[test]
+    fn test_burst() {
+        let (mut host, mut partner) = pair();
+
+        // More than the connection buffers, the rest is sent as the partner receives
+        let count = 1 << 20;
+        for index in 0..count {
+            host.send(Message::Transfer(index as u8)).unwrap();
+        }
+        for index in 0..count {
+            let message = loop {
+                host.receive().unwrap();
+                if let Some(message) = partner.receive().unwrap() {
+                    break message;
+                }
+            };
+            assert_eq!(message, Message::Transfer(index as u8));
+        }
+        assert!(host.sending.is_empty());
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (mut host, partner) = pair();
+
+        drop(partner);
+        let error = loop {
+            match host.receive() {
+                Ok(_) => thread::sleep(Duration::from_millis(1)),
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(error, "The partner disconnected");
+    }
+
+    #[test]
+    fn test_handshake() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let joining = thread::spawn(move || Link::join(port));
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"HTTP/1").unwrap();
+
+        assert!(joining.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_roles() {
+        let (host, partner) = pair();
+        assert!(host.master());
+        assert!(!partner.master());
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let joining = thread::spawn(move || {
+            Link::new(
+                TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap(),
+                true,
+            )
+        });
+        let hosting = Link::new(listener.accept().unwrap().0, true);
+        assert_eq!(
+            hosting.err().unwrap(),
+            "Handshake failed: both partners claim the same clock role"
+        );
+        assert!(joining.join().unwrap().is_err());
+    }
+}
@@ -0,0 +1,510 @@
+//! Game Boy Printer.
+//!
+//! The printer follows the clock of the GameBoy and receives packets, answering each byte. A
+//! packet is made of:
+//!
+//! | Bytes       | Content                                            | Printer answer |
+//! |-------------|----------------------------------------------------|----------------|
+//! | 2           | Magic bytes `0x88`, `0x33`                         | `0x00`         |
+//! | 1           | Command                                            | `0x00`         |
+//! | 1           | Compression flag, bit 0                            | `0x00`         |
+//! | 2           | Length of the data, little endian                  | `0x00`         |
+//! | Length      | Data                                               | `0x00`         |
+//! | 2           | Checksum, the sum of the command to the data bytes | `0x00`         |
+//! | 1           | `0x00`                                             | `0x81`, alive  |
+//! | 1           | `0x00`                                             | Status         |
+//!
+//! The commands are:
+//! - `0x01`, init: clears the image buffer.
+//! - `0x02`, print: prints the image buffer, its 4 data bytes being the number of sheets, the
+//!   margins, the palette and the exposure.
+//! - `0x04`, data: appends 640 bytes of image to the buffer, a band of 2 rows of 20 tiles. When
+//!   compressed, the data is run-length encoded.
+//! - `0x0F`, status: only asks for the status.
+//!
+//! The status reports a checksum error (bit 0), a print in progress (bit 1), a full image
+//! buffer (bit 2) and image data not printed yet (bit 3).
+//!
+//! Printing renders the buffer, 160 pixels wide, through the palette, in the same way as the
+//! DMG background, once per sheet. The upper nibble of the margins feeds paper before the
+//! image and the lower nibble after it, each feed being rendered as 16 blank pixel rows.
+//! Consecutive prints form one page, completed by a print feeding paper after its image or by
+//! [Printer::finish_page], and the pages are collected with [Printer::take_printouts]. The
+//! exposure is ignored.
+//!
+
+use super::super::ppu::DMG_COLORS;
+
+use std::mem;
+
+/// Width of a printout in pixels.
+pub const PRINT_WIDTH: usize = 160;
+
+/// First magic byte of a packet.
+const MAGIC_1: u8 = 0x88;
+
+/// Second magic byte of a packet.
+const MAGIC_2: u8 = 0x33;
+
+/// Answer to the first byte following the checksum.
+const ALIVE: u8 = 0x81;
+
+/// Command: clears the image buffer.
+const COMMAND_INIT: u8 = 0x01;
+
+/// Command: prints the image buffer.
+const COMMAND_PRINT: u8 = 0x02;
+
+/// Command: appends a band to the image buffer.
+const COMMAND_DATA: u8 = 0x04;
+
+/// Status: the checksum of the last packet was wrong.
+const STATUS_CHECKSUM_ERROR: u8 = 0b0000_0001;
+
+/// Status: a print is in progress.
+const STATUS_PRINTING: u8 = 0b0000_0010;
+
+/// Status: the image buffer is full.
+const STATUS_FULL: u8 = 0b0000_0100;
+
+/// Status: the image buffer holds data not printed yet.
+const STATUS_UNPROCESSED: u8 = 0b0000_1000;
+
+/// Size of the image buffer, 9 bands.
+const BUFFER_SIZE: usize = 0x1680;
+
+/// Size of a tile in the image buffer.
+const TILE_SIZE: usize = 16;
+
+/// Number of tiles in a row of the image buffer.
+const TILES_PER_ROW: usize = PRINT_WIDTH / 8;
+
+/// Palette applied when a print gives none, the identity.
+const DEFAULT_PALETTE: u8 = 0xE4;
+
+/// Pixel rows rendered per paper feed.
+const FEED_ROWS: usize = 16;
+
+/// Status answers reporting a print in progress after each print.
+const PRINT_BUSY_ANSWERS: u8 = 4;
+
+/// Enumerates the parts of a packet, the next byte received belonging to the current one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+/// A printed page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Printout {
+    /// Height of the page in pixels.
+    pub height: usize,
+
+    /// The pixels of the page, one `0xRRGGBB` color per pixel, row by row.
+    pub pixels: Vec<u32>,
+}
+
+pub struct Printer {
+    /// The part of the packet being received.
+    state: State,
+
+    /// The command of the packet being received.
+    command: u8,
+
+    /// Set if the data of the packet being received is compressed.
+    compressed: bool,
+
+    /// The length of the data of the packet being received.
+    length: u16,
+
+    /// The data of the packet being received.
+    data: Vec<u8>,
+
+    /// The checksum computed over the packet being received.
+    checksum: u16,
+
+    /// The checksum sent with the packet being received.
+    expected_checksum: u16,
+
+    /// Set if the checksum of the last packet was wrong.
+    checksum_error: bool,
+
+    /// The image buffer, tile data of 20 tiles per row.
+    buffer: Vec<u8>,
+
+    /// Status answers left reporting the print in progress.
+    busy: u8,
+
+    /// The pixels of the page being printed.
+    page: Vec<u32>,
+
+    /// The pages completed since the last call to [Printer::take_printouts].
+    printouts: Vec<Printout>,
+}
+
+impl Printer {
+    /// Constructs an idle printer with an empty image buffer.
+    pub fn new() -> Self {
+        Self {
+            state: State::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            expected_checksum: 0,
+            checksum_error: false,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            busy: 0,
+            page: Vec::new(),
+            printouts: Vec::new(),
+        }
+    }
+
+    /// Receives *byte* from the GameBoy, returning the answer shifted out meanwhile.
+    pub fn exchange(&mut self, byte: u8) -> u8 {
+        let answer = match self.state {
+            State::Alive => ALIVE,
+            State::Status => self.status(),
+            _ => 0x00,
+        };
+        self.state = match self.state {
+            State::Magic1 | State::Magic2 if byte == MAGIC_1 => State::Magic2,
+            State::Magic2 if byte == MAGIC_2 => State::Command,
+            State::Magic1 | State::Magic2 => State::Magic1,
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                State::Compression
+            }
+            State::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthLow
+            }
+            State::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                match self.length {
+                    0 => State::ChecksumLow,
+                    _ => State::Data,
+                }
+            }
+            State::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                match self.data.len() == self.length as usize {
+                    true => State::ChecksumLow,
+                    false => State::Data,
+                }
+            }
+            State::ChecksumLow => {
+                self.expected_checksum = byte as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                self.expected_checksum |= (byte as u16) << 8;
+                self.run();
+                State::Alive
+            }
+            State::Alive => State::Status,
+            State::Status => State::Magic1,
+        };
+        answer
+    }
+
+    /// Completes the page being printed, if any, as when the paper is torn off.
+    pub fn finish_page(&mut self) {
+        if !self.page.is_empty() {
+            let pixels = mem::take(&mut self.page);
+            self.printouts.push(Printout {
+                height: pixels.len() / PRINT_WIDTH,
+                pixels,
+            });
+        }
+    }
+
+    /// Returns the pages completed since the last call.
+    pub fn take_printouts(&mut self) -> Vec<Printout> {
+        mem::take(&mut self.printouts)
+    }
+
+    /// Returns the status, counting down the print in progress.
+    fn status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.checksum_error {
+            status |= STATUS_CHECKSUM_ERROR;
+        }
+        if self.busy > 0 {
+            self.busy -= 1;
+            status |= STATUS_PRINTING;
+        }
+        if self.buffer.len() >= BUFFER_SIZE {
+            status |= STATUS_FULL;
+        }
+        if !self.buffer.is_empty() {
+            status |= STATUS_UNPROCESSED;
+        }
+        status
+    }
+
+    /// Runs the command of the packet received, unless its checksum is wrong.
+    fn run(&mut self) {
+        self.checksum_error = self.checksum != self.expected_checksum;
+        if self.checksum_error {
+            return;
+        }
+        match self.command {
+            COMMAND_INIT => {
+                self.buffer.clear();
+                self.busy = 0;
+            }
+            COMMAND_PRINT => {
+                if let [sheets, margins, palette, _exposure] = self.data[..] {
+                    self.print(sheets, margins, palette);
+                }
+            }
+            COMMAND_DATA => {
+                let data = match self.compressed {
+                    true => decompress(&self.data),
+                    false => mem::take(&mut self.data),
+                };
+                let free = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend(data.into_iter().take(free));
+            }
+            _ => (),
+        }
+    }
+
+    /// Prints the image buffer through *palette* on *sheets* sheets, feeding paper for
+    /// *margins*. No sheet only feeds paper.
+    fn print(&mut self, sheets: u8, margins: u8, palette: u8) {
+        let palette = match palette {
+            0 => DEFAULT_PALETTE,
+            _ => palette,
+        };
+        self.feed(margins >> 4);
+        let mut strip = Vec::new();
+        if sheets > 0 {
+            let rows = self.buffer.len() / (TILE_SIZE * TILES_PER_ROW) * 8;
+            for y in 0..rows {
+                for x in 0..PRINT_WIDTH {
+                    let tile = y / 8 * TILES_PER_ROW + x / 8;
+                    let address = tile * TILE_SIZE + y % 8 * 2;
+                    let bit = 7 - x % 8;
+                    let low = self.buffer[address] >> bit & 0x01;
+                    let high = self.buffer[address + 1] >> bit & 0x01;
+                    let color = high << 1 | low;
+                    let shade = palette >> (color * 2) & 0x03;
+                    strip.push(DMG_COLORS[shade as usize]);
+                }
+            }
+        }
+        for _ in 0..sheets {
+            self.page.extend_from_slice(&strip);
+        }
+        self.buffer.clear();
+        self.busy = PRINT_BUSY_ANSWERS;
+        self.feed(margins & 0x0F);
+        if margins & 0x0F != 0 {
+            self.finish_page();
+        }
+    }
+
+    /// Feeds *feeds* blank pieces of paper to the page.
+    fn feed(&mut self, feeds: u8) {
+        let pixels = feeds as usize * FEED_ROWS * PRINT_WIDTH;
+        self.page.resize(self.page.len() + pixels, DMG_COLORS[0]);
+    }
+}
+
+/// Decompresses the run-length encoded *data*. A control byte with bit 7 set repeats the next
+/// byte its lower 7 bits plus 2 times, otherwise it is followed by its value plus 1 bytes.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    let mut bytes = data.iter();
+    while let Some(&control) = bytes.next() {
+        if control & 0x80 != 0 {
+            if let Some(&byte) = bytes.next() {
+                let count = (control & 0x7F) as usize + 2;
+                decompressed.resize(decompressed.len() + count, byte);
+            }
+        } else {
+            decompressed.extend(bytes.by_ref().take(control as usize + 1));
+        }
+    }
+    decompressed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a packet of *command* with *data*, returning the answers of the alive and status
+    /// bytes.
+    fn send(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+        let length = (data.len() as u16).to_le_bytes();
+        let mut packet = vec![command, compressed as u8, length[0], length[1]];
+        packet.extend_from_slice(data);
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+
+        for byte in [MAGIC_1, MAGIC_2].iter().chain(&packet) {
+            assert_eq!(printer.exchange(*byte), 0x00);
+        }
+        (printer.exchange(0x00), printer.exchange(0x00))
+    }
+
+    #[test]
+    fn test_packets() {
+        let mut printer = Printer::new();
+
+        assert_eq!(send(&mut printer, COMMAND_INIT, false, &[]), (ALIVE, 0x00));
+        let band = [0; 640];
+        assert_eq!(
+            send(&mut printer, COMMAND_DATA, false, &band),
+            (ALIVE, 0x08)
+        );
+        for _ in 0..8 {
+            send(&mut printer, COMMAND_DATA, false, &band);
+        }
+        assert_eq!(send(&mut printer, 0x0F, false, &[]), (ALIVE, 0x0C));
+
+        assert_eq!(
+            send(&mut printer, COMMAND_PRINT, false, &[1, 0x00, 0xE4, 0x40]),
+            (ALIVE, 0x02)
+        );
+        for _ in 0..3 {
+            assert_eq!(send(&mut printer, 0x0F, false, &[]), (ALIVE, 0x02));
+        }
+        assert_eq!(send(&mut printer, 0x0F, false, &[]), (ALIVE, 0x00));
+
+        // Noise before the magic bytes is skipped
+        printer.exchange(0x00);
+        printer.exchange(0x88);
+        assert_eq!(send(&mut printer, 0x0F, false, &[]), (ALIVE, 0x00));
+    }
+
+    #[test]
+    fn test_checksum_error() {
+        let mut printer = Printer::new();
+
+        for byte in [
+            MAGIC_1,
+            MAGIC_2,
+            COMMAND_DATA,
+            0x00,
+            0x01,
+            0x00,
+            0xFF,
+            0x00,
+            0x00,
+        ] {
+            printer.exchange(byte);
+        }
+        assert_eq!(printer.exchange(0x00), ALIVE);
+        assert_eq!(printer.exchange(0x00), STATUS_CHECKSUM_ERROR);
+        assert_eq!(send(&mut printer, 0x0F, false, &[]), (ALIVE, 0x00));
+    }
+
+    #[test]
+    fn test_print() {
+        let mut printer = Printer::new();
+
+        // First tile row: color 1, 2, 3 then 0 in the first tile, color 0 elsewhere
+        let mut band = vec![0; 640];
+        band[..16].copy_from_slice(&[0b1010_0000, 0b0110_0000].repeat(8));
+        send(&mut printer, COMMAND_DATA, false, &band);
+        send(&mut printer, COMMAND_PRINT, false, &[1, 0x10, 0xE4, 0x40]);
+        assert!(printer.take_printouts().is_empty());
+
+        // Inverted palette, then feeds
+        send(&mut printer, COMMAND_DATA, false, &band);
+        send(&mut printer, COMMAND_PRINT, false, &[1, 0x02, 0x1B, 0x40]);
+        let printouts = printer.take_printouts();
+        assert_eq!(printouts.len(), 1);
+        let printout = &printouts[0];
+        assert_eq!(printout.height, FEED_ROWS + 16 + 16 + FEED_ROWS * 2);
+        assert_eq!(printout.pixels.len(), printout.height * PRINT_WIDTH);
+
+        let row = |y: usize| &printout.pixels[y * PRINT_WIDTH..][..5];
+        assert_eq!(row(FEED_ROWS - 1), [DMG_COLORS[0]; 5]);
+        assert_eq!(
+            row(FEED_ROWS),
+            [
+                DMG_COLORS[1],
+                DMG_COLORS[2],
+                DMG_COLORS[3],
+                DMG_COLORS[0],
+                DMG_COLORS[0]
+            ]
+        );
+        assert_eq!(
+            row(FEED_ROWS + 16 + 7),
+            [
+                DMG_COLORS[2],
+                DMG_COLORS[1],
+                DMG_COLORS[0],
+                DMG_COLORS[3],
+                DMG_COLORS[3]
+            ]
+        );
+        assert_eq!(row(FEED_ROWS + 16 + 8), [DMG_COLORS[3]; 5]);
+    }
+
+    #[test]
+    fn test_print_sheets() {
+        let mut printer = Printer::new();
+
+        // Two copies of the band, without feed after them
+        send(&mut printer, COMMAND_DATA, false, &[0xFF; 640]);
+        send(&mut printer, COMMAND_PRINT, false, &[2, 0x00, 0xE4, 0x40]);
+        assert!(printer.take_printouts().is_empty());
+
+        // The pending page is completed on demand
+        printer.finish_page();
+        let printouts = printer.take_printouts();
+        assert_eq!(printouts.len(), 1);
+        assert_eq!(printouts[0].height, 16 * 2);
+        assert_eq!(
+            printouts[0].pixels,
+            vec![DMG_COLORS[3]; 16 * 2 * PRINT_WIDTH]
+        );
+
+        printer.finish_page();
+        assert!(printer.take_printouts().is_empty());
+    }
+
+    #[test]
+    fn test_compressed_data() {
+        let mut printer = Printer::new();
+
+        let data = [0x81, 0xAB, 0x02, 0x01, 0x02, 0x03, 0xFF, 0x00];
+        assert_eq!(
+            decompress(&data),
+            [vec![0xAB; 3], vec![0x01, 0x02, 0x03], vec![0x00; 129]].concat()
+        );
+
+        let data = [0x80 | 126, 0x00].repeat(5);
+        send(&mut printer, COMMAND_DATA, true, &data);
+        assert_eq!(printer.buffer.len(), 640);
+    }
+}
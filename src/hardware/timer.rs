@@ -0,0 +1,277 @@
+//! Timer.
+//!
+//! The timer is driven by a 16-bit system counter incremented every T-cycle. Its registers are:
+//!
+//! | Address  | Register | Content                                                       |
+//! |----------|----------|---------------------------------------------------------------|
+//! | `0xFF04` | DIV      | Upper byte of the system counter, writing resets the counter  |
+//! | `0xFF05` | TIMA     | Timer counter                                                 |
+//! | `0xFF06` | TMA      | Value loaded into TIMA when it overflows                      |
+//! | `0xFF07` | TAC      | Bit 2: timer enable, bits 0-1: clock select                   |
+//!
+//! TIMA is incremented on the falling edge of the system counter bit selected by TAC, while the
+//! timer is enabled:
+//!
+//! | TAC bits 0-1 | Counter bit | Frequency  |
+//! |--------------|-------------|------------|
+//! | `00`         | 9           | 4096 Hz    |
+//! | `01`         | 3           | 262144 Hz  |
+//! | `10`         | 5           | 65536 Hz   |
+//! | `11`         | 7           | 16384 Hz   |
+//!
+//! Since the edge detector sees the selected bit ANDed with the enable bit, resetting DIV or
+//! writing TAC may produce a falling edge and increment TIMA.
+//!
+//! When TIMA overflows, it holds `0x00` for one M-cycle before TMA is loaded and the timer
+//! interrupt is requested. Writing TIMA during that M-cycle cancels the reload, while writes to
+//! TIMA on the M-cycle of the reload are ignored and writes to TMA are also copied to TIMA.
+//!
+
+use super::interrupts::{Interrupt, Interrupts};
+
+/// Address of the divider register.
+pub const DIV: u16 = 0xFF04;
+
+/// Address of the timer counter.
+pub const TIMA: u16 = 0xFF05;
+
+/// Address of the timer modulo.
+pub const TMA: u16 = 0xFF06;
+
+/// Address of the timer control register.
+pub const TAC: u16 = 0xFF07;
+
+/// The timer enable bit of TAC.
+const TAC_ENABLE: u8 = 0b0000_0100;
+
+/// The clock select bits of TAC.
+const TAC_CLOCK: u8 = 0b0000_0011;
+
+/// T-cycles in one M-cycle, the timer is updated once per M-cycle.
+const M_CYCLE: u32 = 4;
+
+#[derive(Default)]
+pub struct Timer {
+    /// The 16-bit system counter, DIV is its upper byte.
+    counter: u16,
+
+    /// The TIMA register.
+    tima: u8,
+
+    /// The TMA register.
+    tma: u8,
+
+    /// The TAC register.
+    tac: u8,
+
+    /// Set during the M-cycle following an overflow, TMA is loaded at its end.
+    overflow: bool,
+
+    /// Set during the M-cycle TMA is loaded into TIMA.
+    reloading: bool,
+}
+
+impl Timer {
+    /// Constructs a timer with every register cleared.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the timer by *cycles* T-cycles, requesting the timer interrupt on *interrupts*
+    /// when TIMA is reloaded.
+    pub fn tick(&mut self, cycles: u32, interrupts: &mut Interrupts) {
+        for _ in 0..cycles / M_CYCLE {
+            self.reloading = false;
+            if self.overflow {
+                self.overflow = false;
+                self.reloading = true;
+                self.tima = self.tma;
+                interrupts.request(Interrupt::Timer);
+            }
+
+            let signal = self.signal();
+            self.counter = self.counter.wrapping_add(M_CYCLE as u16);
+            self.detect_falling_edge(signal);
+        }
+    }
+
+    /// Reads the register at *address*, in the range `0xFF04-0xFF07`.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            DIV => (self.counter >> 8) as u8,
+            TIMA => self.tima,
+            TMA => self.tma,
+            TAC => self.tac | !(TAC_ENABLE | TAC_CLOCK),
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes *value* to the register at *address*, in the range `0xFF04-0xFF07`.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            DIV => {
+                let signal = self.signal();
+                self.counter = 0;
+                self.detect_falling_edge(signal);
+            }
+            TIMA if self.reloading => (),
+            TIMA => {
+                self.tima = value;
+                self.overflow = false;
+            }
+            TMA => {
+                self.tma = value;
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
+            TAC => {
+                let signal = self.signal();
+                self.tac = value & (TAC_ENABLE | TAC_CLOCK);
+                self.detect_falling_edge(signal);
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns the input of the edge detector: the selected counter bit ANDed with the timer
+    /// enable bit.
+    fn signal(&self) -> bool {
+        let bit = match self.tac & TAC_CLOCK {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        self.tac & TAC_ENABLE != 0 && self.counter & (1 << bit) != 0
+    }
+
+    /// Increments TIMA if the edge detector input fell from the *previous* signal.
+    fn detect_falling_edge(&mut self, previous: bool) {
+        if previous && !self.signal() {
+            let (tima, overflow) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            self.overflow |= overflow;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Constructs a timer enabled at 262144 Hz, incrementing TIMA every 16 T-cycles.
+    fn timer() -> Timer {
+        let mut timer = Timer::new();
+        timer.write(TAC, TAC_ENABLE | 0b01);
+        timer
+    }
+
+    #[test]
+    fn test_div() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+
+        timer.tick(0x1FC, &mut interrupts);
+        assert_eq!(timer.read(DIV), 0x01);
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.read(DIV), 0x02);
+
+        timer.write(DIV, 0x42);
+        assert_eq!(timer.read(DIV), 0x00);
+        assert_eq!(timer.read(TIMA), 0x00);
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let mut interrupts = Interrupts::new();
+
+        for (clock, period) in [(0b00, 1024), (0b01, 16), (0b10, 64), (0b11, 256)] {
+            let mut timer = Timer::new();
+            timer.write(TAC, TAC_ENABLE | clock);
+
+            timer.tick(period - 4, &mut interrupts);
+            assert_eq!(timer.read(TIMA), 0);
+            timer.tick(4, &mut interrupts);
+            assert_eq!(timer.read(TIMA), 1);
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+
+        timer.write(TAC, 0b01);
+        timer.tick(1024, &mut interrupts);
+        assert_eq!(timer.read(TIMA), 0);
+        assert_eq!(timer.read(TAC), 0xF9);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut timer = timer();
+        let mut interrupts = Interrupts::new();
+        interrupts.enable = 0x04;
+
+        timer.write(TMA, 0x42);
+        timer.write(TIMA, 0xFF);
+        timer.tick(16, &mut interrupts);
+        assert_eq!(timer.read(TIMA), 0x00);
+        assert_eq!(interrupts.pending(), 0);
+
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.read(TIMA), 0x42);
+        assert_eq!(interrupts.next(), Some(Interrupt::Timer));
+    }
+
+    #[test]
+    fn test_overflow_canceled() {
+        let mut timer = timer();
+        let mut interrupts = Interrupts::new();
+        interrupts.enable = 0x04;
+
+        timer.write(TMA, 0x42);
+        timer.write(TIMA, 0xFF);
+        timer.tick(16, &mut interrupts);
+        timer.write(TIMA, 0x10);
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.read(TIMA), 0x10);
+        assert_eq!(interrupts.pending(), 0);
+    }
+
+    #[test]
+    fn test_write_during_reload() {
+        let mut timer = timer();
+        let mut interrupts = Interrupts::new();
+
+        timer.write(TIMA, 0xFF);
+        timer.tick(20, &mut interrupts);
+
+        timer.write(TIMA, 0x10);
+        assert_eq!(timer.read(TIMA), 0x00);
+        timer.write(TMA, 0x24);
+        assert_eq!(timer.read(TIMA), 0x24);
+    }
+
+    #[test]
+    fn test_div_write_glitch() {
+        let mut timer = timer();
+        let mut interrupts = Interrupts::new();
+
+        timer.tick(8, &mut interrupts);
+        assert_eq!(timer.read(TIMA), 0);
+        timer.write(DIV, 0x00);
+        assert_eq!(timer.read(TIMA), 1);
+    }
+
+    #[test]
+    fn test_tac_write_glitch() {
+        let mut timer = timer();
+        let mut interrupts = Interrupts::new();
+
+        timer.tick(8, &mut interrupts);
+        timer.write(TAC, 0b01);
+        assert_eq!(timer.read(TIMA), 1);
+    }
+}
@@ -1,9 +1,94 @@
+mod gameboy;
 mod graphics;
 mod hardware;
+use graphics::input::InputMap;
 use graphics::Graphics;
+use hardware::bootrom::Bootrom;
+use hardware::ppu::Renderer;
+use hardware::serial::link::Link;
+use hardware::serial::printer::Printer;
+use hardware::serial::Partner;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Input map loaded when no `--input` file is given, if it exists
+const DEFAULT_INPUT_PATH: &str = "config/input.cfg";
+
+/// Port of localhost used by the link cable when no `--port` is given
+const DEFAULT_LINK_PORT: u16 = 8765;
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let port = args
+        .iter()
+        .position(|arg| arg == "--port")
+        .and_then(|index| args.get(index + 1));
+    let port = match port.map(|port| port.parse()) {
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            println!("Invalid link cable port, using {}", DEFAULT_LINK_PORT);
+            DEFAULT_LINK_PORT
+        }
+        None => DEFAULT_LINK_PORT,
+    };
+    let printer = args.iter().position(|arg| arg == "--printer");
+    let link = if printer.is_some() {
+        None
+    } else if args.iter().any(|arg| arg == "--host") {
+        println!("Waiting for a partner to join on port {}...", port);
+        Some(Link::host(port))
+    } else if args.iter().any(|arg| arg == "--join") {
+        Some(Link::join(port))
+    } else {
+        None
+    };
+    let link = match link {
+        Some(Ok(link)) => Some(link),
+        Some(Err(error)) => {
+            println!("Could not connect the link cable: {}", error);
+            None
+        }
+        None => None,
+    };
     let mut graphics: Graphics = Graphics::new();
+    graphics.partner = link.map(Partner::Link);
+    if let Some(index) = printer {
+        graphics.partner = Some(Partner::Printer(Printer::new()));
+        if let Some(dir) = args.get(index + 1).filter(|arg| !arg.starts_with("--")) {
+            graphics.print_dir = PathBuf::from(dir);
+        }
+    }
+    if args.iter().any(|arg| arg == "--fifo") {
+        graphics.renderer = Renderer::Fifo;
+    }
+    if args.iter().any(|arg| arg == "--serial") {
+        graphics.print_serial = true;
+    }
+    let bootrom_path = args
+        .iter()
+        .position(|arg| arg == "--bootrom")
+        .and_then(|index| args.get(index + 1));
+    if let Some(path) = bootrom_path {
+        let bootrom = fs::read(path)
+            .map_err(|error| format!("{}: {}", path, error))
+            .and_then(Bootrom::new);
+        match bootrom {
+            Ok(bootrom) => graphics.bootrom = Some(bootrom),
+            Err(error) => println!("Could not load boot ROM: {}", error),
+        }
+    }
+    let input_path = args
+        .iter()
+        .position(|arg| arg == "--input")
+        .and_then(|index| args.get(index + 1));
+    let path = Path::new(input_path.map_or(DEFAULT_INPUT_PATH, String::as_str));
+    if input_path.is_some() || path.exists() {
+        match InputMap::load(path) {
+            Ok(input) => graphics.set_input(input),
+            Err(error) => println!("Could not load input map: {}", error),
+        }
+    }
     graphics.render();
 }
 